@@ -236,7 +236,8 @@ fn bench(c: &mut Criterion) {
             num_instances,
             None,
         );
-        evm_verify(deployment_code, instances, proof);
+        let report = evm_verify(deployment_code, instances, proof).unwrap();
+        assert!(report.accepted);
     }
 }
 
@@ -105,7 +105,8 @@ fn bench(c: &mut Criterion) {
             None,
         );
 
-        evm_verify(deployment_code, instances.clone(), proof);
+        let report = evm_verify(deployment_code, instances.clone(), proof).unwrap();
+        assert!(report.accepted);
 
         let start2 = start_timer!(|| "Create EVM GWC proof");
         let agg_circuit = AggregationCircuit::new::<SHPLONK>(
@@ -126,7 +127,8 @@ fn bench(c: &mut Criterion) {
             None,
         );
 
-        evm_verify(deployment_code, instances, proof);
+        let report = evm_verify(deployment_code, instances, proof).unwrap();
+        assert!(report.accepted);
     }
 
     // run benches
@@ -1,6 +1,8 @@
 use crate::{GWC, SHPLONK};
 
-use super::{CircuitExt, PlonkVerifier};
+use super::{
+    CircuitExt, PlonkSuccinctVerifier, PlonkVerifier, Snark, SnarkTranscript, SnarkWithTranscript,
+};
 #[cfg(feature = "display")]
 use ark_std::{end_timer, start_timer};
 use ethereum_types::Address;
@@ -21,17 +23,40 @@ use halo2_base::halo2_proofs::{
 };
 use itertools::Itertools;
 use rand::{rngs::StdRng, SeedableRng};
-pub use snark_verifier::loader::evm::encode_calldata;
+pub use snark_verifier::loader::evm::{
+    encode_calldata, encode_calldata_abi, encode_calldata_multi, encode_calldata_route,
+    solidity_abi, AssemblerError, CheckFailure, CheckKind, CheckSite, EvmCodeSizeError,
+    GasEstimate, GasSchedule, PrecompileUsage, Precompiled, MAX_RUNTIME_SIZE,
+};
 use snark_verifier::{
-    loader::evm::{compile_yul, EvmLoader, ExecutorBuilder},
+    loader::{
+        evm::{
+            assemble_yul, compile_yul, estimate_verifier_gas, CalldataPtr, EvmLoader,
+            EvmVerifyingKey, ExecutorBuilder,
+        },
+        Loader,
+    },
     pcs::{
         kzg::{KzgAccumulator, KzgAsVerifyingKey, KzgDecidingKey, KzgSuccinctVerifyingKey},
         AccumulationDecider, AccumulationScheme, PolynomialCommitmentScheme,
     },
-    system::halo2::{compile, transcript::evm::EvmTranscript, Config},
-    verifier::SnarkVerifier,
+    system::halo2::{
+        compile,
+        transcript::evm::{EvmLoaderTranscript, EvmTranscript},
+        Config,
+    },
+    verifier::{plonk::PlonkProtocol, SnarkVerifier},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs, io,
+    path::Path,
+    rc::Rc,
 };
-use std::{fs, io, path::Path, rc::Rc};
+
+#[cfg(feature = "evm_gas_profiling")]
+pub use snark_verifier::loader::evm::{GasProfile, GasSection};
 
 /// Generates a proof for evm verification using either SHPLONK or GWC proving method. Uses Keccak for Fiat-Shamir.
 pub fn gen_evm_proof<'params, C, P, V>(
@@ -107,6 +132,47 @@ pub fn gen_evm_proof_shplonk<'params, C: Circuit<Fr>>(
     gen_evm_proof::<C, ProverSHPLONK<_>, VerifierSHPLONK<_>>(params, pk, circuit, instances)
 }
 
+/// Generates a SNARK for evm verification using either SHPLONK or GWC proving method. Uses Keccak
+/// for Fiat-Shamir, so its proof is the same as [`gen_evm_proof`], and it's paired with
+/// [`SnarkTranscript::Evm`] so it's aggregated with the same transcript.
+pub fn gen_evm_snark<'params, ConcreteCircuit, P, V>(
+    params: &'params ParamsKZG<Bn256>,
+    pk: &'params ProvingKey<G1Affine>,
+    circuit: ConcreteCircuit,
+) -> SnarkWithTranscript
+where
+    ConcreteCircuit: CircuitExt<Fr>,
+    P: Prover<'params, KZGCommitmentScheme<Bn256>>,
+    V: Verifier<
+        'params,
+        KZGCommitmentScheme<Bn256>,
+        Guard = GuardKZG<'params, Bn256>,
+        MSMAccumulator = DualMSM<'params, Bn256>,
+    >,
+{
+    let protocol = compile_protocol::<ConcreteCircuit>(params, pk.get_vk(), circuit.num_instance());
+
+    let instances = circuit.instances();
+    let proof = gen_evm_proof::<ConcreteCircuit, P, V>(params, pk, circuit, instances.clone());
+    Snark::new(protocol, instances, proof).with_transcript(SnarkTranscript::Evm)
+}
+
+pub fn gen_evm_snark_gwc<'params, ConcreteCircuit: CircuitExt<Fr>>(
+    params: &'params ParamsKZG<Bn256>,
+    pk: &'params ProvingKey<G1Affine>,
+    circuit: ConcreteCircuit,
+) -> SnarkWithTranscript {
+    gen_evm_snark::<ConcreteCircuit, ProverGWC<_>, VerifierGWC<_>>(params, pk, circuit)
+}
+
+pub fn gen_evm_snark_shplonk<'params, ConcreteCircuit: CircuitExt<Fr>>(
+    params: &'params ParamsKZG<Bn256>,
+    pk: &'params ProvingKey<G1Affine>,
+    circuit: ConcreteCircuit,
+) -> SnarkWithTranscript {
+    gen_evm_snark::<ConcreteCircuit, ProverSHPLONK<_>, VerifierSHPLONK<_>>(params, pk, circuit)
+}
+
 pub trait EvmKzgAccumulationScheme = PolynomialCommitmentScheme<
         G1Affine,
         Rc<EvmLoader>,
@@ -119,42 +185,127 @@ pub trait EvmKzgAccumulationScheme = PolynomialCommitmentScheme<
         Accumulator = KzgAccumulator<G1Affine, Rc<EvmLoader>>,
     > + AccumulationDecider<G1Affine, Rc<EvmLoader>, DecidingKey = KzgDecidingKey<Bn256>>;
 
-pub fn gen_evm_verifier<C, AS>(
+fn compile_protocol<C: CircuitExt<Fr>>(
     params: &ParamsKZG<Bn256>,
     vk: &VerifyingKey<G1Affine>,
     num_instance: Vec<usize>,
-    path: Option<&Path>,
-) -> Vec<u8>
-where
-    C: CircuitExt<Fr>,
-    AS: EvmKzgAccumulationScheme,
-{
-    let protocol = compile(
+) -> PlonkProtocol<G1Affine> {
+    compile(
         params,
         vk,
         Config::kzg()
-            .with_num_instance(num_instance.clone())
+            .with_num_instance(num_instance)
             .with_accumulator_indices(C::accumulator_indices()),
-    );
-    // deciding key
-    let dk = (params.get_g()[0], params.g2(), params.s_g2()).into();
-
-    let loader = EvmLoader::new::<Fq, Fr>();
-    let protocol = protocol.loaded(&loader);
-    let mut transcript = EvmTranscript::<_, Rc<EvmLoader>, _, _>::new(&loader);
+    )
+}
 
-    let instances = transcript.load_instances(num_instance);
-    let proof =
-        PlonkVerifier::<AS>::read_proof(&dk, &protocol, &instances, &mut transcript).unwrap();
-    PlonkVerifier::<AS>::verify(&dk, &protocol, &instances, &proof).unwrap();
+fn deciding_key(params: &ParamsKZG<Bn256>) -> KzgDecidingKey<Bn256> {
+    (params.get_g()[0], params.g2(), params.s_g2()).into()
+}
 
-    let yul_code = loader.yul_code();
-    let byte_code = compile_yul(&yul_code);
+fn write_yul(yul_code: &str, path: Option<&Path>) {
     if let Some(path) = path {
         path.parent().and_then(|dir| fs::create_dir_all(dir).ok()).unwrap();
         fs::write(path, yul_code).unwrap();
     }
-    byte_code
+}
+
+/// Loads verification of `protocol` into `loader`. The loaded protocol is
+/// passed to `setup` before the transcript is created by `transcript`, so the
+/// protocol could be adjusted or the loader could allocate memory without
+/// breaking the contiguous buffer of the transcript.
+fn load_evm_verifier<AS>(
+    loader: &Rc<EvmLoader>,
+    dk: &KzgDecidingKey<Bn256>,
+    protocol: &PlonkProtocol<G1Affine>,
+    setup: impl FnOnce(&Rc<EvmLoader>, &mut PlonkProtocol<G1Affine, Rc<EvmLoader>>),
+    transcript: impl FnOnce(&Rc<EvmLoader>) -> EvmLoaderTranscript<G1Affine>,
+) where
+    AS: EvmKzgAccumulationScheme,
+{
+    let mut protocol = protocol.loaded(loader);
+    setup(loader, &mut protocol);
+    let mut transcript = transcript(loader);
+
+    let instances = transcript.load_instances(protocol.num_instance.clone());
+    let proof =
+        PlonkVerifier::<AS>::read_proof(dk, &protocol, &instances, &mut transcript).unwrap();
+    transcript.check_proof_len();
+    PlonkVerifier::<AS>::verify(dk, &protocol, &instances, &proof).unwrap();
+}
+
+/// Generates yul code of a verifier of `protocol` by [`load_evm_verifier`] on a
+/// new loader, writes it to `path` if given, and returns it with the loader.
+fn gen_evm_verifier_yul_with<AS>(
+    dk: &KzgDecidingKey<Bn256>,
+    protocol: &PlonkProtocol<G1Affine>,
+    path: Option<&Path>,
+    setup: impl FnOnce(&Rc<EvmLoader>, &mut PlonkProtocol<G1Affine, Rc<EvmLoader>>),
+    transcript: impl FnOnce(&Rc<EvmLoader>) -> EvmLoaderTranscript<G1Affine>,
+) -> (Rc<EvmLoader>, String)
+where
+    AS: EvmKzgAccumulationScheme,
+{
+    let loader = EvmLoader::new::<Fq, Fr>();
+    load_evm_verifier::<AS>(&loader, dk, protocol, setup, transcript);
+
+    let yul_code = loader.yul_code();
+    write_yul(&yul_code, path);
+    (loader, yul_code)
+}
+
+fn gen_evm_verifier_yul<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    path: Option<&Path>,
+) -> String
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    let protocol = compile_protocol::<C>(params, vk, num_instance);
+    let (_, yul_code) = gen_evm_verifier_yul_with::<AS>(
+        &deciding_key(params),
+        &protocol,
+        path,
+        |_, _| {},
+        EvmLoaderTranscript::new,
+    );
+    yul_code
+}
+
+/// Generates a verifier of `vk` compiled by `solc`, which reads calldata
+/// encoded by [`encode_calldata`] and returns keccak256 of the instances as
+/// big-endian words if the proof is accepted.
+pub fn gen_evm_verifier<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    path: Option<&Path>,
+) -> Vec<u8>
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    compile_yul(&gen_evm_verifier_yul::<C, AS>(params, vk, num_instance, path))
+}
+
+/// Generates the same verifier as [`gen_evm_verifier`] but assembles it by
+/// [`assemble_yul`] in process instead of by `solc`, so it doesn't require
+/// `solc` in `PATH`. The bytecode is not optimized, so it's larger and costs
+/// more gas than the one compiled by `solc`.
+pub fn gen_evm_verifier_assembled<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    path: Option<&Path>,
+) -> Result<Vec<u8>, AssemblerError>
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    assemble_yul(&gen_evm_verifier_yul::<C, AS>(params, vk, num_instance, path))
 }
 
 pub fn gen_evm_verifier_gwc<C: CircuitExt<Fr>>(
@@ -175,20 +326,443 @@ pub fn gen_evm_verifier_shplonk<C: CircuitExt<Fr>>(
     gen_evm_verifier::<C, SHPLONK>(params, vk, num_instance, path)
 }
 
-pub fn evm_verify(deployment_code: Vec<u8>, instances: Vec<Vec<Fr>>, proof: Vec<u8>) {
-    let calldata = encode_calldata(&instances, &proof);
-    let success = {
-        let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+/// Estimates gas cost of verifying a proof by the verifier
+/// [`gen_evm_verifier_assembled`] generates with the same arguments, priced by
+/// `schedule` without assembling the verifier, so configurations (e.g. `AS` as
+/// [`GWC`] or [`SHPLONK`]) could be compared cheaply, even before any proof is
+/// generated.
+///
+/// `calldata` encoded by [`encode_calldata`] is optional, and without it the
+/// calldata cost is an upper bound. See [`estimate_verifier_gas`] for its
+/// accuracy, also against the verifier compiled by `solc` as
+/// [`gen_evm_verifier`].
+pub fn estimate_evm_verifier_gas<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    calldata: Option<&[u8]>,
+    schedule: &GasSchedule,
+) -> GasEstimate
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    let protocol = compile_protocol::<C>(params, vk, num_instance);
+    let dk = deciding_key(params);
 
-        let caller = Address::from_low_u64_be(0xfe);
-        let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
-        let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+    estimate_verifier_gas::<_, PlonkVerifier<AS>>(&dk, &protocol, calldata, schedule).unwrap()
+}
 
-        dbg!(result.gas_used);
+/// Generates a debug build of the verifier as [`gen_evm_verifier`], which
+/// reverts at the first failed check with the reason decodable by
+/// [`CheckFailure::decode`], and returns the same as [`gen_evm_verifier`] on
+/// success.
+///
+/// Returns the deployment code and the sites of checks indexed by
+/// [`CheckFailure::site`].
+pub fn gen_evm_verifier_debug<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    path: Option<&Path>,
+) -> (Vec<u8>, Vec<CheckSite>)
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    let protocol = compile_protocol::<C>(params, vk, num_instance);
+    let (loader, yul_code) = gen_evm_verifier_yul_with::<AS>(
+        &deciding_key(params),
+        &protocol,
+        path,
+        |loader, _| loader.enable_revert_reasons(),
+        EvmLoaderTranscript::new,
+    );
+    (compile_yul(&yul_code), loader.check_sites())
+}
 
-        !result.reverted
+/// Generates a verifier as [`gen_evm_verifier`] but callable as
+/// `verifyProof(bytes proof, uint256[] instances) returns (bool)` with calldata
+/// encoded by [`encode_calldata_abi`], e.g. by another contract.
+///
+/// If `path` is given, the yul code is written to `path` and the ABI JSON from
+/// [`solidity_abi`] is written next to it with extension `abi.json`.
+pub fn gen_evm_verifier_abi<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    path: Option<&Path>,
+) -> Vec<u8>
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    let protocol = compile_protocol::<C>(params, vk, num_instance);
+    let (_, yul_code) = gen_evm_verifier_yul_with::<AS>(
+        &deciding_key(params),
+        &protocol,
+        path,
+        |_, _| {},
+        EvmLoaderTranscript::new_abi,
+    );
+    if let Some(path) = path {
+        fs::write(path.with_extension("abi.json"), solidity_abi()).unwrap();
+    }
+    compile_yul(&yul_code)
+}
+
+/// Generates a verifier of several protocols, which reads the instances and
+/// proof of each protocol in turn from calldata encoded by
+/// [`encode_calldata_multi`], runs succinct verification of each, and then
+/// decides all the resulting accumulators with a single pairing.
+///
+/// All protocols must be committed with the same `params`, since the
+/// accumulators are only decidable together under the same deciding key.
+pub fn gen_evm_verifier_multi<AS>(
+    params: &ParamsKZG<Bn256>,
+    protocols: &[PlonkProtocol<G1Affine>],
+    path: Option<&Path>,
+) -> Vec<u8>
+where
+    AS: EvmKzgAccumulationScheme,
+{
+    assert!(!protocols.is_empty());
+
+    let dk = deciding_key(params);
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let mut offset = CalldataPtr::from(0);
+    let verified = protocols
+        .iter()
+        .map(|protocol| {
+            let protocol = protocol.loaded(&loader);
+            let mut transcript = EvmLoaderTranscript::new_at(&loader, offset);
+
+            let instances = transcript.load_instances(protocol.num_instance.clone());
+            let proof = PlonkSuccinctVerifier::<AS>::read_proof(
+                dk.as_ref(),
+                &protocol,
+                &instances,
+                &mut transcript,
+            )
+            .unwrap();
+            offset = transcript.offset();
+
+            (protocol, instances, proof)
+        })
+        .collect_vec();
+    loader.calldata_check_end(offset);
+    let accumulators = verified
+        .iter()
+        .flat_map(|(protocol, instances, proof)| {
+            PlonkSuccinctVerifier::<AS>::verify(dk.as_ref(), protocol, instances, proof).unwrap()
+        })
+        .collect_vec();
+
+    loader.start_cost_metering("decide");
+    AS::decide_all(&dk, accumulators).unwrap();
+    loader.end_cost_metering();
+
+    let yul_code = loader.yul_code();
+    write_yul(&yul_code, path);
+    compile_yul(&yul_code)
+}
+
+/// Generates a router verifying each of `protocols` by its own route, which
+/// is taken by the circuit identifier paired with the protocol in the first 4
+/// bytes of calldata encoded by [`encode_calldata_route`].
+///
+/// Routes share the helper functions of the verifier, and calldata with an
+/// unknown identifier always reverts.
+///
+/// Since runtime code of all routes adds up, returns [`EvmCodeSizeError`] if it
+/// exceeds [`MAX_RUNTIME_SIZE`], then the router is not deployable on chains
+/// enforcing EIP-170.
+pub fn gen_evm_verifier_router<AS>(
+    params: &ParamsKZG<Bn256>,
+    protocols: &[(PlonkProtocol<G1Affine>, u32)],
+    path: Option<&Path>,
+) -> Result<Vec<u8>, EvmCodeSizeError>
+where
+    AS: EvmKzgAccumulationScheme,
+{
+    assert!(!protocols.is_empty());
+    assert!(
+        protocols.iter().map(|(_, id)| id).all_unique(),
+        "circuit identifiers should be unique"
+    );
+
+    let dk = deciding_key(params);
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    for (protocol, id) in protocols {
+        loader.begin_route(*id);
+        load_evm_verifier::<AS>(
+            &loader,
+            &dk,
+            protocol,
+            |_, _| {},
+            |loader| EvmLoaderTranscript::new_at(loader, 0x04),
+        );
+        loader.end_route();
+    }
+
+    let yul_code = loader.yul_code();
+    write_yul(&yul_code, path);
+    let byte_code = compile_yul(&yul_code);
+    let runtime_size = evm_runtime_size(byte_code.clone());
+    if runtime_size > MAX_RUNTIME_SIZE {
+        return Err(EvmCodeSizeError { runtime_size, deployment_code: byte_code });
+    }
+    Ok(byte_code)
+}
+
+/// Returns size of the runtime code deployed by `deployment_code`, which is
+/// measured as the size returned by its constructor in the bundled executor,
+/// so it's known even if the deployment fails by exceeding
+/// [`MAX_RUNTIME_SIZE`].
+pub fn evm_runtime_size(deployment_code: Vec<u8>) -> usize {
+    let mut evm =
+        ExecutorBuilder::default().set_debugger(true).with_gas_limit(u64::MAX.into()).build();
+    let caller = Address::from_low_u64_be(0xfe);
+    let deployment = evm.deploy(caller, deployment_code.into(), 0.into());
+    // the last step of the constructor is `return(offset, size)`
+    let step = deployment
+        .debug
+        .and_then(|debug| debug.arena.into_iter().next())
+        .and_then(|node| node.steps.last().cloned())
+        .expect("constructor should be traced");
+    assert_eq!(step.instruction.0, 0xf3, "constructor should return the runtime code");
+    step.stack[step.stack.len() - 2].as_usize()
+}
+
+#[cfg(feature = "evm_gas_profiling")]
+/// Generates a verifier with gas metering of each verifier section, then runs it
+/// with `instances` and `proof` and returns the gas used by each section.
+pub fn evm_gas_profile<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    instances: Vec<Vec<Fr>>,
+    proof: Vec<u8>,
+) -> GasProfile
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    let (loader, deployment_code) = gen_evm_verifier_metered::<C, AS>(params, vk, num_instance);
+    loader.profile_gas(deployment_code, encode_calldata(&instances, &proof))
+}
+
+#[cfg(feature = "evm_gas_profiling")]
+/// Generates a verifier with gas metering of each verifier section, and returns
+/// the loader to make sense of the metering and the deployment code.
+fn gen_evm_verifier_metered<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+) -> (Rc<EvmLoader>, Vec<u8>)
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    let protocol = compile_protocol::<C>(params, vk, num_instance);
+    let (loader, yul_code) = gen_evm_verifier_yul_with::<AS>(
+        &deciding_key(params),
+        &protocol,
+        None,
+        |loader, _| loader.enable_gas_metering(),
+        EvmLoaderTranscript::new,
+    );
+    (loader, compile_yul(&yul_code))
+}
+
+/// Generates a verifier which reads constants of the protocol, i.e. the
+/// preprocessed commitments, `transcript_initial_state` and the deciding key,
+/// from a verifying key contract generated by [`gen_evm_verifying_key`].
+///
+/// The verifier only depends on the shape of `vk`, i.e. its constraint system
+/// and domain, so it is shared by every verifying key of the same shape. The
+/// domain is checked against the verifying key contract at runtime, since the
+/// verifier is unrolled for it.
+///
+/// The verifier is meant to be deployed once and then called through verifying key
+/// contracts, each of which forwards the calldata from [`encode_calldata`] to it by
+/// `DELEGATECALL`. Calling the verifier directly always reverts.
+pub fn gen_evm_verifier_logic<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    path: Option<&Path>,
+) -> Vec<u8>
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    let protocol = compile_protocol::<C>(params, vk, num_instance);
+    let dk = deciding_key(params);
+    let evm_vk = EvmVerifyingKey::new(&protocol, &dk);
+
+    // `transcript_initial_state` and preprocessed commitments are read from the
+    // verifying key contract instead
+    let (_, yul_code) = gen_evm_verifier_yul_with::<AS>(
+        &dk,
+        &protocol,
+        path,
+        |loader, protocol| {
+            loader.load_verifying_key(&evm_vk);
+            protocol.transcript_initial_state = None;
+            protocol.preprocessed = loader.verifying_key_preprocessed();
+        },
+        |loader| {
+            let mut transcript = EvmLoaderTranscript::new(loader);
+            transcript.copy_initial_state(&evm_vk);
+            transcript
+        },
+    );
+    compile_yul(&yul_code)
+}
+
+/// Generates deployment code of a verifying key contract, which holds constants
+/// of the protocol and forwards every call to `verifier` generated by
+/// [`gen_evm_verifier_logic`].
+///
+/// If `path` is given, the deployment code is written to `path` in hex.
+///
+/// Returns [`EvmCodeSizeError`] if the constants make the runtime code exceed
+/// [`MAX_RUNTIME_SIZE`], then the contract is not deployable on chains
+/// enforcing EIP-170.
+pub fn gen_evm_verifying_key<C: CircuitExt<Fr>>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    verifier: Address,
+    path: Option<&Path>,
+) -> Result<Vec<u8>, EvmCodeSizeError> {
+    let protocol = compile_protocol::<C>(params, vk, num_instance);
+    let byte_code =
+        EvmVerifyingKey::new(&protocol, &deciding_key(params)).deployment_code(verifier)?;
+    if let Some(path) = path {
+        path.parent().and_then(|dir| fs::create_dir_all(dir).ok()).unwrap();
+        fs::write(path, hex::encode(&byte_code)).unwrap();
+    }
+    Ok(byte_code)
+}
+
+/// Report of verifying a proof by a verifier deployed in the bundled executor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvmVerifyReport {
+    /// Whether the verifier accepted the proof.
+    pub accepted: bool,
+    /// Total gas used by the call, including the intrinsic cost.
+    pub gas_used: u64,
+    /// Size of the deployment code in bytes.
+    pub deployment_size: usize,
+    /// Return data if the proof is accepted, otherwise revert data.
+    pub output: Vec<u8>,
+    /// Calls and gas used of each precompile called by the verifier.
+    pub precompiles: BTreeMap<Precompiled, PrecompileUsage>,
+    /// Gas used by each verifier section, if profiled by [`evm_verify_profiled`].
+    #[cfg(feature = "evm_gas_profiling")]
+    pub gas_profile: Option<GasProfile>,
+}
+
+impl EvmVerifyReport {
+    /// Returns the failed check if the proof is rejected by a debug build of
+    /// the verifier generated by [`gen_evm_verifier_debug`].
+    pub fn check_failure(&self) -> Option<CheckFailure> {
+        (!self.accepted).then(|| CheckFailure::decode(&self.output)).flatten()
+    }
+}
+
+/// Error of deploying a verifier in the bundled executor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvmDeployError {
+    /// Exit reason of the deployment.
+    pub exit_reason: String,
+    /// Size of the deployment code in bytes.
+    pub deployment_size: usize,
+}
+
+impl Display for EvmDeployError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deployment of {} bytes failed with {}", self.deployment_size, self.exit_reason)
+    }
+}
+
+impl std::error::Error for EvmDeployError {}
+
+/// Deploys `deployment_code` in the bundled executor and calls it with
+/// `calldata`, then returns the [`EvmVerifyReport`]. A rejected proof is not an
+/// error but a report with `accepted` being `false`, so tampered calldata could
+/// be checked the same way.
+pub fn evm_verify_calldata(
+    deployment_code: Vec<u8>,
+    calldata: Vec<u8>,
+) -> Result<EvmVerifyReport, EvmDeployError> {
+    let deployment_size = deployment_code.len();
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let deployment = evm.deploy(caller, deployment_code.into(), 0.into());
+    let verifier = match (deployment.reverted, deployment.address) {
+        (false, Some(verifier)) => verifier,
+        _ => {
+            let exit_reason = format!("{:?}", deployment.exit_reason);
+            return Err(EvmDeployError { exit_reason, deployment_size });
+        }
     };
-    assert!(success);
+    let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+
+    Ok(EvmVerifyReport {
+        accepted: !result.reverted,
+        gas_used: result.gas_used,
+        deployment_size,
+        output: result.result.to_vec(),
+        precompiles: result.precompiles,
+        #[cfg(feature = "evm_gas_profiling")]
+        gas_profile: None,
+    })
+}
+
+/// Verifies `instances` and `proof` by the verifier deployed from
+/// `deployment_code` as [`evm_verify_calldata`] with calldata encoded by
+/// [`encode_calldata`].
+pub fn evm_verify(
+    deployment_code: Vec<u8>,
+    instances: Vec<Vec<Fr>>,
+    proof: Vec<u8>,
+) -> Result<EvmVerifyReport, EvmDeployError> {
+    evm_verify_calldata(deployment_code, encode_calldata(&instances, &proof))
+}
+
+#[cfg(feature = "evm_gas_profiling")]
+/// Verifies `instances` and `proof` as [`evm_verify`] by the verifier
+/// [`gen_evm_verifier`] generates, so the gas used and deployment size in the
+/// report are of the deployable verifier, then profiles the same call by a
+/// verifier with gas metering as [`evm_gas_profile`], whose sections are in
+/// [`EvmVerifyReport::gas_profile`].
+///
+/// The metered verifier costs more gas than the deployable one, so only
+/// [`GasProfile::sections`] are comparable with each other.
+pub fn evm_verify_profiled<C, AS>(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+    instances: Vec<Vec<Fr>>,
+    proof: Vec<u8>,
+) -> Result<EvmVerifyReport, EvmDeployError>
+where
+    C: CircuitExt<Fr>,
+    AS: EvmKzgAccumulationScheme,
+{
+    let deployment_code = gen_evm_verifier::<C, AS>(params, vk, num_instance.clone(), None);
+    let calldata = encode_calldata(&instances, &proof);
+    let mut report = evm_verify_calldata(deployment_code, calldata.clone())?;
+
+    let (loader, metered_code) = gen_evm_verifier_metered::<C, AS>(params, vk, num_instance);
+    report.gas_profile = Some(loader.profile_gas(metered_code, calldata));
+    Ok(report)
 }
 
 pub fn write_calldata(instances: &[Vec<Fr>], proof: &[u8], path: &Path) -> io::Result<String> {
@@ -197,3 +771,436 @@ pub fn write_calldata(instances: &[Vec<Fr>], proof: &[u8], path: &Path) -> io::R
     fs::write(path, &calldata)?;
     Ok(calldata)
 }
+
+#[cfg(feature = "evm_gas_profiling")]
+#[test]
+fn test_evm_gas_profile() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+
+    let (params, pk, num_instance, instances, proof) = gen_evm_fixture(StandardPlonk::new(7));
+
+    let profile = evm_gas_profile::<StandardPlonk, SHPLONK>(
+        &params,
+        pk.get_vk(),
+        num_instance.clone(),
+        instances.clone(),
+        proof.clone(),
+    );
+    assert!(profile.accepted);
+    let sections = ["read_proof", "common_polynomial_evaluation", "pcs_verify", "decide"];
+    for identifier in sections {
+        assert!(profile.gas_of(identifier).unwrap() > 0, "{identifier} is not metered");
+    }
+    let metered = profile
+        .sections
+        .iter()
+        .filter(|section| section.depth == 0)
+        .map(|section| section.gas.unwrap())
+        .sum::<u64>();
+    assert!(metered < profile.gas_used);
+
+    // the deployable verifier has no gas metering, so it costs less than the profiled one
+    let deployment_code =
+        gen_evm_verifier_shplonk::<StandardPlonk>(&params, pk.get_vk(), num_instance, None);
+    let report = evm_verify(deployment_code, instances, proof).unwrap();
+    assert!(report.accepted);
+    assert!(report.gas_used < profile.gas_used);
+}
+
+#[test]
+fn test_solidity_caller() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+    use ethereum_types::U256;
+    use snark_verifier::loader::evm::compile_solidity;
+    use std::iter;
+
+    let (params, pk, num_instance, instances, proof) = gen_evm_fixture(StandardPlonk::new(7));
+
+    let verifier =
+        gen_evm_verifier_abi::<StandardPlonk, SHPLONK>(&params, pk.get_vk(), num_instance, None);
+    // calls the verifier as `solidity_abi` describes, by `STATICCALL` since it's `view`
+    let caller_code = compile_solidity(
+        "// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+contract VerifierCaller {
+    address public immutable verifier;
+
+    constructor(address _verifier) {
+        verifier = _verifier;
+    }
+
+    function verifyProof(bytes calldata proof, uint256[] calldata instances)
+        external
+        view
+        returns (bool)
+    {
+        (bool success, bytes memory data) = verifier.staticcall(
+            abi.encodeWithSignature(\"verifyProof(bytes,uint256[])\", proof, instances)
+        );
+        return success && abi.decode(data, (bool));
+    }
+}
+",
+    );
+
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let caller = Address::from_low_u64_be(0xfe);
+    let verifier = evm.deploy(caller, verifier.into(), 0.into()).address.unwrap();
+    let caller_code = iter::empty()
+        .chain(caller_code)
+        .chain([0; 12])
+        .chain(verifier.to_fixed_bytes())
+        .collect::<Vec<_>>();
+    let verifier_caller = evm.deploy(caller, caller_code.into(), 0.into()).address.unwrap();
+
+    let verify_proof = |instances: &[Vec<Fr>], proof: &[u8]| {
+        let calldata = encode_calldata_abi(instances, proof);
+        let result = evm.call_raw(caller, verifier_caller, calldata.into(), 0.into());
+        assert!(!result.reverted);
+        U256::from_big_endian(&result.result) == U256::one()
+    };
+
+    assert!(verify_proof(&instances, &proof));
+    let tampered_proof = {
+        let mut proof = proof.clone();
+        proof[0] ^= 1;
+        proof
+    };
+    assert!(!verify_proof(&instances, &tampered_proof));
+    let extra_instances = vec![vec![instances[0][0], Fr::one()]];
+    assert!(!verify_proof(&extra_instances, &proof));
+}
+
+#[test]
+fn test_evm_verifier_logic() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+
+    // circuits of the same shape but with different preprocessed commitments
+    let [(params, pk_0, num_instance, instances, proof_0), (_, pk_1, _, _, proof_1)] =
+        [StandardPlonk::with_scale(7, 1), StandardPlonk::with_scale(7, 2)].map(gen_evm_fixture);
+    let pks = [pk_0, pk_1];
+    let proofs = [proof_0, proof_1];
+
+    let gen_logic = |idx: usize| {
+        gen_evm_verifier_logic::<StandardPlonk, SHPLONK>(
+            &params,
+            pks[idx].get_vk(),
+            num_instance.clone(),
+            None,
+        )
+    };
+    // the logic only depends on the shape, so it is the same for both
+    let logic = gen_logic(0);
+    assert_eq!(logic, gen_logic(1));
+
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let caller = Address::from_low_u64_be(0xfe);
+    let logic = evm.deploy(caller, logic.into(), 0.into()).address.unwrap();
+    let vks = [0, 1].map(|idx| {
+        gen_evm_verifying_key::<StandardPlonk>(
+            &params,
+            pks[idx].get_vk(),
+            num_instance.clone(),
+            logic,
+            None,
+        )
+        .unwrap()
+    });
+    assert_ne!(vks[0], vks[1]);
+    let vks = vks.map(|code| evm.deploy(caller, code.into(), 0.into()).address.unwrap());
+
+    let verify = |to: Address, proof: &[u8]| {
+        let calldata = encode_calldata(&instances, proof);
+        !evm.call_raw(caller, to, calldata.into(), 0.into()).reverted
+    };
+    for idx in [0, 1] {
+        assert!(verify(vks[idx], &proofs[idx]));
+        assert!(!verify(vks[idx], &proofs[1 - idx]));
+        assert!(!verify(logic, &proofs[idx]));
+    }
+}
+
+#[test]
+fn test_evm_verifier_multi() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+
+    // circuits with different verifying keys and instances
+    let fixtures =
+        [StandardPlonk::with_scale(7, 1), StandardPlonk::with_scale(11, 2)].map(gen_evm_fixture);
+    let params = &fixtures[0].0;
+    let snarks = fixtures
+        .iter()
+        .map(|(params, pk, num_instance, instances, proof)| {
+            let config = Config::kzg().with_num_instance(num_instance.clone());
+            (compile(params, pk.get_vk(), config), instances.clone(), proof.clone())
+        })
+        .collect_vec();
+    let protocols = snarks.iter().map(|(protocol, _, _)| protocol.clone()).collect_vec();
+    let deployment_code = gen_evm_verifier_multi::<SHPLONK>(params, &protocols, None);
+
+    let encode = |order: [usize; 2]| {
+        encode_calldata_multi(&order.map(|idx| (snarks[idx].1.clone(), snarks[idx].2.clone())))
+    };
+    let calldata = encode([0, 1]);
+    let sizes = snarks.iter().map(|(_, instances, proof)| encode_calldata(instances, proof).len());
+    assert_eq!(calldata.len(), sizes.sum::<usize>());
+
+    let report = evm_verify_calldata(deployment_code.clone(), calldata.clone()).unwrap();
+    assert!(report.accepted);
+    assert_eq!(report.precompiles[&Precompiled::Bn254Pairing].calls, 1);
+
+    let rejected = |calldata: Vec<u8>| {
+        !evm_verify_calldata(deployment_code.clone(), calldata).unwrap().accepted
+    };
+    assert!(rejected(encode([1, 0])));
+    assert!(rejected(calldata[..calldata.len() - 1].to_vec()));
+    assert!(rejected(calldata.iter().cloned().chain([0]).collect()));
+    let mut tampered = calldata;
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(rejected(tampered));
+}
+
+#[test]
+fn test_evm_verifier_abi() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+    use ethereum_types::U256;
+
+    let (params, pk, num_instance, instances, proof) = gen_evm_fixture(StandardPlonk::new(7));
+
+    let deployment_code =
+        gen_evm_verifier_abi::<StandardPlonk, SHPLONK>(&params, pk.get_vk(), num_instance, None);
+    let verify =
+        |calldata: Vec<u8>| evm_verify_calldata(deployment_code.clone(), calldata).unwrap();
+
+    let report = verify(encode_calldata_abi(&instances, &proof));
+    assert!(report.accepted);
+    assert_eq!(U256::from_big_endian(&report.output), U256::one());
+
+    // calldata not in ABI encoding
+    assert!(!verify(encode_calldata(&instances, &proof)).accepted);
+    let tampered_proof = {
+        let mut proof = proof.clone();
+        proof[0] ^= 1;
+        proof
+    };
+    assert!(!verify(encode_calldata_abi(&instances, &tampered_proof)).accepted);
+    let truncated_proof = &proof[..proof.len() - 0x20];
+    assert!(!verify(encode_calldata_abi(&instances, truncated_proof)).accepted);
+    let extra_instances = vec![vec![instances[0][0], Fr::one()]];
+    assert!(!verify(encode_calldata_abi(&extra_instances, &proof)).accepted);
+}
+
+#[test]
+fn test_evm_verifier_assembled() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+
+    let (params, pk, num_instance, instances, proof) = gen_evm_fixture(StandardPlonk::new(7));
+
+    let compiled = gen_evm_verifier::<StandardPlonk, SHPLONK>(
+        &params,
+        pk.get_vk(),
+        num_instance.clone(),
+        None,
+    );
+    let assembled = gen_evm_verifier_assembled::<StandardPlonk, SHPLONK>(
+        &params,
+        pk.get_vk(),
+        num_instance,
+        None,
+    )
+    .unwrap();
+
+    let tampered_proof = {
+        let mut proof = proof.clone();
+        proof[0] ^= 1;
+        proof
+    };
+    let tampered_instances = vec![vec![instances[0][0] + Fr::one()]];
+    let calldatas = [
+        (true, encode_calldata(&instances, &proof)),
+        (false, encode_calldata(&instances, &tampered_proof)),
+        (false, encode_calldata(&tampered_instances, &proof)),
+        (false, encode_calldata(&instances, &proof[..proof.len() - 0x20])),
+        (false, encode_calldata(&instances, &[proof.as_slice(), &[0; 0x20]].concat())),
+    ];
+    for (expected, calldata) in calldatas {
+        let compiled = evm_verify_calldata(compiled.clone(), calldata.clone()).unwrap();
+        let assembled = evm_verify_calldata(assembled.clone(), calldata).unwrap();
+        assert_eq!(compiled.accepted, expected);
+        assert_eq!(assembled.accepted, expected);
+        assert_eq!(compiled.output, assembled.output);
+    }
+}
+
+#[test]
+fn test_estimate_evm_verifier_gas() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+
+    let (params, pk, num_instance, instances, proof) = gen_evm_fixture(StandardPlonk::new(7));
+    let calldata = encode_calldata(&instances, &proof);
+
+    let estimate = |calldata: Option<&[u8]>| {
+        estimate_evm_verifier_gas::<StandardPlonk, SHPLONK>(
+            &params,
+            pk.get_vk(),
+            num_instance.clone(),
+            calldata,
+            &GasSchedule::default(),
+        )
+    };
+    let (estimate, upper_bound) = (estimate(Some(&calldata)), estimate(None));
+    assert_eq!(upper_bound.calldata, 16 * calldata.len() as u64);
+    assert!(upper_bound.calldata >= estimate.calldata);
+    assert_eq!(upper_bound.total() - upper_bound.calldata, estimate.total() - estimate.calldata);
+
+    // close to the unoptimized verifier, which is priced the same way
+    let deployment_code = gen_evm_verifier_assembled::<StandardPlonk, SHPLONK>(
+        &params,
+        pk.get_vk(),
+        num_instance.clone(),
+        None,
+    )
+    .unwrap();
+    let report = evm_verify(deployment_code, instances.clone(), proof.clone()).unwrap();
+    assert!(report.accepted);
+    assert!(estimate.precompiles.keys().eq(report.precompiles.keys()));
+    let diff = estimate.total().abs_diff(report.gas_used);
+    assert!(diff * 20 <= report.gas_used, "{estimate:?} deviates from {}", report.gas_used);
+
+    // and the optimized verifier compiled by `solc` isn't far from it either
+    let deployment_code =
+        gen_evm_verifier::<StandardPlonk, SHPLONK>(&params, pk.get_vk(), num_instance, None);
+    let report = evm_verify(deployment_code, instances, proof).unwrap();
+    assert!(report.accepted);
+    assert!(estimate.precompiles.keys().eq(report.precompiles.keys()));
+    let diff = estimate.total().abs_diff(report.gas_used);
+    assert!(diff * 10 <= report.gas_used, "{estimate:?} deviates from {}", report.gas_used);
+}
+
+#[test]
+fn test_evm_verifier_router() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+
+    // circuits with different verifying keys and instances
+    let fixtures =
+        [StandardPlonk::with_scale(7, 1), StandardPlonk::with_scale(11, 2)].map(gen_evm_fixture);
+    let ids = [0x1234_5678, 0x9abc_def0];
+    let params = &fixtures[0].0;
+    let snarks = fixtures
+        .iter()
+        .map(|(params, pk, num_instance, instances, proof)| {
+            let config = Config::kzg().with_num_instance(num_instance.clone());
+            (compile(params, pk.get_vk(), config), instances.clone(), proof.clone())
+        })
+        .collect_vec();
+    let protocols =
+        snarks.iter().zip(ids).map(|((protocol, _, _), id)| (protocol.clone(), id)).collect_vec();
+    let deployment_code = gen_evm_verifier_router::<SHPLONK>(params, &protocols, None).unwrap();
+    assert!(evm_runtime_size(deployment_code.clone()) <= MAX_RUNTIME_SIZE);
+
+    let accepted = |calldata: Vec<u8>| {
+        evm_verify_calldata(deployment_code.clone(), calldata).unwrap().accepted
+    };
+    for (idx, (_, instances, proof)) in snarks.iter().enumerate() {
+        assert!(accepted(encode_calldata_route(ids[idx], instances, proof)));
+        // proof of the other route
+        assert!(!accepted(encode_calldata_route(ids[1 - idx], instances, proof)));
+        // unknown identifier
+        assert!(!accepted(encode_calldata_route(0, instances, proof)));
+        let mut tampered = proof.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(!accepted(encode_calldata_route(ids[idx], instances, &tampered)));
+        let truncated = &proof[..proof.len() - 0x20];
+        assert!(!accepted(encode_calldata_route(ids[idx], instances, truncated)));
+    }
+
+    // runtime code of routes adds up until exceeding the limit of EIP-170
+    let protocols = (0..8).map(|id| (snarks[0].0.clone(), id)).collect_vec();
+    let err = gen_evm_verifier_router::<SHPLONK>(params, &protocols, None).unwrap_err();
+    assert!(err.runtime_size > MAX_RUNTIME_SIZE);
+    assert_eq!(evm_runtime_size(err.deployment_code.clone()), err.runtime_size);
+    assert!(evm_verify_calldata(err.deployment_code, vec![]).is_err());
+}
+
+#[cfg(feature = "evm_gas_profiling")]
+#[test]
+fn test_evm_verify_profiled() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+
+    let (params, pk, num_instance, instances, proof) = gen_evm_fixture(StandardPlonk::new(7));
+    let verify_profiled = |proof: Vec<u8>| {
+        evm_verify_profiled::<StandardPlonk, SHPLONK>(
+            &params,
+            pk.get_vk(),
+            num_instance.clone(),
+            instances.clone(),
+            proof,
+        )
+        .unwrap()
+    };
+
+    // gas used and deployment size are of the deployable verifier
+    let deployment_code =
+        gen_evm_verifier_shplonk::<StandardPlonk>(&params, pk.get_vk(), num_instance.clone(), None);
+    let expected = evm_verify(deployment_code, instances.clone(), proof.clone()).unwrap();
+    let report = verify_profiled(proof.clone());
+    assert!(report.accepted);
+    assert_eq!(report.gas_used, expected.gas_used);
+    assert_eq!(report.deployment_size, expected.deployment_size);
+    assert_eq!(report.output, expected.output);
+    let profile = report.gas_profile.unwrap();
+    assert!(profile.accepted);
+    assert!(profile.gas_used > report.gas_used);
+    assert!(profile.gas_of("pcs_verify").unwrap() > 0);
+
+    // a rejected proof is still profiled
+    let tampered_proof = {
+        let mut proof = proof;
+        proof[0] ^= 1;
+        proof
+    };
+    let report = verify_profiled(tampered_proof);
+    assert!(!report.accepted);
+    assert!(!report.gas_profile.unwrap().accepted);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_calldata_layout() {
+    use crate::test::{gen_evm_fixture, StandardPlonk};
+    use snark_verifier::loader::evm::soundness::{
+        assert_rejects_mutations, CalldataItem, CalldataLayout,
+    };
+
+    let (params, pk, num_instance, instances, proof) = gen_evm_fixture(StandardPlonk::new(7));
+    let calldata = encode_calldata(&instances, &proof);
+
+    let protocol = compile_protocol::<StandardPlonk>(&params, pk.get_vk(), num_instance.clone());
+    let dk = deciding_key(&params);
+    let layout =
+        CalldataLayout::new::<G1Affine, PlonkVerifier<SHPLONK>>(&dk, &protocol, &instances, &proof)
+            .unwrap();
+
+    // items cover the whole calldata without gaps, with instances first
+    assert_eq!(layout.len, calldata.len());
+    let mut offset = 0;
+    for (idx, (item_offset, item)) in layout.items.iter().enumerate() {
+        assert_eq!(*item_offset, offset);
+        assert_eq!(*item == CalldataItem::Instance, idx < num_instance.iter().sum::<usize>());
+        offset += match item {
+            CalldataItem::Instance | CalldataItem::Scalar => 0x20,
+            CalldataItem::EcPoint => 0x40,
+        };
+    }
+    assert_eq!(offset, calldata.len());
+    let num_ec_point =
+        layout.items.iter().filter(|(_, item)| *item == CalldataItem::EcPoint).count();
+    let num_scalar = layout.items.iter().filter(|(_, item)| *item == CalldataItem::Scalar).count();
+    assert!(num_ec_point > protocol.num_witness.iter().sum::<usize>());
+    assert_eq!(num_scalar, protocol.evaluations.len());
+
+    let deployment_code =
+        gen_evm_verifier_shplonk::<StandardPlonk>(&params, pk.get_vk(), num_instance, None);
+    assert_rejects_mutations(deployment_code, &calldata, &layout);
+}
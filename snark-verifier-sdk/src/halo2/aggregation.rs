@@ -1,5 +1,5 @@
 use super::PlonkSuccinctVerifier;
-use crate::{BITS, LIMBS};
+use crate::{SnarkTranscript, SnarkWithTranscript, BITS, LIMBS};
 use halo2_base::{
     gates::{
         builder::{
@@ -24,33 +24,54 @@ use halo2_base::{
 use itertools::Itertools;
 use rand::{rngs::StdRng, SeedableRng};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "loader_evm")]
+use snark_verifier::system::halo2::transcript::evm::EvmTranscript;
 #[cfg(debug_assertions)]
 use snark_verifier::util::arithmetic::fe_to_limbs;
 use snark_verifier::{
     loader::{
         self,
-        halo2::halo2_ecc::{self, bn254::FpChip},
+        halo2::{
+            halo2_ecc::{self, bn254::FpChip},
+            CellProfile,
+        },
         native::NativeLoader,
+        Loader,
     },
     pcs::{
-        kzg::{KzgAccumulator, KzgAsProvingKey, KzgAsVerifyingKey, KzgSuccinctVerifyingKey},
-        AccumulationScheme, AccumulationSchemeProver, PolynomialCommitmentScheme,
+        kzg::{
+            KzgAccumulator, KzgAsProvingKey, KzgAsVerifyingKey, KzgDecidingKey,
+            KzgSuccinctVerifyingKey,
+        },
+        AccumulationDecider, AccumulationScheme, AccumulationSchemeProver,
+        PolynomialCommitmentScheme,
     },
     verifier::SnarkVerifier,
 };
 use std::{
     env::{set_var, var},
     fs::File,
+    ops::{Deref, DerefMut},
     path::Path,
     rc::Rc,
 };
 
-use super::{CircuitExt, PoseidonTranscript, Snark, POSEIDON_SPEC};
+use super::{CircuitExt, PoseidonTranscript, POSEIDON_SPEC};
 
 pub type Svk = KzgSuccinctVerifyingKey<G1Affine>;
 pub type BaseFieldEccChip<'chip> = halo2_ecc::ecc::BaseFieldEccChip<'chip, G1Affine>;
 pub type Halo2Loader<'chip> = loader::halo2::Halo2Loader<G1Affine, BaseFieldEccChip<'chip>>;
 
+/// Error of reading a proof written with [`SnarkTranscript::Evm`], which requires feature
+/// `loader_evm`.
+#[cfg(not(feature = "loader_evm"))]
+fn evm_transcript_unsupported() -> snark_verifier::Error {
+    snark_verifier::Error::Transcript(
+        std::io::ErrorKind::Unsupported,
+        "reading EVM transcript requires feature loader_evm".to_string(),
+    )
+}
+
 #[allow(clippy::type_complexity)]
 /// Core function used in `synthesize` to aggregate multiple `snarks`.
 ///  
@@ -58,12 +79,15 @@ pub type Halo2Loader<'chip> = loader::halo2::Halo2Loader<G1Affine, BaseFieldEccC
 /// For each previous snark, we concatenate all instances into a single vector. We return a vector of vectors,
 /// one vector per snark, for convenience.
 ///
+/// The proof of each snark is read in circuit by the transcript it's proven with, as its
+/// [`SnarkWithTranscript::transcript`] says.
+///
 /// # Assumptions
 /// * `snarks` is not empty
 pub fn aggregate<'a, AS>(
     svk: &Svk,
     loader: &Rc<Halo2Loader<'a>>,
-    snarks: &[Snark],
+    snarks: &[SnarkWithTranscript],
     as_proof: &[u8],
 ) -> (Vec<Vec<AssignedValue<Fr>>>, KzgAccumulator<G1Affine, Rc<Halo2Loader<'a>>>)
 where
@@ -99,19 +123,36 @@ where
 
     let mut accumulators = snarks
         .iter()
-        .flat_map(|snark| {
+        .flat_map(|SnarkWithTranscript { snark, transcript: snark_transcript }| {
             let protocol = snark.protocol.loaded(loader);
             let instances = assign_instances(&snark.instances);
 
             // read the transcript and perform Fiat-Shamir
             // run through verification computation and produce the final pair `succinct`
-            transcript.new_stream(snark.proof());
-            let proof = PlonkSuccinctVerifier::<AS>::read_proof(
-                svk,
-                &protocol,
-                &instances,
-                &mut transcript,
-            )
+            let proof = match snark_transcript {
+                #[cfg(feature = "loader_evm")]
+                SnarkTranscript::Evm => {
+                    let mut transcript =
+                        EvmTranscript::<_, Rc<Halo2Loader<'a>>, _, _>::new(loader, snark.proof());
+                    PlonkSuccinctVerifier::<AS>::read_proof(
+                        svk,
+                        &protocol,
+                        &instances,
+                        &mut transcript,
+                    )
+                }
+                #[cfg(not(feature = "loader_evm"))]
+                SnarkTranscript::Evm => Err(evm_transcript_unsupported()),
+                SnarkTranscript::Poseidon => {
+                    transcript.new_stream(snark.proof());
+                    PlonkSuccinctVerifier::<AS>::read_proof(
+                        svk,
+                        &protocol,
+                        &instances,
+                        &mut transcript,
+                    )
+                }
+            }
             .unwrap();
             let accumulator =
                 PlonkSuccinctVerifier::<AS>::verify(svk, &protocol, &instances, &proof).unwrap();
@@ -125,6 +166,7 @@ where
         .collect_vec();
 
     let accumulator = if accumulators.len() > 1 {
+        Loader::<G1Affine>::start_cost_metering(loader, "accumulation_scheme");
         transcript.new_stream(as_proof);
         let proof = <AS as AccumulationScheme<_, _>>::read_proof(
             &Default::default(),
@@ -132,8 +174,11 @@ where
             &mut transcript,
         )
         .unwrap();
-        <AS as AccumulationScheme<_, _>>::verify(&Default::default(), &accumulators, &proof)
-            .unwrap()
+        let accumulator =
+            <AS as AccumulationScheme<_, _>>::verify(&Default::default(), &accumulators, &proof)
+                .unwrap();
+        Loader::<G1Affine>::end_cost_metering(loader);
+        accumulator
     } else {
         accumulators.pop().unwrap()
     };
@@ -167,6 +212,10 @@ pub struct AggregationCircuit {
     pub previous_instances: Vec<Vec<AssignedValue<Fr>>>,
     // accumulation scheme proof, private input
     pub as_proof: Vec<u8>, // not sure this needs to be stored, keeping for now
+    // cells used by each section of the in-circuit verification, e.g. `read_proof` (transcript) and
+    // `pcs_verify` (MSM) of each snark; arithmetic deferred by `quotient_evaluation` is counted by
+    // the section fusing it, usually `pcs_verify`, and also reported as its `deferred` cells
+    pub cell_profile: CellProfile,
 }
 
 // trait just so we can have a generic that is either SHPLONK or GWC
@@ -180,7 +229,8 @@ pub trait Halo2KzgAccumulationScheme<'a> = PolynomialCommitmentScheme<
         Rc<Halo2Loader<'a>>,
         Accumulator = KzgAccumulator<G1Affine, Rc<Halo2Loader<'a>>>,
         VerifyingKey = KzgAsVerifyingKey,
-    > + PolynomialCommitmentScheme<
+    > + AccumulationDecider<G1Affine, Rc<Halo2Loader<'a>>, DecidingKey = KzgDecidingKey<Bn256>>
+    + PolynomialCommitmentScheme<
         G1Affine,
         NativeLoader,
         VerifyingKey = Svk,
@@ -205,27 +255,62 @@ impl AggregationCircuit {
         break_points: Option<MultiPhaseThreadBreakPoints>,
         lookup_bits: usize,
         params: &ParamsKZG<Bn256>,
-        snarks: impl IntoIterator<Item = Snark>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
+    ) -> Self
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+    {
+        Self::build::<AS>(stage, break_points, lookup_bits, params, snarks, false)
+    }
+
+    /// Builds the circuit as [`AggregationCircuit::new`], which also does the final pairing check
+    /// in circuit by [`AccumulationDecider`] and exposes no accumulator if `decide` is true. It's
+    /// only reachable with `decide` through [`DecidedAggregationCircuit`], whose
+    /// [`CircuitExt::accumulator_indices`] is `None` accordingly.
+    fn build<AS>(
+        stage: CircuitBuilderStage,
+        break_points: Option<MultiPhaseThreadBreakPoints>,
+        lookup_bits: usize,
+        params: &ParamsKZG<Bn256>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
+        decide: bool,
     ) -> Self
     where
         AS: for<'a> Halo2KzgAccumulationScheme<'a>,
     {
         let svk: Svk = params.get_g()[0].into();
-        let snarks = snarks.into_iter().collect_vec();
+        let snarks = snarks.into_iter().map(Into::into).collect_vec();
 
         let mut transcript_read =
             PoseidonTranscript::<NativeLoader, &[u8]>::from_spec(&[], POSEIDON_SPEC.clone());
         // TODO: the snarks can probably store these accumulators
         let accumulators = snarks
             .iter()
-            .flat_map(|snark| {
-                transcript_read.new_stream(snark.proof());
-                let proof = PlonkSuccinctVerifier::<AS>::read_proof(
-                    &svk,
-                    &snark.protocol,
-                    &snark.instances,
-                    &mut transcript_read,
-                )
+            .flat_map(|SnarkWithTranscript { snark, transcript }| {
+                let proof = match transcript {
+                    #[cfg(feature = "loader_evm")]
+                    SnarkTranscript::Evm => {
+                        let mut transcript =
+                            EvmTranscript::<_, NativeLoader, _, _>::new(snark.proof());
+                        PlonkSuccinctVerifier::<AS>::read_proof(
+                            &svk,
+                            &snark.protocol,
+                            &snark.instances,
+                            &mut transcript,
+                        )
+                    }
+                    #[cfg(not(feature = "loader_evm"))]
+                    SnarkTranscript::Evm => Err(evm_transcript_unsupported()),
+                    SnarkTranscript::Poseidon => {
+                        transcript_read.new_stream(snark.proof());
+                        PlonkSuccinctVerifier::<AS>::read_proof(
+                            &svk,
+                            &snark.protocol,
+                            &snark.instances,
+                            &mut transcript_read,
+                        )
+                    }
+                }
                 .unwrap();
                 PlonkSuccinctVerifier::<AS>::verify(&svk, &snark.protocol, &snark.instances, &proof)
                     .unwrap()
@@ -258,17 +343,24 @@ impl AggregationCircuit {
 
         let (previous_instances, accumulator) =
             aggregate::<AS>(&svk, &loader, &snarks, as_proof.as_slice());
-        let lhs = accumulator.lhs.assigned();
-        let rhs = accumulator.rhs.assigned();
-        let assigned_instances = lhs
-            .x()
-            .limbs()
-            .iter()
-            .chain(lhs.y().limbs().iter())
-            .chain(rhs.x().limbs().iter())
-            .chain(rhs.y().limbs().iter())
-            .copied()
-            .collect_vec();
+        let assigned_instances = if decide {
+            let dk: KzgDecidingKey<Bn256> = (svk.g, params.g2(), params.s_g2()).into();
+            Loader::<G1Affine>::start_cost_metering(&loader, "decide");
+            <AS as AccumulationDecider<_, Rc<Halo2Loader>>>::decide(&dk, accumulator).unwrap();
+            Loader::<G1Affine>::end_cost_metering(&loader);
+            Vec::new()
+        } else {
+            let lhs = accumulator.lhs.assigned();
+            let rhs = accumulator.rhs.assigned();
+            lhs.x()
+                .limbs()
+                .iter()
+                .chain(lhs.y().limbs().iter())
+                .chain(rhs.x().limbs().iter())
+                .chain(rhs.y().limbs().iter())
+                .copied()
+                .collect_vec()
+        };
 
         #[cfg(debug_assertions)]
         {
@@ -280,6 +372,7 @@ impl AggregationCircuit {
             }
         }
 
+        let cell_profile = loader.cell_profile();
         let builder = loader.take_ctx();
         let circuit = match stage {
             CircuitBuilderStage::Mock => RangeCircuitBuilder::mock(builder),
@@ -289,7 +382,7 @@ impl AggregationCircuit {
             }
         };
         let inner = RangeWithInstanceCircuitBuilder::new(circuit, assigned_instances);
-        Self { inner, previous_instances, as_proof }
+        Self { inner, previous_instances, as_proof, cell_profile }
     }
 
     pub fn public<AS>(
@@ -297,7 +390,7 @@ impl AggregationCircuit {
         break_points: Option<MultiPhaseThreadBreakPoints>,
         lookup_bits: usize,
         params: &ParamsKZG<Bn256>,
-        snarks: impl IntoIterator<Item = Snark>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
         has_prev_accumulator: bool,
     ) -> Self
     where
@@ -310,13 +403,33 @@ impl AggregationCircuit {
 
     // this function is for convenience
     /// `params` should be the universal trusted setup to be used for the aggregation circuit, not the one used to generate the previous snarks, although we assume both use the same generator g[0]
-    pub fn keygen<AS>(params: &ParamsKZG<Bn256>, snarks: impl IntoIterator<Item = Snark>) -> Self
+    pub fn keygen<AS>(
+        params: &ParamsKZG<Bn256>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
+    ) -> Self
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+    {
+        Self::keygen_with::<AS>(params, snarks, false)
+    }
+
+    fn keygen_with<AS>(
+        params: &ParamsKZG<Bn256>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
+        decide: bool,
+    ) -> Self
     where
         AS: for<'a> Halo2KzgAccumulationScheme<'a>,
     {
         let lookup_bits = params.k() as usize - 1; // almost always we just use the max lookup bits possible, which is k - 1 because of blinding factors
-        let circuit =
-            Self::new::<AS>(CircuitBuilderStage::Keygen, None, lookup_bits, params, snarks);
+        let circuit = Self::build::<AS>(
+            CircuitBuilderStage::Keygen,
+            None,
+            lookup_bits,
+            params,
+            snarks,
+            decide,
+        );
         circuit.config(params.k(), Some(10));
         set_var("LOOKUP_BITS", lookup_bits.to_string());
         circuit
@@ -325,19 +438,32 @@ impl AggregationCircuit {
     // this function is for convenience
     pub fn prover<AS>(
         params: &ParamsKZG<Bn256>,
-        snarks: impl IntoIterator<Item = Snark>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
         break_points: MultiPhaseThreadBreakPoints,
     ) -> Self
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+    {
+        Self::prover_with::<AS>(params, snarks, break_points, false)
+    }
+
+    fn prover_with<AS>(
+        params: &ParamsKZG<Bn256>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
+        break_points: MultiPhaseThreadBreakPoints,
+        decide: bool,
+    ) -> Self
     where
         AS: for<'a> Halo2KzgAccumulationScheme<'a>,
     {
         let lookup_bits: usize = var("LOOKUP_BITS").expect("LOOKUP_BITS not set").parse().unwrap();
-        let circuit = Self::new::<AS>(
+        let circuit = Self::build::<AS>(
             CircuitBuilderStage::Prover,
             Some(break_points),
             lookup_bits,
             params,
             snarks,
+            decide,
         );
         let minimum_rows = var("MINIMUM_ROWS").map(|s| s.parse().unwrap_or(10)).unwrap_or(10);
         circuit.config(params.k(), Some(minimum_rows));
@@ -429,6 +555,127 @@ impl CircuitExt<Fr> for AggregationCircuit {
     }
 }
 
+/// [`AggregationCircuit`] which also does the final pairing check in circuit, so its snark is
+/// self-contained and exposes no accumulator limbs, only the previous instances the user chooses
+/// to expose. The in-circuit pairing is costly, so it's best used as the last layer of
+/// aggregation.
+///
+/// The deciding key is taken from `g2` and `s_g2` of the `params` passed to build the circuit, so
+/// they must come from the same trusted setup, i.e. the same tau, as the params the aggregated
+/// snarks are committed with, though the degree may differ. Otherwise the pairing check fails and
+/// the circuit is unsatisfiable even for valid snarks.
+#[derive(Clone, Debug)]
+pub struct DecidedAggregationCircuit(pub AggregationCircuit);
+
+impl DecidedAggregationCircuit {
+    /// Same as [`AggregationCircuit::new`], but decides the final accumulator in circuit.
+    pub fn new<AS>(
+        stage: CircuitBuilderStage,
+        break_points: Option<MultiPhaseThreadBreakPoints>,
+        lookup_bits: usize,
+        params: &ParamsKZG<Bn256>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
+    ) -> Self
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+    {
+        Self(AggregationCircuit::build::<AS>(
+            stage,
+            break_points,
+            lookup_bits,
+            params,
+            snarks,
+            true,
+        ))
+    }
+
+    pub fn public<AS>(
+        stage: CircuitBuilderStage,
+        break_points: Option<MultiPhaseThreadBreakPoints>,
+        lookup_bits: usize,
+        params: &ParamsKZG<Bn256>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
+        has_prev_accumulator: bool,
+    ) -> Self
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+    {
+        let mut private = Self::new::<AS>(stage, break_points, lookup_bits, params, snarks);
+        private.expose_previous_instances(has_prev_accumulator);
+        private
+    }
+
+    pub fn keygen<AS>(
+        params: &ParamsKZG<Bn256>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
+    ) -> Self
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+    {
+        Self(AggregationCircuit::keygen_with::<AS>(params, snarks, true))
+    }
+
+    pub fn prover<AS>(
+        params: &ParamsKZG<Bn256>,
+        snarks: impl IntoIterator<Item = impl Into<SnarkWithTranscript>>,
+        break_points: MultiPhaseThreadBreakPoints,
+    ) -> Self
+    where
+        AS: for<'a> Halo2KzgAccumulationScheme<'a>,
+    {
+        Self(AggregationCircuit::prover_with::<AS>(params, snarks, break_points, true))
+    }
+}
+
+impl Deref for DecidedAggregationCircuit {
+    type Target = AggregationCircuit;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for DecidedAggregationCircuit {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Circuit<Fr> for DecidedAggregationCircuit {
+    type Config = RangeWithInstanceConfig<Fr>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self(self.0.without_witnesses())
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        AggregationCircuit::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        layouter: impl Layouter<Fr>,
+    ) -> Result<(), plonk::Error> {
+        self.0.synthesize(config, layouter)
+    }
+}
+
+impl CircuitExt<Fr> for DecidedAggregationCircuit {
+    fn num_instance(&self) -> Vec<usize> {
+        self.0.num_instance()
+    }
+
+    fn instances(&self) -> Vec<Vec<Fr>> {
+        self.0.instances()
+    }
+
+    fn selectors(config: &Self::Config) -> Vec<Selector> {
+        AggregationCircuit::selectors(config)
+    }
+}
+
 pub fn load_verify_circuit_degree() -> u32 {
     let path = std::env::var("VERIFY_CONFIG")
         .unwrap_or_else(|_| "./configs/verify_circuit.config".to_string());
@@ -438,3 +685,79 @@ pub fn load_verify_circuit_degree() -> u32 {
     .unwrap();
     params.degree
 }
+
+#[cfg(feature = "loader_evm")]
+#[test]
+fn test_decided_aggregation() {
+    use crate::{
+        halo2::gen_snark_shplonk,
+        test::{gen_evm_fixture, StandardPlonk},
+        Snark, SHPLONK,
+    };
+    use halo2_base::halo2_proofs::dev::MockProver;
+
+    // the deciding key only needs the same setup as the aggregated snarks, not a larger one
+    let (params, pk, _, _, _) = gen_evm_fixture(StandardPlonk::new(7));
+    let snark = gen_snark_shplonk(&params, &pk, StandardPlonk::new(7), None::<&str>);
+    let mut tampered = snark.clone();
+    tampered.instances[0][0] += Fr::from(1);
+
+    let k = 20;
+    let lookup_bits = k as usize - 1;
+    set_var("LOOKUP_BITS", lookup_bits.to_string());
+    let mock = |snark: Snark| {
+        let circuit = DecidedAggregationCircuit::new::<SHPLONK>(
+            CircuitBuilderStage::Mock,
+            None,
+            lookup_bits,
+            &params,
+            [snark],
+        );
+        circuit.config(k, Some(10));
+        assert_eq!(circuit.instances(), vec![vec![]]);
+        assert!(circuit.cell_profile.total_of("decide").advice > 0);
+        MockProver::run(k, &circuit, circuit.instances()).unwrap().verify()
+    };
+    assert_eq!(mock(snark), Ok(()));
+    assert!(mock(tampered).is_err());
+    assert_eq!(DecidedAggregationCircuit::accumulator_indices(), None);
+}
+
+#[cfg(feature = "loader_evm")]
+#[test]
+fn test_aggregation_transcript_per_snark() {
+    use crate::{
+        halo2::gen_snark_shplonk,
+        test::{gen_evm_fixture, StandardPlonk},
+        Snark, SHPLONK,
+    };
+    use halo2_base::halo2_proofs::dev::MockProver;
+    use snark_verifier::system::halo2::{compile, Config};
+
+    let (params, pk, num_instance, instances, proof) = gen_evm_fixture(StandardPlonk::new(7));
+    let poseidon_snark = gen_snark_shplonk(&params, &pk, StandardPlonk::new(7), None::<&str>);
+    let protocol = compile(&params, pk.get_vk(), Config::kzg().with_num_instance(num_instance));
+    let evm_snark = Snark::new(protocol, instances, proof).with_transcript(SnarkTranscript::Evm);
+    let mut tampered = evm_snark.clone();
+    tampered.snark.instances[0][0] += Fr::from(1);
+
+    let k = 20;
+    let lookup_bits = k as usize - 1;
+    set_var("LOOKUP_BITS", lookup_bits.to_string());
+    let mock = |snarks: Vec<SnarkWithTranscript>| {
+        let circuit = DecidedAggregationCircuit::new::<SHPLONK>(
+            CircuitBuilderStage::Mock,
+            None,
+            lookup_bits,
+            &params,
+            snarks,
+        );
+        circuit.config(k, Some(10));
+        MockProver::run(k, &circuit, circuit.instances()).unwrap().verify()
+    };
+    // a snark without transcript is read with poseidon as before
+    let poseidon_snark = SnarkWithTranscript::from(poseidon_snark);
+    assert_eq!(poseidon_snark.transcript, SnarkTranscript::Poseidon);
+    assert_eq!(mock(vec![poseidon_snark.clone(), evm_snark]), Ok(()));
+    assert!(mock(vec![poseidon_snark, tampered]).is_err());
+}
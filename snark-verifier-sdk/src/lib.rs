@@ -30,6 +30,9 @@ pub mod evm;
 #[cfg(feature = "loader_halo2")]
 pub mod halo2;
 
+#[cfg(test)]
+mod test;
+
 pub const LIMBS: usize = 3;
 pub const BITS: usize = 88;
 
@@ -41,6 +44,18 @@ pub type PlonkSuccinctVerifier<AS> =
 pub type SHPLONK = KzgAs<Bn256, Bdfg21>;
 pub type GWC = KzgAs<Bn256, Gwc19>;
 
+/// Transcript a snark's proof is written with, which decides how it's read when aggregated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "halo2-axiom", derive(Serialize, Deserialize))]
+pub enum SnarkTranscript {
+    /// Poseidon transcript, as used by `halo2::gen_snark_shplonk` and `halo2::gen_snark_gwc`.
+    #[default]
+    Poseidon,
+    /// Keccak256 transcript, as used by `evm::gen_evm_snark`, which is only readable with feature
+    /// `loader_evm`.
+    Evm,
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "halo2-axiom", derive(Serialize, Deserialize))]
 pub struct Snark {
@@ -57,6 +72,28 @@ impl Snark {
     pub fn proof(&self) -> &[u8] {
         &self.proof[..]
     }
+
+    /// Pairs the snark with the transcript its proof is written with, e.g.
+    /// [`SnarkTranscript::Evm`] for a proof from `evm::gen_evm_proof`, to be aggregated.
+    pub fn with_transcript(self, transcript: SnarkTranscript) -> SnarkWithTranscript {
+        SnarkWithTranscript { snark: self, transcript }
+    }
+}
+
+/// [`Snark`] with the transcript its proof is written with, which is what aggregation takes, so
+/// [`Snark`] itself is serialized the same regardless of it. A [`Snark`] converts into one with
+/// [`SnarkTranscript::Poseidon`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "halo2-axiom", derive(Serialize, Deserialize))]
+pub struct SnarkWithTranscript {
+    pub snark: Snark,
+    pub transcript: SnarkTranscript,
+}
+
+impl From<Snark> for SnarkWithTranscript {
+    fn from(snark: Snark) -> Self {
+        snark.with_transcript(SnarkTranscript::default())
+    }
 }
 
 pub trait CircuitExt<F: Field>: Circuit<F> {
@@ -0,0 +1,165 @@
+//! Circuits and helpers shared by tests.
+
+use crate::CircuitExt;
+#[cfg(feature = "loader_evm")]
+use crate::{evm::gen_evm_proof_shplonk, gen_pk};
+use halo2_base::halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    halo2curves::bn256::{Bn256, Fr},
+    plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Fixed, Instance},
+    poly::{kzg::commitment::ParamsKZG, Rotation},
+};
+#[cfg(feature = "loader_evm")]
+use halo2_base::halo2_proofs::{halo2curves::bn256::G1Affine, plonk::ProvingKey};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+#[derive(Clone, Copy)]
+pub struct StandardPlonkConfig {
+    a: Column<Advice>,
+    b: Column<Advice>,
+    c: Column<Advice>,
+    q_a: Column<Fixed>,
+    q_b: Column<Fixed>,
+    q_c: Column<Fixed>,
+    q_ab: Column<Fixed>,
+    constant: Column<Fixed>,
+    #[allow(dead_code)]
+    instance: Column<Instance>,
+}
+
+impl StandardPlonkConfig {
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self {
+        let [a, b, c] = [(); 3].map(|_| meta.advice_column());
+        let [q_a, q_b, q_c, q_ab, constant] = [(); 5].map(|_| meta.fixed_column());
+        let instance = meta.instance_column();
+
+        [a, b, c].map(|column| meta.enable_equality(column));
+
+        meta.create_gate("q_a·a + q_b·b + q_c·c + q_ab·a·b + constant + instance = 0", |meta| {
+            let [a, b, c] = [a, b, c].map(|column| meta.query_advice(column, Rotation::cur()));
+            let [q_a, q_b, q_c, q_ab, constant] = [q_a, q_b, q_c, q_ab, constant]
+                .map(|column| meta.query_fixed(column, Rotation::cur()));
+            let instance = meta.query_instance(instance, Rotation::cur());
+            Some(q_a * a.clone() + q_b * b.clone() + q_c * c + q_ab * a * b + constant + instance)
+        });
+
+        StandardPlonkConfig { a, b, c, q_a, q_b, q_c, q_ab, constant, instance }
+    }
+}
+
+/// Circuit with a single instance equal to `instance`. Its fixed columns are
+/// scaled by `scale`, so circuits with different `scale` share the same shape
+/// but have different preprocessed commitments.
+#[derive(Clone, Copy)]
+pub struct StandardPlonk {
+    pub instance: Fr,
+    pub scale: u64,
+}
+
+impl StandardPlonk {
+    pub fn new(instance: u64) -> Self {
+        Self::with_scale(instance, 1)
+    }
+
+    pub fn with_scale(instance: u64, scale: u64) -> Self {
+        Self { instance: Fr::from(instance), scale }
+    }
+}
+
+impl CircuitExt<Fr> for StandardPlonk {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn instances(&self) -> Vec<Vec<Fr>> {
+        vec![vec![self.instance]]
+    }
+}
+
+impl Circuit<Fr> for StandardPlonk {
+    type Config = StandardPlonkConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { instance: Fr::zero(), scale: self.scale }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        meta.set_minimum_degree(4);
+        StandardPlonkConfig::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        let scale = Fr::from(self.scale);
+        layouter.assign_region(
+            || "",
+            |mut region| {
+                #[cfg(feature = "halo2-pse")]
+                {
+                    region.assign_advice(|| "", config.a, 0, || Value::known(self.instance))?;
+                    region.assign_fixed(|| "", config.q_a, 0, || Value::known(-Fr::one()))?;
+                    region.assign_advice(|| "", config.a, 1, || Value::known(-Fr::from(5u64)))?;
+                    for (idx, column) in (1..).zip([
+                        config.q_a,
+                        config.q_b,
+                        config.q_c,
+                        config.q_ab,
+                        config.constant,
+                    ]) {
+                        let value = Fr::from(idx as u64) * scale;
+                        region.assign_fixed(|| "", column, 1, || Value::known(value))?;
+                    }
+                    let a = region.assign_advice(|| "", config.a, 2, || Value::known(Fr::one()))?;
+                    a.copy_advice(|| "", &mut region, config.b, 3)?;
+                    a.copy_advice(|| "", &mut region, config.c, 4)?;
+                }
+                #[cfg(feature = "halo2-axiom")]
+                {
+                    region.assign_advice(config.a, 0, Value::known(self.instance));
+                    region.assign_fixed(config.q_a, 0, -Fr::one());
+                    region.assign_advice(config.a, 1, Value::known(-Fr::from(5u64)));
+                    for (idx, column) in (1..).zip([
+                        config.q_a,
+                        config.q_b,
+                        config.q_c,
+                        config.q_ab,
+                        config.constant,
+                    ]) {
+                        region.assign_fixed(column, 1, Fr::from(idx as u64) * scale);
+                    }
+                    let a = region.assign_advice(config.a, 2, Value::known(Fr::one()));
+                    a.copy_advice(&mut region, config.b, 3);
+                    a.copy_advice(&mut region, config.c, 4);
+                }
+
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Returns deterministic `ParamsKZG` of degree `k` for tests.
+pub fn gen_srs(k: u32) -> ParamsKZG<Bn256> {
+    ParamsKZG::setup(k, ChaCha20Rng::from_seed(Default::default()))
+}
+
+/// Returns params of degree 8, proving key, number of instances, instances
+/// and proof of `circuit` generated by [`gen_evm_proof_shplonk`].
+///
+/// Params are deterministic, so they're the same for every `circuit`.
+#[cfg(feature = "loader_evm")]
+#[allow(clippy::type_complexity)]
+pub fn gen_evm_fixture(
+    circuit: StandardPlonk,
+) -> (ParamsKZG<Bn256>, ProvingKey<G1Affine>, Vec<usize>, Vec<Vec<Fr>>, Vec<u8>) {
+    let params = gen_srs(8);
+    let pk = gen_pk(&params, &circuit, None);
+    let num_instance = circuit.num_instance();
+    let instances = circuit.instances();
+    let proof = gen_evm_proof_shplonk(&params, &pk, circuit, instances.clone());
+    (params, pk, num_instance, instances, proof)
+}
@@ -180,7 +180,7 @@ fn gen_pk<C: Circuit<Fr>>(params: &ParamsKZG<Bn256>, circuit: &C) -> ProvingKey<
     keygen_pk(params, vk, circuit).unwrap()
 }
 
-fn gen_proof<C: Circuit<Fr>>(
+fn gen_proof<C: Circuit<Fr>, const COMPRESSED: bool>(
     params: &ParamsKZG<Bn256>,
     pk: &ProvingKey<G1Affine>,
     circuit: C,
@@ -191,14 +191,14 @@ fn gen_proof<C: Circuit<Fr>>(
     let instances = instances.iter().map(|instances| instances.as_slice()).collect_vec();
     let proof = {
         let mut transcript = TranscriptWriterBuffer::<_, G1Affine, _>::init(Vec::new());
-        create_proof::<KZGCommitmentScheme<Bn256>, ProverGWC<_>, _, _, EvmTranscript<_, _, _, _>, _>(
-            params,
-            pk,
-            &[circuit],
-            &[instances.as_slice()],
-            OsRng,
-            &mut transcript,
-        )
+        create_proof::<
+            KZGCommitmentScheme<Bn256>,
+            ProverGWC<_>,
+            _,
+            _,
+            EvmTranscript<_, _, _, _, COMPRESSED>,
+            _,
+        >(params, pk, &[circuit], &[instances.as_slice()], OsRng, &mut transcript)
         .unwrap();
         transcript.finalize()
     };
@@ -206,7 +206,7 @@ fn gen_proof<C: Circuit<Fr>>(
     let accept = {
         let mut transcript = TranscriptReadBuffer::<_, G1Affine, _>::init(proof.as_slice());
         VerificationStrategy::<_, VerifierGWC<_>>::finalize(
-            verify_proof::<_, VerifierGWC<_>, _, EvmTranscript<_, _, _, _>, _>(
+            verify_proof::<_, VerifierGWC<_>, _, EvmTranscript<_, _, _, _, COMPRESSED>, _>(
                 params.verifier_params(),
                 pk.get_vk(),
                 AccumulatorStrategy::new(params.verifier_params()),
@@ -221,7 +221,7 @@ fn gen_proof<C: Circuit<Fr>>(
     proof
 }
 
-fn gen_evm_verifier(
+fn gen_evm_verifier<const COMPRESSED: bool>(
     params: &ParamsKZG<Bn256>,
     vk: &VerifyingKey<G1Affine>,
     num_instance: Vec<usize>,
@@ -231,7 +231,7 @@ fn gen_evm_verifier(
 
     let loader = EvmLoader::new::<Fq, Fr>();
     let protocol = protocol.loaded(&loader);
-    let mut transcript = EvmTranscript::<_, Rc<EvmLoader>, _, _>::new(&loader);
+    let mut transcript = EvmTranscript::<_, Rc<EvmLoader>, _, _, COMPRESSED>::new(&loader);
 
     let instances = transcript.load_instances(num_instance);
     let proof = PlonkVerifier::read_proof(&vk, &protocol, &instances, &mut transcript).unwrap();
@@ -249,8 +249,6 @@ fn evm_verify(deployment_code: Vec<u8>, instances: Vec<Vec<Fr>>, proof: Vec<u8>)
         let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
         let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
 
-        dbg!(result.gas_used);
-
         !result.reverted
     };
     assert!(success);
@@ -261,8 +259,14 @@ fn main() {
 
     let circuit = StandardPlonk::rand(OsRng);
     let pk = gen_pk(&params, &circuit);
-    let deployment_code = gen_evm_verifier(&params, pk.get_vk(), StandardPlonk::num_instance());
 
-    let proof = gen_proof(&params, &pk, circuit.clone(), circuit.instances());
+    let deployment_code =
+        gen_evm_verifier::<false>(&params, pk.get_vk(), StandardPlonk::num_instance());
+    let proof = gen_proof::<_, false>(&params, &pk, circuit.clone(), circuit.instances());
+    evm_verify(deployment_code, circuit.instances(), proof);
+
+    let deployment_code =
+        gen_evm_verifier::<true>(&params, pk.get_vk(), StandardPlonk::num_instance());
+    let proof = gen_proof::<_, true>(&params, &pk, circuit.clone(), circuit.instances());
     evm_verify(deployment_code, circuit.instances(), proof);
 }
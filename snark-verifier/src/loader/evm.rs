@@ -2,16 +2,31 @@
 
 mod code;
 pub(crate) mod loader;
+#[cfg(any(test, feature = "evm_gas_profiling"))]
+mod profile;
+mod revert;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod soundness;
 pub(crate) mod util;
+pub(crate) mod vk;
 
 #[cfg(test)]
 mod test;
 
+pub use code::{solidity_abi, Precompiled};
 pub use loader::{EcPoint, EvmLoader, Scalar};
+#[cfg(any(test, feature = "evm_gas_profiling"))]
+pub use profile::{GasProfile, GasSection};
+pub use revert::{CheckFailure, CheckKind, CheckSite, CHECK_FAILURE_SIGNATURE};
 pub use util::{
-    compile_yul, encode_calldata, estimate_gas, fe_to_u256, modulus, u256_to_fe, Address,
-    ExecutorBuilder, H256, U256, U512,
+    assemble_yul, assemble_yul_with_source_map, compile_solidity, compile_yul, compress_ec_point,
+    decompress_ec_point, encode_calldata, encode_calldata_abi, encode_calldata_multi,
+    encode_calldata_route, estimate_gas, estimate_verifier_gas, estimate_yul_gas, fe_to_u256,
+    fn_selector, modulus, u256_to_fe, Address, AssemblerError, CalldataPtr, EvmCodeSizeError,
+    ExecutorBuilder, GasEstimate, GasSchedule, PrecompileUsage, SourceMap, StructLog, H256,
+    MAX_RUNTIME_SIZE, U256, U512, VERIFY_PROOF_SIGNATURE,
 };
+pub use vk::EvmVerifyingKey;
 
 #[cfg(test)]
-pub use test::execute;
+pub use test::{execute, execute_yul};
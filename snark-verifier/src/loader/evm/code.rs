@@ -1,25 +1,82 @@
+use crate::loader::evm::Address;
+use std::ops::Range;
+
+/// Precompiled contract called by verifier, whose discriminant is its address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Precompiled {
+    /// Modular exponentiation `modexp`, used for inversion.
     BigModExp = 0x05,
+    /// Point addition `ecAdd` on BN254.
     Bn254Add = 0x6,
+    /// Scalar multiplication `ecMul` on BN254.
     Bn254ScalarMul = 0x7,
+    /// Pairing check `ecPairing` on BN254.
     Bn254Pairing = 0x8,
+    /// Compression function `blake2f` of BLAKE2b.
+    Blake2f = 0x9,
 }
 
+impl Precompiled {
+    /// Returns the precompile at `address`, or `None` if it's not one of
+    /// [`Precompiled`].
+    pub fn from_address(address: Address) -> Option<Self> {
+        [
+            Self::BigModExp,
+            Self::Bn254Add,
+            Self::Bn254ScalarMul,
+            Self::Bn254Pairing,
+            Self::Blake2f,
+        ]
+        .into_iter()
+        .find(|precompile| precompile.address() == address)
+    }
+
+    /// Returns address of the precompile.
+    pub fn address(&self) -> Address {
+        Address::from_low_u64_be(*self as u64)
+    }
+}
+
+const REVERSE_BYTES: &str = "function reverse_bytes(v) -> r {
+                        v := bitor(shr(8, bitand(v, 0xff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00)), shl(8, bitand(v, 0x00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff00ff)))
+                        v := bitor(shr(16, bitand(v, 0xffff0000ffff0000ffff0000ffff0000ffff0000ffff0000ffff0000ffff0000)), shl(16, bitand(v, 0x0000ffff0000ffff0000ffff0000ffff0000ffff0000ffff0000ffff0000ffff)))
+                        v := bitor(shr(32, bitand(v, 0xffffffff00000000ffffffff00000000ffffffff00000000ffffffff00000000)), shl(32, bitand(v, 0x00000000ffffffff00000000ffffffff00000000ffffffff00000000ffffffff)))
+                        v := bitor(shr(64, bitand(v, 0xffffffffffffffff0000000000000000ffffffffffffffff0000000000000000)), shl(64, bitand(v, 0x0000000000000000ffffffffffffffff0000000000000000ffffffffffffffff)))
+                        r := bitor(shr(128, v), shl(128, v))
+                    }
+                    ";
+
 #[derive(Clone, Debug)]
 pub struct YulCode {
     // runtime code area
     runtime: String,
+    // offsets in runtime code area where labels start
+    labels: Vec<(usize, String)>,
+    // whether runtime code calls `reverse_bytes`
+    reverse_bytes: bool,
 }
 
 impl YulCode {
     pub fn new() -> Self {
         YulCode {
             runtime: String::new(),
+            labels: Vec::new(),
+            reverse_bytes: false,
         }
     }
 
     pub fn code(&self, base_modulus: String, scalar_modulus: String) -> String {
-        format!(
+        self.code_with_labels(base_modulus, scalar_modulus).0
+    }
+
+    /// Returns code with byte ranges of it covered by non-empty labels.
+    pub fn code_with_labels(
+        &self,
+        base_modulus: String,
+        scalar_modulus: String,
+    ) -> (String, Vec<(Range<usize>, String)>) {
+        let reverse_bytes = if self.reverse_bytes { REVERSE_BYTES } else { "" };
+        let prefix = format!(
             "
         object \"plonk_verifier\" {{
             code {{
@@ -53,12 +110,40 @@ impl YulCode {
                             valid := and(valid, is_affine)
                         }}
                     }}
-                    {}
-                }}
-            }}
-        }}",
-            self.runtime
-        )
+                    {reverse_bytes}"
+        );
+        let suffix = "
+                }
+            }
+        }";
+
+        let start = prefix.len();
+        let labels = self
+            .labels
+            .iter()
+            .zip(
+                self.labels
+                    .iter()
+                    .skip(1)
+                    .map(|(offset, _)| *offset)
+                    .chain(Some(self.runtime.len())),
+            )
+            .filter(|((offset, label), end)| !label.is_empty() && offset < end)
+            .map(|((offset, label), end)| (start + offset..start + end, label.clone()))
+            .collect();
+        (prefix + &self.runtime + suffix, labels)
+    }
+
+    /// Includes `reverse_bytes` in the runtime code, which should be called
+    /// by anything appending code that calls it.
+    pub fn require_reverse_bytes(&mut self) {
+        self.reverse_bytes = true;
+    }
+
+    /// Labels the code appended afterwards, or stops labelling if `label` is
+    /// empty.
+    pub fn set_label(&mut self, label: String) {
+        self.labels.push((self.runtime.len(), label));
     }
 
     pub fn runtime_append(&mut self, mut code: String) {
@@ -66,3 +151,62 @@ impl YulCode {
         self.runtime.push_str(&code);
     }
 }
+
+/// Returns ABI JSON of the verifier reading calldata by
+/// [`EvmTranscript::new_abi`](crate::system::halo2::transcript::evm::EvmTranscript::new_abi),
+/// whose only function is `verifyProof(bytes proof, uint256[] instances) returns (bool)`, so
+/// other contracts could call the verifier directly.
+pub fn solidity_abi() -> String {
+    r#"[
+  {
+    "inputs": [
+      { "internalType": "bytes", "name": "proof", "type": "bytes" },
+      { "internalType": "uint256[]", "name": "instances", "type": "uint256[]" }
+    ],
+    "name": "verifyProof",
+    "outputs": [{ "internalType": "bool", "name": "", "type": "bool" }],
+    "stateMutability": "view",
+    "type": "function"
+  }
+]
+"#
+    .to_string()
+}
+
+#[test]
+fn test_solidity_abi() {
+    use crate::{loader::evm::VERIFY_PROOF_SIGNATURE, util::Itertools};
+
+    let abi: serde_json::Value = serde_json::from_str(&solidity_abi()).unwrap();
+    let signatures = abi
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            let inputs = entry["inputs"].as_array().unwrap();
+            let types = inputs.iter().map(|input| input["type"].as_str().unwrap()).join(",");
+            format!("{}({types})", entry["name"].as_str().unwrap())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(signatures, [VERIFY_PROOF_SIGNATURE]);
+}
+
+#[test]
+fn test_reverse_bytes_on_demand() {
+    use crate::halo2_curves::bn256::{Fq, Fr};
+    use crate::loader::evm::{assemble_yul, EvmLoader};
+
+    let declared = |code: &str| code.contains("function reverse_bytes");
+    let loader = EvmLoader::new::<Fq, Fr>();
+    loader.calldataload_scalar(0);
+    loader.calldataload_ec_point_compressed(0x20);
+    assert!(!declared(&loader.yul_code()));
+    loader.calldataload_scalar_le(0x40);
+    assert!(declared(&loader.yul_code()));
+    assemble_yul(&loader.yul_code()).unwrap();
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    loader.calldataload_ec_point_compressed_le(0);
+    assert!(declared(&loader.yul_code()));
+    assemble_yul(&loader.yul_code()).unwrap();
+}
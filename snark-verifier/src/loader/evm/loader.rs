@@ -1,23 +1,29 @@
+#[cfg(any(test, feature = "evm_gas_profiling"))]
+use crate::loader::evm::{util::executor::Log, Address, ExecutorBuilder, GasProfile, GasSection};
 use crate::{
     loader::{
         evm::{
             code::{Precompiled, YulCode},
-            fe_to_u256, modulus, u256_to_fe, U256, U512,
+            fe_to_u256, fn_selector, modulus, u256_to_fe,
+            util::CalldataPtr,
+            CheckKind, CheckSite, EvmVerifyingKey, CHECK_FAILURE_SIGNATURE, U256, U512,
         },
         EcPointLoader, LoadedEcPoint, LoadedScalar, Loader, ScalarLoader,
     },
     util::{
-        arithmetic::{CurveAffine, FieldOps, PrimeField},
+        arithmetic::{CurveAffine, FieldExt, FieldOps, PrimeField},
+        hash::OptimizedPoseidonSpec,
         Itertools,
     },
+    Error,
 };
 use hex;
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::{self, Debug},
     iter,
-    ops::{Add, AddAssign, DerefMut, Mul, MulAssign, Neg, Sub, SubAssign},
+    ops::{Add, AddAssign, DerefMut, Mul, MulAssign, Neg, Range, Sub, SubAssign},
     rc::Rc,
 };
 
@@ -37,6 +43,17 @@ impl<T: Debug> PartialEq for Value<T> {
 }
 
 impl<T: Debug> Value<T> {
+    fn ptrs(&self) -> Vec<usize> {
+        match self {
+            Value::Constant(_) => Vec::new(),
+            Value::Memory(ptr) => vec![*ptr],
+            Value::Negated(value) => value.ptrs(),
+            Value::Sum(lhs, rhs) | Value::Product(lhs, rhs) => {
+                iter::empty().chain(lhs.ptrs()).chain(rhs.ptrs()).collect()
+            }
+        }
+    }
+
     fn identifier(&self) -> String {
         match self {
             Value::Constant(_) | Value::Memory(_) => format!("{self:?}"),
@@ -54,9 +71,25 @@ pub struct EvmLoader {
     scalar_modulus: U256,
     code: RefCell<YulCode>,
     ptr: RefCell<usize>,
+    free: RefCell<BTreeMap<usize, usize>>,
+    live: RefCell<HashMap<usize, (usize, usize)>>,
     cache: RefCell<HashMap<String, usize>>,
-    #[cfg(test)]
-    gas_metering_ids: RefCell<Vec<String>>,
+    cache_deps: RefCell<HashMap<usize, Vec<String>>>,
+    deferral: RefCell<bool>,
+    vk: RefCell<Option<(usize, EvmVerifyingKey)>>,
+    poseidon_constants: RefCell<HashMap<Vec<U256>, usize>>,
+    abi_selector: RefCell<Option<[u8; 4]>>,
+    routes: RefCell<Vec<u32>>,
+    route: RefCell<Option<u32>>,
+    checks: RefCell<Option<Vec<CheckSite>>>,
+    instances: RefCell<Vec<(usize, usize)>>,
+    labels: RefCell<Vec<String>>,
+    #[cfg(any(test, feature = "evm_gas_profiling"))]
+    gas_metering: RefCell<bool>,
+    #[cfg(any(test, feature = "evm_gas_profiling"))]
+    gas_metering_ids: RefCell<Vec<(String, usize)>>,
+    #[cfg(any(test, feature = "evm_gas_profiling"))]
+    gas_metering_stack: RefCell<Vec<usize>>,
 }
 
 fn hex_encode_u256(value: &U256) -> String {
@@ -81,31 +114,275 @@ impl EvmLoader {
             scalar_modulus,
             code: RefCell::new(code),
             ptr: Default::default(),
+            free: Default::default(),
+            live: Default::default(),
             cache: Default::default(),
-            #[cfg(test)]
-            gas_metering_ids: RefCell::new(Vec::new()),
+            cache_deps: Default::default(),
+            deferral: RefCell::new(true),
+            vk: Default::default(),
+            poseidon_constants: Default::default(),
+            abi_selector: Default::default(),
+            routes: Default::default(),
+            route: Default::default(),
+            checks: Default::default(),
+            instances: Default::default(),
+            labels: Default::default(),
+            #[cfg(any(test, feature = "evm_gas_profiling"))]
+            gas_metering: Default::default(),
+            #[cfg(any(test, feature = "evm_gas_profiling"))]
+            gas_metering_ids: Default::default(),
+            #[cfg(any(test, feature = "evm_gas_profiling"))]
+            gas_metering_stack: Default::default(),
         })
     }
 
     /// Returns generated yul code.
     pub fn yul_code(self: &Rc<Self>) -> String {
-        let code = "
-            if not(success) { revert(0, 0) }
-            return(0, 0)"
+        self.yul_code_with_labels().0
+    }
+
+    /// Returns generated yul code with byte ranges of it labelled by
+    /// [`EvmLoader::start_label`], where nested labels are joined by `" > "`,
+    /// e.g. `pcs_verify > msm`.
+    pub fn yul_code_with_labels(self: &Rc<Self>) -> (String, Vec<(Range<usize>, String)>) {
+        assert!(self.route.borrow().is_none(), "route is not ended");
+        assert!(self.labels.borrow().is_empty(), "label is not ended");
+        let code = if !self.routes.borrow().is_empty() {
+            // none of the routes matches the identifier
+            "revert(0, 0)".to_string()
+        } else {
+            self.success_code()
+        };
+        self.code.borrow_mut().runtime_append(code);
+        self.code.borrow().code_with_labels(
+            hex_encode_u256(&self.base_modulus),
+            hex_encode_u256(&self.scalar_modulus),
+        )
+    }
+
+    /// Starts labelling code generated afterwards with `label`, nested in the
+    /// labels started before, until [`EvmLoader::end_label`] is called.
+    ///
+    /// The verifier phases started by
+    /// [`Loader::start_cost_metering`](crate::loader::Loader::start_cost_metering)
+    /// and operations like `msm` and `pairing` are labelled already.
+    pub fn start_label(&self, label: &str) {
+        self.labels.borrow_mut().push(label.to_string());
+        self.code.borrow_mut().set_label(self.labels.borrow().join(" > "));
+    }
+
+    /// Ends the label started last by [`EvmLoader::start_label`].
+    pub fn end_label(&self) {
+        self.labels.borrow_mut().pop().expect("no label started");
+        self.code.borrow_mut().set_label(self.labels.borrow().join(" > "));
+    }
+
+    /// Returns code ending the verification, which reverts if any check fails
+    /// and returns otherwise, with `true` if calldata is ABI encoded and with
+    /// keccak256 of the instances as big-endian words if not, so the caller
+    /// could bind the result to them.
+    fn success_code(self: &Rc<Self>) -> String {
+        let mut code = "
+            if not(success) { revert(0, 0) }"
             .to_string();
+        if self.abi_selector.borrow().is_some() {
+            code.push_str(
+                "
+            mstore(0, 1)
+            return(0, 0x20)",
+            );
+        } else {
+            let (ptr, size) = self.instances_chunk();
+            code.push_str(&format!(
+                "
+            mstore(0, keccak256({ptr:#x}, {size:#x}))
+            return(0, 0x20)"
+            ));
+        }
+        code
+    }
+
+    /// Makes the verifier a debug build, which reverts at the first failed
+    /// check with `CheckFailure(uint256 kind, uint256 site)` (see
+    /// [`CheckFailure`](super::CheckFailure)) instead of with nothing at the
+    /// end, where `site` indexes [`EvmLoader::check_sites`]. On success it
+    /// returns the same as the release build.
+    ///
+    /// It should be called before anything is loaded.
+    pub fn enable_revert_reasons(self: &Rc<Self>) {
+        assert!(self.checks.borrow().is_none(), "revert reasons are already enabled");
+        assert_eq!(self.ptr(), 0, "revert reasons should be enabled before loading");
+        *self.checks.borrow_mut() = Some(Vec::new());
+
+        let selector = format!("0x{}", hex::encode(fn_selector(CHECK_FAILURE_SIGNATURE)));
+        let code = format!(
+            "
+        function revert_with_reason(kind, site) {{
+            mstore(0x00, shl(224, {selector}))
+            mstore(0x04, kind)
+            mstore(0x24, site)
+            revert(0x00, 0x44)
+        }}"
+        );
         self.code.borrow_mut().runtime_append(code);
-        self.code
-            .borrow()
-            .code(hex_encode_u256(&self.base_modulus), hex_encode_u256(&self.scalar_modulus))
+    }
+
+    /// Returns sites of checks generated so far if revert reasons are enabled
+    /// by [`EvmLoader::enable_revert_reasons`], otherwise returns empty.
+    pub fn check_sites(&self) -> Vec<CheckSite> {
+        self.checks.borrow().clone().unwrap_or_default()
+    }
+
+    /// Returns code folding `condition` into `success`, or reverting with the
+    /// reason if revert reasons are enabled.
+    fn check(&self, kind: CheckKind, annotation: &str, condition: &str) -> String {
+        match self.checks.borrow_mut().as_mut() {
+            Some(checks) => {
+                let site = checks.len();
+                checks.push(CheckSite { kind, annotation: annotation.to_string() });
+                let kind = kind as usize;
+                format!("if not({condition}) {{ revert_with_reason({kind:#x}, {site:#x}) }}")
+            }
+            None => format!("success := and({condition}, success)"),
+        }
+    }
+
+    /// Records the memory of instances loaded from calldata, which are hashed
+    /// as the return data on success. Instances in consecutive words are
+    /// coalesced into a single chunk, so they are copied only if scattered.
+    pub(crate) fn record_instances<'a>(&self, instances: impl IntoIterator<Item = &'a Scalar>) {
+        let mut chunks = self.instances.borrow_mut();
+        for ptr in instances.into_iter().map(Scalar::ptr) {
+            match chunks.last_mut() {
+                Some((start, size)) if *start + *size == ptr => *size += 0x20,
+                _ => chunks.push((ptr, 0x20)),
+            }
+        }
+    }
+
+    /// Returns a memory chunk with all recorded instances, copying them into a
+    /// contiguous one if they are not yet.
+    fn instances_chunk(self: &Rc<Self>) -> (usize, usize) {
+        let instances = self.instances.borrow().clone();
+        match instances.as_slice() {
+            [] => (0, 0),
+            [(ptr, size)] => (*ptr, *size),
+            _ => {
+                let size = instances.iter().map(|(_, size)| size).sum();
+                let ptr = self.allocate(size);
+                let code = instances
+                    .iter()
+                    .flat_map(|(src_ptr, size)| (*src_ptr..src_ptr + size).step_by(0x20))
+                    .zip((ptr..).step_by(0x20))
+                    .map(|(src_ptr, ptr)| format!("mstore({ptr:#x}, mload({src_ptr:#x}))"))
+                    .join("\n");
+                self.code.borrow_mut().runtime_append(code);
+                (ptr, size)
+            }
+        }
     }
 
     /// Allocates memory chunk with given `size` and returns pointer.
+    ///
+    /// The chunk is always allocated at the end of used memory and never
+    /// recycled, so it's safe to refer to it by raw pointer (e.g. as transcript
+    /// buffer). Memory freed right before the end is given back to the end, so
+    /// the chunk might be dirty and should be written before being read.
     pub fn allocate(self: &Rc<Self>, size: usize) -> usize {
         let ptr = *self.ptr.borrow();
         *self.ptr.borrow_mut() += size;
         ptr
     }
 
+    /// Allocates memory chunk with given `size` by reusing freed memory if
+    /// possible, which is expected to be freed by [`EvmLoader::free`] or
+    /// [`EvmLoader::track`] after use.
+    fn allocate_temporary(self: &Rc<Self>, size: usize) -> usize {
+        let chunk = self
+            .free
+            .borrow()
+            .iter()
+            .find(|(_, chunk_size)| **chunk_size >= size)
+            .map(|(ptr, chunk_size)| (*ptr, *chunk_size));
+        match chunk {
+            Some((ptr, chunk_size)) => {
+                let mut free = self.free.borrow_mut();
+                free.remove(&ptr);
+                if chunk_size > size {
+                    free.insert(ptr + size, chunk_size - size);
+                }
+                ptr
+            }
+            None => self.allocate(size),
+        }
+    }
+
+    /// Allocates memory chunk for a [`Scalar`] or [`EcPoint`], which is freed
+    /// once the last one pointing to it is dropped.
+    pub(crate) fn allocate_value(self: &Rc<Self>, size: usize) -> usize {
+        let ptr = self.allocate_temporary(size);
+        self.track(ptr, size);
+        ptr
+    }
+
+    /// Tracks the memory chunk to be freed once the last [`Scalar`] or
+    /// [`EcPoint`] pointing to it is dropped.
+    fn track(&self, ptr: usize, size: usize) {
+        self.live.borrow_mut().insert(ptr, (size, 0));
+    }
+
+    fn retain<T: Debug>(&self, value: &Value<T>) {
+        let mut live = self.live.borrow_mut();
+        for ptr in value.ptrs() {
+            if let Some((_, count)) = live.get_mut(&ptr) {
+                *count += 1;
+            }
+        }
+    }
+
+    fn release<T: Debug>(&self, value: &Value<T>) {
+        for ptr in value.ptrs() {
+            let size = match self.live.borrow_mut().get_mut(&ptr) {
+                Some((size, count)) => {
+                    *count = count.checked_sub(1).expect("released more than retained");
+                    (*count == 0).then_some(*size)
+                }
+                None => None,
+            };
+            if let Some(size) = size {
+                self.live.borrow_mut().remove(&ptr);
+                self.free(ptr, size);
+            }
+        }
+    }
+
+    /// Frees memory chunk for reuse, and invalidates cached values depending
+    /// on it.
+    fn free(&self, ptr: usize, size: usize) {
+        for ptr in (ptr..ptr + size).step_by(0x20) {
+            self.invalidate(ptr);
+        }
+
+        let mut free = self.free.borrow_mut();
+        let (mut ptr, mut size) = (ptr, size);
+        if let Some((&prev_ptr, &prev_size)) = free.range(..ptr).next_back() {
+            if prev_ptr + prev_size == ptr {
+                free.remove(&prev_ptr);
+                ptr = prev_ptr;
+                size += prev_size;
+            }
+        }
+        if let Some(next_size) = free.remove(&(ptr + size)) {
+            size += next_size;
+        }
+        let mut end = self.ptr.borrow_mut();
+        if ptr + size == *end {
+            *end = ptr;
+        } else {
+            free.insert(ptr, size);
+        }
+    }
+
     pub(crate) fn ptr(&self) -> usize {
         *self.ptr.borrow()
     }
@@ -114,8 +391,12 @@ impl EvmLoader {
         self.code.borrow_mut()
     }
 
-    fn push(self: &Rc<Self>, scalar: &Scalar) -> String {
-        match scalar.value.clone() {
+    pub(crate) fn push(self: &Rc<Self>, scalar: &Scalar) -> String {
+        self.push_value(&scalar.value)
+    }
+
+    fn push_value(self: &Rc<Self>, value: &Value<U256>) -> String {
+        match value {
             Value::Constant(constant) => {
                 format!("{constant}")
             }
@@ -123,33 +404,48 @@ impl EvmLoader {
                 format!("mload({ptr:#x})")
             }
             Value::Negated(value) => {
-                let v = self.push(&self.scalar(*value));
+                let v = self.push_value(value);
                 format!("sub(f_q, {v})")
             }
             Value::Sum(lhs, rhs) => {
-                let lhs = self.push(&self.scalar(*lhs));
-                let rhs = self.push(&self.scalar(*rhs));
+                let lhs = self.push_value(lhs);
+                let rhs = self.push_value(rhs);
                 format!("addmod({lhs}, {rhs}, f_q)")
             }
             Value::Product(lhs, rhs) => {
-                let lhs = self.push(&self.scalar(*lhs));
-                let rhs = self.push(&self.scalar(*rhs));
+                let lhs = self.push_value(lhs);
+                let rhs = self.push_value(rhs);
                 format!("mulmod({lhs}, {rhs}, f_q)")
             }
         }
     }
 
-    /// Calldata load a field element.
-    pub fn calldataload_scalar(self: &Rc<Self>, offset: usize) -> Scalar {
+    /// Calldata load a field element and check it's canonical.
+    ///
+    /// A word not less than the scalar modulus makes the verification fail
+    /// instead of being reduced, otherwise the same proof would have several
+    /// encodings accepted, and instances bound by the caller (e.g. by their
+    /// hash) could differ from what is verified.
+    pub fn calldataload_scalar(self: &Rc<Self>, offset: impl Into<CalldataPtr>) -> Scalar {
+        let offset = offset.into();
         let ptr = self.allocate(0x20);
-        let code = format!("mstore({ptr:#x}, mod(calldataload({offset:#x}), f_q))");
+        let check = self.check(CheckKind::Scalar, "canonical", "lt(scalar, f_q)");
+        let code = format!(
+            "
+        {{
+            let scalar := calldataload({offset})
+            mstore({ptr:#x}, scalar)
+            {check}
+        }}"
+        );
         self.code.borrow_mut().runtime_append(code);
         self.scalar(Value::Memory(ptr))
     }
 
     /// Calldata load an elliptic curve point and validate it's on affine plane.
     /// Note that identity will cause the verification to fail.
-    pub fn calldataload_ec_point(self: &Rc<Self>, offset: usize) -> EcPoint {
+    pub fn calldataload_ec_point(self: &Rc<Self>, offset: impl Into<CalldataPtr>) -> EcPoint {
+        let offset = offset.into();
         let x_ptr = self.allocate(0x40);
         let y_ptr = x_ptr + 0x20;
         let x_cd_ptr = offset;
@@ -158,9 +454,9 @@ impl EvmLoader {
         let code = format!(
             "
         {{
-            let x := calldataload({x_cd_ptr:#x})
+            let x := calldataload({x_cd_ptr})
             mstore({x_ptr:#x}, x)
-            let y := calldataload({y_cd_ptr:#x})
+            let y := calldataload({y_cd_ptr})
             mstore({y_ptr:#x}, y)
             {validate_code}
         }}"
@@ -169,13 +465,97 @@ impl EvmLoader {
         self.ec_point(Value::Memory(x_ptr))
     }
 
+    /// Calldata load a field element in little-endian, which is how
+    /// `halo2_proofs` writes scalars into proofs, and check it's canonical.
+    pub fn calldataload_scalar_le(self: &Rc<Self>, offset: impl Into<CalldataPtr>) -> Scalar {
+        let offset = offset.into();
+        let ptr = self.allocate(0x20);
+        let check = self.check(CheckKind::Scalar, "canonical", "lt(scalar, f_q)");
+        let code = format!(
+            "
+        {{
+            let scalar := reverse_bytes(calldataload({offset}))
+            mstore({ptr:#x}, scalar)
+            {check}
+        }}"
+        );
+        self.code.borrow_mut().require_reverse_bytes();
+        self.code.borrow_mut().runtime_append(code);
+        self.scalar(Value::Memory(ptr))
+    }
+
+    /// Calldata load an elliptic curve point compressed by
+    /// [`compress_ec_point`](super::compress_ec_point), decompress it by
+    /// computing square root with `BigModExp` precompile and validate it's on
+    /// affine plane.
+    pub fn calldataload_ec_point_compressed(
+        self: &Rc<Self>,
+        offset: impl Into<CalldataPtr>,
+    ) -> EcPoint {
+        self.decompress_ec_point(format!("calldataload({})", offset.into()))
+    }
+
+    /// Calldata load an elliptic curve point compressed by `GroupEncoding` of
+    /// `halo2curves`, which is the same as
+    /// [`compress_ec_point`](super::compress_ec_point) but in little-endian,
+    /// then decompress and validate it as
+    /// [`EvmLoader::calldataload_ec_point_compressed`].
+    pub fn calldataload_ec_point_compressed_le(
+        self: &Rc<Self>,
+        offset: impl Into<CalldataPtr>,
+    ) -> EcPoint {
+        self.code.borrow_mut().require_reverse_bytes();
+        self.decompress_ec_point(format!("reverse_bytes(calldataload({}))", offset.into()))
+    }
+
+    fn decompress_ec_point(self: &Rc<Self>, x_with_sign: String) -> EcPoint {
+        let x_ptr = self.allocate(0x40);
+        let y_ptr = x_ptr + 0x20;
+        let ptr = self.allocate_temporary(0xc0);
+        let [base_len_ptr, exp_len_ptr, mod_len_ptr, base_ptr, exp_ptr, mod_ptr] =
+            [0x00, 0x20, 0x40, 0x60, 0x80, 0xa0].map(|offset| ptr + offset);
+        // square root exponent as p = 3 mod 4
+        let exp = hex_encode_u256(&((self.base_modulus + 1) / 4));
+        let a = Precompiled::BigModExp as usize;
+        let sqrt_check = self.check(
+            CheckKind::Precompile,
+            "BigModExp",
+            &format!("eq(staticcall(gas(), {a:#x}, {ptr:#x}, 0xc0, {ptr:#x}, 0x20), 1)"),
+        );
+        let validate_code = self.validate_ec_point();
+        let code = format!(
+            "
+        {{
+            let x_with_sign := {x_with_sign}
+            let x := bitand(x_with_sign, shr(1, bitnot(0)))
+            mstore({base_len_ptr:#x}, 0x20)
+            mstore({exp_len_ptr:#x}, 0x20)
+            mstore({mod_len_ptr:#x}, 0x20)
+            mstore({base_ptr:#x}, addmod(mulmod(mulmod(x, x, f_p), x, f_p), 3, f_p))
+            mstore({exp_ptr:#x}, {exp})
+            mstore({mod_ptr:#x}, f_p)
+            {sqrt_check}
+            let y := mload({ptr:#x})
+            if not(eq(bitand(y, 1), shr(255, x_with_sign))) {{
+                y := sub(f_p, y)
+            }}
+            mstore({x_ptr:#x}, x)
+            mstore({y_ptr:#x}, y)
+            {validate_code}
+        }}"
+        );
+        self.code.borrow_mut().runtime_append(code);
+        self.free(ptr, 0xc0);
+        self.ec_point(Value::Memory(x_ptr))
+    }
+
     /// Decode an elliptic curve point from limbs.
     pub fn ec_point_from_limbs<const LIMBS: usize, const BITS: usize>(
         self: &Rc<Self>,
         x_limbs: [&Scalar; LIMBS],
         y_limbs: [&Scalar; LIMBS],
     ) -> EcPoint {
-        let ptr = self.allocate(0x40);
+        let ptr = self.allocate_value(0x40);
         let mut code = String::new();
         for (idx, limb) in x_limbs.iter().enumerate() {
             let limb_i = self.push(limb);
@@ -210,12 +590,306 @@ impl EvmLoader {
         self.ec_point(Value::Memory(ptr))
     }
 
+    /// Checks the function selector of calldata is `selector`, and decodes the
+    /// heads of `num_dynamic` dynamic parameters (e.g. `bytes` or `uint256[]`)
+    /// following it in standard ABI encoding. Returns pointers to the start of
+    /// data of each parameter, which is right after its length.
+    ///
+    /// Once called, the verifier returns ABI-encoded `true` on success instead
+    /// of nothing, so it could be called as a normal contract function.
+    pub fn calldata_abi_head(
+        self: &Rc<Self>,
+        selector: [u8; 4],
+        num_dynamic: usize,
+    ) -> Vec<CalldataPtr> {
+        assert!(self.abi_selector.borrow().is_none(), "calldata is already decoded");
+        assert!(self.routes.borrow().is_empty(), "calldata is decoded by routes");
+        *self.abi_selector.borrow_mut() = Some(selector);
+
+        let ptr = self.allocate(num_dynamic * 0x20);
+        let selector = format!("0x{}", hex::encode(selector));
+        let condition = format!("eq(shr(224, calldataload(0)), {selector})");
+        let mut code = self.check(CheckKind::Calldata, "selector", &condition);
+        for idx in 0..num_dynamic {
+            let head_ptr = 0x04 + idx * 0x20;
+            let data_ptr = ptr + idx * 0x20;
+            let check = self.check(CheckKind::Calldata, "offset", "lt(offset, 0x100000000)");
+            code.push_str(&format!(
+                "
+        {{
+            let offset := calldataload({head_ptr:#x})
+            {check}
+            mstore({data_ptr:#x}, add(offset, 0x24))
+        }}"
+            ));
+        }
+        self.code.borrow_mut().runtime_append(code);
+        (0..num_dynamic).map(|idx| CalldataPtr::dynamic(ptr + idx * 0x20)).collect()
+    }
+
+    /// Checks length of the dynamic parameter starting at `data` decoded by
+    /// [`EvmLoader::calldata_abi_head`] is `len`, and its `size` bytes are all
+    /// within calldata.
+    pub fn calldata_abi_check_len(self: &Rc<Self>, data: CalldataPtr, len: usize, size: usize) {
+        assert!(data.is_dynamic() && data.offset() == 0);
+        let len_check = self.check(
+            CheckKind::Calldata,
+            "length",
+            &format!("eq(calldataload(sub(data, 0x20)), {len:#x})"),
+        );
+        let size_check = self.check(
+            CheckKind::Calldata,
+            "size",
+            &format!("not(gt(add(data, {size:#x}), calldatasize()))"),
+        );
+        let code = format!(
+            "
+        {{
+            let data := {data}
+            {len_check}
+            {size_check}
+        }}"
+        );
+        self.code.borrow_mut().runtime_append(code);
+    }
+
+    /// Starts a route of a router verifying several protocols in one
+    /// contract, which is taken only if the first 4 bytes of calldata are the
+    /// big-endian `id` (see [`encode_calldata_route`](super::encode_calldata_route)).
+    /// So calldata of the protocol verified by the route starts at `0x04`.
+    ///
+    /// Routes are mutually exclusive, so each starts with empty memory and
+    /// shares only the helper functions of the verifier. Every value loaded in
+    /// the previous route should be dropped before starting a new one.
+    pub fn begin_route(self: &Rc<Self>, id: u32) {
+        assert!(self.route.borrow().is_none(), "route is not ended");
+        assert!(!self.routes.borrow().contains(&id), "route {id:#x} already exists");
+        assert!(self.abi_selector.borrow().is_none(), "calldata is already decoded");
+        assert!(self.live.borrow().is_empty(), "values of previous route are still alive");
+
+        *self.ptr.borrow_mut() = 0;
+        self.free.borrow_mut().clear();
+        self.cache.borrow_mut().clear();
+        self.cache_deps.borrow_mut().clear();
+        self.poseidon_constants.borrow_mut().clear();
+        self.instances.borrow_mut().clear();
+        *self.vk.borrow_mut() = None;
+
+        self.routes.borrow_mut().push(id);
+        *self.route.borrow_mut() = Some(id);
+        let code = format!("if eq(shr(224, calldataload(0)), {id:#x}) {{");
+        self.code.borrow_mut().runtime_append(code);
+        self.start_label(&format!("route {id:#x}"));
+    }
+
+    /// Ends the route started by [`EvmLoader::begin_route`], which returns if
+    /// all checks in it pass and reverts otherwise.
+    pub fn end_route(self: &Rc<Self>) {
+        assert!(self.route.borrow().is_some(), "no route started");
+        self.end_label();
+        let code = format!("{}\n}}", self.success_code());
+        self.code.borrow_mut().runtime_append(code);
+        *self.route.borrow_mut() = None;
+    }
+
+    /// Checks calldata ends at `end`, so nothing could be appended to it.
+    pub fn calldata_check_end(self: &Rc<Self>, end: CalldataPtr) {
+        let code = self.check(CheckKind::Calldata, "size", &format!("eq(calldatasize(), {end})"));
+        self.code.borrow_mut().runtime_append(code);
+    }
+
+    /// Copies `transcript_initial_state` of the [`EvmVerifyingKey`] appended to
+    /// the code of the verifying key contract into given `ptr`, which is
+    /// usually the one pre-allocated by the transcript.
+    pub fn copy_transcript_initial_state(self: &Rc<Self>, vk: &EvmVerifyingKey, ptr: usize) {
+        let offset = EvmVerifyingKey::TRANSCRIPT_INITIAL_STATE_OFFSET;
+        let size = vk.size();
+        let code = format!(
+            "extcodecopy(address(), {ptr:#x}, add(sub(extcodesize(address()), {size:#x}), {offset:#x}), 0x20)"
+        );
+        self.code.borrow_mut().runtime_append(code);
+    }
+
+    /// Copies the [`EvmVerifyingKey`] appended to the code of the verifying key
+    /// contract into memory and checks its domain is the same as `vk`'s, since
+    /// the verifier is unrolled for the domain. Only the domain and the number
+    /// of preprocessed commitments of `vk` are used, so the generated code is
+    /// shared by every verifying key of the same shape.
+    ///
+    /// After that, [`EvmLoader::verifying_key_preprocessed`] returns the
+    /// preprocessed commitments in memory, and doing pairing with `vk.g2` and
+    /// `vk.minus_s_g2` reads them from memory instead of inlining them.
+    pub fn load_verifying_key(self: &Rc<Self>, vk: &EvmVerifyingKey) {
+        assert!(self.vk.borrow().is_none(), "verifying key is already loaded");
+
+        let size = vk.size();
+        let ptr = self.allocate(size);
+        let n_ptr = ptr + EvmVerifyingKey::N_OFFSET;
+        let omega_ptr = ptr + EvmVerifyingKey::OMEGA_OFFSET;
+        let n = hex_encode_u256(&vk.n);
+        let omega = hex_encode_u256(&vk.omega);
+        let n_check =
+            self.check(CheckKind::VerifyingKey, "n", &format!("eq(mload({n_ptr:#x}), {n})"));
+        let omega_check = self.check(
+            CheckKind::VerifyingKey,
+            "omega",
+            &format!("eq(mload({omega_ptr:#x}), {omega})"),
+        );
+        let code = format!(
+            "
+        {{
+            extcodecopy(address(), {ptr:#x}, sub(extcodesize(address()), {size:#x}), {size:#x})
+            {n_check}
+            {omega_check}
+        }}"
+        );
+        self.code.borrow_mut().runtime_append(code);
+        *self.vk.borrow_mut() = Some((ptr, vk.clone()));
+    }
+
+    /// Returns preprocessed commitments of the [`EvmVerifyingKey`] loaded by
+    /// [`EvmLoader::load_verifying_key`], which are read from memory by index,
+    /// so they are never inlined even if equal to some other constant.
+    pub fn verifying_key_preprocessed(self: &Rc<Self>) -> Vec<EcPoint> {
+        let (ptr, num_preprocessed) = {
+            let vk = self.vk.borrow();
+            let (ptr, vk) = vk.as_ref().expect("verifying key is not loaded");
+            (*ptr, vk.preprocessed.len())
+        };
+        (0..num_preprocessed)
+            .map(|idx| {
+                let ptr = ptr + EvmVerifyingKey::PREPROCESSED_OFFSET + idx * 0x40;
+                self.ec_point(Value::Memory(ptr))
+            })
+            .collect()
+    }
+
+    fn vk_g2_ptr(&self, value: &(U256, U256, U256, U256)) -> Option<usize> {
+        let vk = self.vk.borrow();
+        let (ptr, vk) = vk.as_ref()?;
+        if &vk.g2 == value {
+            Some(ptr + EvmVerifyingKey::G2_OFFSET)
+        } else if &vk.minus_s_g2 == value {
+            Some(ptr + EvmVerifyingKey::MINUS_S_G2_OFFSET)
+        } else {
+            None
+        }
+    }
+
     fn validate_ec_point(self: &Rc<Self>) -> String {
-        "success := and(validate_ec_point(x, y), success)".to_string()
+        self.check(CheckKind::EcPoint, "on curve", "validate_ec_point(x, y)")
+    }
+
+    /// Simplifies `value` by folding constants, also across deferred operations
+    /// (e.g. `(a + 1) + 2` into `a + 3`), applying algebraic identities and
+    /// ordering operands of commutative operations, so equivalent expressions
+    /// share the same identifier and hit the same cache entry.
+    fn simplify(&self, value: Value<U256>) -> Value<U256> {
+        let modulus = U512::from(self.scalar_modulus);
+        let fold = |value: U512| Value::Constant((value % modulus).try_into().unwrap());
+        // constant goes last, otherwise order by identifier
+        let ordered = |lhs: Value<U256>, rhs: Value<U256>| {
+            let swap = match (&lhs, &rhs) {
+                (_, Value::Constant(_)) => false,
+                (Value::Constant(_), _) => true,
+                _ => lhs.identifier() > rhs.identifier(),
+            };
+            if swap {
+                (rhs, lhs)
+            } else {
+                (lhs, rhs)
+            }
+        };
+
+        match value {
+            Value::Constant(_) | Value::Memory(_) => value,
+            Value::Negated(value) => match self.simplify(*value) {
+                Value::Constant(constant) => fold(modulus - U512::from(constant)),
+                Value::Negated(value) => *value,
+                value => Value::Negated(Box::new(value)),
+            },
+            Value::Sum(lhs, rhs) => match ordered(self.simplify(*lhs), self.simplify(*rhs)) {
+                (Value::Constant(lhs), Value::Constant(rhs)) => {
+                    fold(U512::from(lhs) + U512::from(rhs))
+                }
+                (value, Value::Constant(constant)) if constant.is_zero() => value,
+                (Value::Sum(value, lhs), Value::Constant(rhs))
+                    if matches!(*lhs, Value::Constant(_)) =>
+                {
+                    let constant = self.simplify(Value::Sum(lhs, Box::new(Value::Constant(rhs))));
+                    self.simplify(Value::Sum(value, Box::new(constant)))
+                }
+                (lhs, Value::Negated(rhs)) | (Value::Negated(rhs), lhs) if lhs == *rhs => {
+                    Value::Constant(U256::zero())
+                }
+                (lhs, rhs) => Value::Sum(Box::new(lhs), Box::new(rhs)),
+            },
+            Value::Product(lhs, rhs) => match ordered(self.simplify(*lhs), self.simplify(*rhs)) {
+                (Value::Constant(lhs), Value::Constant(rhs)) => {
+                    fold(U512::from(lhs) * U512::from(rhs))
+                }
+                (_, Value::Constant(constant)) if constant.is_zero() => {
+                    Value::Constant(U256::zero())
+                }
+                (value, Value::Constant(constant)) if constant == U256::one() => value,
+                (Value::Product(value, lhs), Value::Constant(rhs))
+                    if matches!(*lhs, Value::Constant(_)) =>
+                {
+                    let constant =
+                        self.simplify(Value::Product(lhs, Box::new(Value::Constant(rhs))));
+                    self.simplify(Value::Product(value, Box::new(constant)))
+                }
+                (Value::Negated(value), Value::Constant(constant)) => {
+                    let constant = fold(modulus - U512::from(constant));
+                    self.simplify(Value::Product(value, Box::new(constant)))
+                }
+                (Value::Negated(lhs), Value::Negated(rhs)) => {
+                    self.simplify(Value::Product(lhs, rhs))
+                }
+                (lhs, rhs) => Value::Product(Box::new(lhs), Box::new(rhs)),
+            },
+        }
+    }
+
+    /// Invalidates cached expressions which are evaluated into `ptr` or
+    /// depend on the value in `ptr`.
+    fn invalidate(&self, ptr: usize) {
+        let mut cache = self.cache.borrow_mut();
+        for identifier in self.cache_deps.borrow_mut().remove(&ptr).into_iter().flatten() {
+            cache.remove(&identifier);
+        }
+    }
+
+    /// Returns whether `value` could be left as an expression instead of being
+    /// evaluated into memory, which is the case for a single operation with a
+    /// constant on a value in memory (e.g. `a + 1` or `-a * 2`). Its code is
+    /// inlined into whichever expression uses it, so chained operations with
+    /// constants fold together and the intermediate value is never stored.
+    fn is_deferrable(&self, value: &Value<U256>) -> bool {
+        let is_leaf = |value: &Value<U256>| match value {
+            Value::Constant(_) | Value::Memory(_) => true,
+            Value::Negated(value) => matches!(**value, Value::Memory(_)),
+            _ => false,
+        };
+        match value {
+            Value::Constant(_) | Value::Memory(_) => true,
+            Value::Negated(value) => is_leaf(value) || self.is_deferrable(value),
+            Value::Sum(lhs, rhs) | Value::Product(lhs, rhs) => {
+                *self.deferral.borrow() && is_leaf(lhs) && matches!(**rhs, Value::Constant(_))
+            }
+        }
+    }
+
+    /// Evaluates every operation into memory right away, to measure what
+    /// deferring saves.
+    #[cfg(test)]
+    fn disable_deferral(&self) {
+        *self.deferral.borrow_mut() = false;
     }
 
     pub(crate) fn scalar(self: &Rc<Self>, value: Value<U256>) -> Scalar {
-        let value = if matches!(value, Value::Constant(_) | Value::Memory(_) | Value::Negated(_)) {
+        let value = self.simplify(value);
+        let value = if self.is_deferrable(&value) {
             value
         } else {
             let identifier = value.identifier();
@@ -223,18 +897,24 @@ impl EvmLoader {
             let ptr = if let Some(ptr) = some_ptr {
                 ptr
             } else {
-                let v = self.push(&Scalar { loader: self.clone(), value });
-                let ptr = self.allocate(0x20);
+                let v = self.push_value(&value);
+                let ptr = self.allocate_value(0x20);
                 self.code.borrow_mut().runtime_append(format!("mstore({ptr:#x}, {v})"));
+                let mut cache_deps = self.cache_deps.borrow_mut();
+                for dep in value.ptrs().into_iter().chain(Some(ptr)) {
+                    cache_deps.entry(dep).or_default().push(identifier.clone());
+                }
                 self.cache.borrow_mut().insert(identifier, ptr);
                 ptr
             };
             Value::Memory(ptr)
         };
+        self.retain(&value);
         Scalar { loader: self.clone(), value }
     }
 
-    fn ec_point(self: &Rc<Self>, value: Value<(U256, U256)>) -> EcPoint {
+    pub(crate) fn ec_point(self: &Rc<Self>, value: Value<(U256, U256)>) -> EcPoint {
+        self.retain(&value);
         EcPoint { loader: self.clone(), value }
     }
 
@@ -247,6 +927,162 @@ impl EvmLoader {
         hash_ptr
     }
 
+    /// Performs `BLAKE2F` compression on the 213 bytes input at
+    /// `memory[ptr..ptr+0xd5]` and writes the new state into
+    /// `memory[rd_ptr..rd_ptr+0x40]`.
+    pub fn blake2f(self: &Rc<Self>, ptr: usize, rd_ptr: usize) {
+        self.staticcall(Precompiled::Blake2f, ptr, rd_ptr);
+    }
+
+    /// Absorbs `inputs` into `state` with padding as
+    /// [`Poseidon`](crate::util::hash::Poseidon) does, then performs the
+    /// permutation of `spec` and returns the new state.
+    ///
+    /// The permutation is a Yul function looping over the optimized round
+    /// constants of `spec` in memory. It applies the dense MDS matrix in every
+    /// round, which is equivalent to the sparse matrices used natively but
+    /// keeps the constants small, at the cost of more multiplications in
+    /// partial rounds.
+    pub fn poseidon_permutation<F, const T: usize, const RATE: usize>(
+        self: &Rc<Self>,
+        spec: &OptimizedPoseidonSpec<F, T, RATE>,
+        state: &[Scalar; T],
+        inputs: &[Scalar],
+    ) -> [Scalar; T]
+    where
+        F: FieldExt + PrimeField<Repr = [u8; 0x20]>,
+    {
+        assert!(inputs.len() <= RATE);
+
+        let [constants_ptr, mds_ptr, scratch_ptr] = self.poseidon_constants(spec);
+        let (r_f_half, r_p) = (spec.r_f / 2, spec.constants.partial.len());
+        let ptr = self.allocate_temporary(T * 0x20);
+        let code = state
+            .iter()
+            .enumerate()
+            .map(|(idx, state)| {
+                let state = self.push(state);
+                let value = match idx.checked_sub(1) {
+                    Some(idx) if idx < inputs.len() => {
+                        let input = self.push(&inputs[idx]);
+                        format!("addmod({state}, {input}, f_q)")
+                    }
+                    Some(idx) if idx == inputs.len() => format!("addmod({state}, 1, f_q)"),
+                    _ => state,
+                };
+                format!("mstore({:#x}, {value})", ptr + idx * 0x20)
+            })
+            .chain([format!(
+                "poseidon_permutation({ptr:#x}, {T}, {r_f_half}, {r_p}, {constants_ptr:#x}, {mds_ptr:#x}, {scratch_ptr:#x})"
+            )])
+            .join("\n");
+        self.code.borrow_mut().runtime_append(code);
+
+        let state = [(); T].map(|_| self.allocate_value(0x20));
+        let code = state
+            .iter()
+            .enumerate()
+            .map(|(idx, dst_ptr)| format!("mstore({dst_ptr:#x}, mload({:#x}))", ptr + idx * 0x20))
+            .join("\n");
+        self.code.borrow_mut().runtime_append(code);
+        self.free(ptr, T * 0x20);
+
+        state.map(|ptr| self.scalar(Value::Memory(ptr)))
+    }
+
+    /// Stores round constants and MDS matrix of `spec` into memory if not yet,
+    /// and returns pointers of round constants, MDS matrix and scratch space for
+    /// the permutation.
+    fn poseidon_constants<F, const T: usize, const RATE: usize>(
+        self: &Rc<Self>,
+        spec: &OptimizedPoseidonSpec<F, T, RATE>,
+    ) -> [usize; 3]
+    where
+        F: FieldExt + PrimeField<Repr = [u8; 0x20]>,
+    {
+        let r_f_half = spec.r_f / 2;
+        assert_eq!(spec.constants.start.len(), r_f_half + 1);
+        assert_eq!(spec.constants.end.len(), r_f_half - 1);
+
+        // Round constants in the order of consumption, where the last full
+        // round has no constant.
+        let constants = iter::empty()
+            .chain(spec.constants.start.iter().flatten())
+            .chain(spec.constants.partial.iter())
+            .chain(spec.constants.end.iter().flatten())
+            .copied()
+            .chain(iter::repeat(F::zero()).take(T))
+            .chain(spec.mds_matrices.mds.0.iter().flatten().copied())
+            .map(fe_to_u256)
+            .collect_vec();
+        let mds_ptr = |ptr: usize| ptr + (constants.len() - T * T) * 0x20;
+        let scratch_ptr = |ptr: usize| ptr + constants.len() * 0x20;
+
+        if let Some(&ptr) = self.poseidon_constants.borrow().get(&constants) {
+            return [ptr, mds_ptr(ptr), scratch_ptr(ptr)];
+        }
+
+        if self.poseidon_constants.borrow().is_empty() {
+            let code = self.poseidon_permutation_function();
+            self.code.borrow_mut().runtime_append(code);
+        }
+
+        let ptr = self.allocate((constants.len() + T) * 0x20);
+        let code = constants
+            .iter()
+            .enumerate()
+            .map(|(idx, constant)| {
+                format!("mstore({:#x}, {})", ptr + idx * 0x20, hex_encode_u256(constant))
+            })
+            .join("\n");
+        self.code.borrow_mut().runtime_append(code);
+        self.poseidon_constants.borrow_mut().insert(constants, ptr);
+
+        [ptr, mds_ptr(ptr), scratch_ptr(ptr)]
+    }
+
+    fn poseidon_permutation_function(&self) -> String {
+        let scalar_modulus = hex_encode_u256(&self.scalar_modulus);
+        format!(
+            "
+        function poseidon_permutation(state, t, r_f_half, r_p, constants, mds, scratch) {{
+            let q := {scalar_modulus}
+            let state_end := add(state, mul(t, 0x20))
+            for {{ let ptr := state }} lt(ptr, state_end) {{ ptr := add(ptr, 0x20) }} {{
+                mstore(ptr, addmod(mload(ptr), mload(constants), q))
+                constants := add(constants, 0x20)
+            }}
+            let rounds := add(add(r_f_half, r_f_half), r_p)
+            for {{ let round := 0 }} lt(round, rounds) {{ round := add(round, 1) }} {{
+                let sbox_end := state_end
+                if and(not(lt(round, r_f_half)), lt(round, add(r_f_half, r_p))) {{
+                    sbox_end := add(state, 0x20)
+                }}
+                for {{ let ptr := state }} lt(ptr, sbox_end) {{ ptr := add(ptr, 0x20) }} {{
+                    let x := mload(ptr)
+                    let x_square := mulmod(x, x, q)
+                    let x_pow_5 := mulmod(mulmod(x_square, x_square, q), x, q)
+                    mstore(ptr, addmod(x_pow_5, mload(constants), q))
+                    constants := add(constants, 0x20)
+                }}
+                let m := mds
+                let scratch_end := add(scratch, mul(t, 0x20))
+                for {{ let ptr := scratch }} lt(ptr, scratch_end) {{ ptr := add(ptr, 0x20) }} {{
+                    let acc := 0
+                    for {{ let s := state }} lt(s, state_end) {{ s := add(s, 0x20) }} {{
+                        acc := addmod(acc, mulmod(mload(m), mload(s), q), q)
+                        m := add(m, 0x20)
+                    }}
+                    mstore(ptr, acc)
+                }}
+                for {{ let i := 0 }} lt(i, mul(t, 0x20)) {{ i := add(i, 0x20) }} {{
+                    mstore(add(state, i), mload(add(scratch, i)))
+                }}
+            }}
+        }}"
+        )
+    }
+
     /// Copies a field element into given `ptr`.
     pub fn copy_scalar(self: &Rc<Self>, scalar: &Scalar, ptr: usize) {
         let scalar = self.push(scalar);
@@ -255,14 +1091,20 @@ impl EvmLoader {
 
     /// Allocates a new field element and copies the given value into it.
     pub fn dup_scalar(self: &Rc<Self>, scalar: &Scalar) -> Scalar {
-        let ptr = self.allocate(0x20);
+        let ptr = self.allocate_value(0x20);
         self.copy_scalar(scalar, ptr);
         self.scalar(Value::Memory(ptr))
     }
 
     /// Allocates a new elliptic curve point and copies the given value into it.
     pub fn dup_ec_point(self: &Rc<Self>, value: &EcPoint) -> EcPoint {
-        let ptr = self.allocate(0x40);
+        let ptr = self.allocate_value(0x40);
+        self.copy_ec_point(value, ptr);
+        self.ec_point(Value::Memory(ptr))
+    }
+
+    /// Copies an elliptic curve point into given `ptr`.
+    pub fn copy_ec_point(self: &Rc<Self>, value: &EcPoint, ptr: usize) {
         match value.value {
             Value::Constant((x, y)) => {
                 let x_ptr = ptr;
@@ -290,7 +1132,6 @@ impl EvmLoader {
                 unreachable!()
             }
         }
-        self.ec_point(Value::Memory(ptr))
     }
 
     fn staticcall(self: &Rc<Self>, precompile: Precompiled, cd_ptr: usize, rd_ptr: usize) {
@@ -299,15 +1140,19 @@ impl EvmLoader {
             Precompiled::Bn254Add => (0x80, 0x40),
             Precompiled::Bn254ScalarMul => (0x60, 0x40),
             Precompiled::Bn254Pairing => (0x180, 0x20),
+            Precompiled::Blake2f => (0xd5, 0x40),
         };
         let a = precompile as usize;
-        let code = format!("success := and(eq(staticcall(gas(), {a:#x}, {cd_ptr:#x}, {cd_len:#x}, {rd_ptr:#x}, {rd_len:#x}), 1), success)");
+        let condition = format!(
+            "eq(staticcall(gas(), {a:#x}, {cd_ptr:#x}, {cd_len:#x}, {rd_ptr:#x}, {rd_len:#x}), 1)"
+        );
+        let code = self.check(CheckKind::Precompile, &format!("{precompile:?}"), &condition);
         self.code.borrow_mut().runtime_append(code);
     }
 
     fn invert(self: &Rc<Self>, scalar: &Scalar) -> Scalar {
-        let rd_ptr = self.allocate(0x20);
-        let [cd_ptr, ..] = [
+        let ptr = self.allocate_temporary(0xc0);
+        for (idx, value) in [
             &self.scalar(Value::Constant(0x20.into())),
             &self.scalar(Value::Constant(0x20.into())),
             &self.scalar(Value::Constant(0x20.into())),
@@ -315,23 +1160,35 @@ impl EvmLoader {
             &self.scalar(Value::Constant(self.scalar_modulus - 2)),
             &self.scalar(Value::Constant(self.scalar_modulus)),
         ]
-        .map(|value| self.dup_scalar(value).ptr());
-        self.staticcall(Precompiled::BigModExp, cd_ptr, rd_ptr);
-        self.scalar(Value::Memory(rd_ptr))
+        .into_iter()
+        .enumerate()
+        {
+            self.copy_scalar(value, ptr + idx * 0x20);
+        }
+        self.staticcall(Precompiled::BigModExp, ptr, ptr);
+        self.free(ptr + 0x20, 0xa0);
+        self.track(ptr, 0x20);
+        self.scalar(Value::Memory(ptr))
     }
 
     fn ec_point_add(self: &Rc<Self>, lhs: &EcPoint, rhs: &EcPoint) -> EcPoint {
-        let rd_ptr = self.dup_ec_point(lhs).ptr();
-        self.dup_ec_point(rhs);
-        self.staticcall(Precompiled::Bn254Add, rd_ptr, rd_ptr);
-        self.ec_point(Value::Memory(rd_ptr))
+        let ptr = self.allocate_temporary(0x80);
+        self.copy_ec_point(lhs, ptr);
+        self.copy_ec_point(rhs, ptr + 0x40);
+        self.staticcall(Precompiled::Bn254Add, ptr, ptr);
+        self.free(ptr + 0x40, 0x40);
+        self.track(ptr, 0x40);
+        self.ec_point(Value::Memory(ptr))
     }
 
     fn ec_point_scalar_mul(self: &Rc<Self>, ec_point: &EcPoint, scalar: &Scalar) -> EcPoint {
-        let rd_ptr = self.dup_ec_point(ec_point).ptr();
-        self.dup_scalar(scalar);
-        self.staticcall(Precompiled::Bn254ScalarMul, rd_ptr, rd_ptr);
-        self.ec_point(Value::Memory(rd_ptr))
+        let ptr = self.allocate_temporary(0x60);
+        self.copy_ec_point(ec_point, ptr);
+        self.copy_scalar(scalar, ptr + 0x40);
+        self.staticcall(Precompiled::Bn254ScalarMul, ptr, ptr);
+        self.free(ptr + 0x40, 0x20);
+        self.track(ptr, 0x40);
+        self.ec_point(Value::Memory(ptr))
     }
 
     /// Performs pairing.
@@ -342,59 +1199,47 @@ impl EvmLoader {
         rhs: &EcPoint,
         minus_s_g2: (U256, U256, U256, U256),
     ) {
-        let rd_ptr = self.dup_ec_point(lhs).ptr();
-        self.allocate(0x80);
-        let g2_0 = hex_encode_u256(&g2.0);
-        let g2_0_ptr = rd_ptr + 0x40;
-        let g2_1 = hex_encode_u256(&g2.1);
-        let g2_1_ptr = rd_ptr + 0x60;
-        let g2_2 = hex_encode_u256(&g2.2);
-        let g2_2_ptr = rd_ptr + 0x80;
-        let g2_3 = hex_encode_u256(&g2.3);
-        let g2_3_ptr = rd_ptr + 0xa0;
-        let code = format!(
-            "mstore({g2_0_ptr:#x}, {g2_0})
-            mstore({g2_1_ptr:#x}, {g2_1})
-            mstore({g2_2_ptr:#x}, {g2_2})
-            mstore({g2_3_ptr:#x}, {g2_3})"
-        );
-        self.code.borrow_mut().runtime_append(code);
-        self.dup_ec_point(rhs);
-        self.allocate(0x80);
-        let minus_s_g2_0 = hex_encode_u256(&minus_s_g2.0);
-        let minus_s_g2_0_ptr = rd_ptr + 0x100;
-        let minus_s_g2_1 = hex_encode_u256(&minus_s_g2.1);
-        let minus_s_g2_1_ptr = rd_ptr + 0x120;
-        let minus_s_g2_2 = hex_encode_u256(&minus_s_g2.2);
-        let minus_s_g2_2_ptr = rd_ptr + 0x140;
-        let minus_s_g2_3 = hex_encode_u256(&minus_s_g2.3);
-        let minus_s_g2_3_ptr = rd_ptr + 0x160;
-        let code = format!(
-            "mstore({minus_s_g2_0_ptr:#x}, {minus_s_g2_0})
-            mstore({minus_s_g2_1_ptr:#x}, {minus_s_g2_1})
-            mstore({minus_s_g2_2_ptr:#x}, {minus_s_g2_2})
-            mstore({minus_s_g2_3_ptr:#x}, {minus_s_g2_3})"
-        );
+        self.start_label("pairing");
+        let ptr = self.allocate_temporary(0x180);
+        self.copy_ec_point(lhs, ptr);
+        self.copy_g2(&g2, ptr + 0x40);
+        self.copy_ec_point(rhs, ptr + 0xc0);
+        self.copy_g2(&minus_s_g2, ptr + 0x100);
+        self.staticcall(Precompiled::Bn254Pairing, ptr, ptr);
+        let code = self.check(CheckKind::Pairing, "pairing", &format!("eq(mload({ptr:#x}), 1)"));
         self.code.borrow_mut().runtime_append(code);
-        self.staticcall(Precompiled::Bn254Pairing, rd_ptr, rd_ptr);
-        let code = format!("success := and(eq(mload({rd_ptr:#x}), 1), success)");
+        self.free(ptr, 0x180);
+        self.end_label();
+    }
+
+    fn copy_g2(self: &Rc<Self>, value: &(U256, U256, U256, U256), ptr: usize) {
+        let ptrs = [ptr, ptr + 0x20, ptr + 0x40, ptr + 0x60];
+        let code = if let Some(src_ptr) = self.vk_g2_ptr(value) {
+            ptrs.iter()
+                .enumerate()
+                .map(|(idx, ptr)| {
+                    let src_ptr = src_ptr + idx * 0x20;
+                    format!("mstore({ptr:#x}, mload({src_ptr:#x}))")
+                })
+                .join("\n")
+        } else {
+            let (v0, v1, v2, v3) = value;
+            ptrs.iter()
+                .zip([v0, v1, v2, v3])
+                .map(|(ptr, value)| {
+                    let value = hex_encode_u256(value);
+                    format!("mstore({ptr:#x}, {value})")
+                })
+                .join("\n")
+        };
         self.code.borrow_mut().runtime_append(code);
     }
 
     fn add(self: &Rc<Self>, lhs: &Scalar, rhs: &Scalar) -> Scalar {
-        if let (Value::Constant(lhs), Value::Constant(rhs)) = (&lhs.value, &rhs.value) {
-            let out = (U512::from(lhs) + U512::from(rhs)) % U512::from(self.scalar_modulus);
-            return self.scalar(Value::Constant(out.try_into().unwrap()));
-        }
-
         self.scalar(Value::Sum(Box::new(lhs.value.clone()), Box::new(rhs.value.clone())))
     }
 
     fn sub(self: &Rc<Self>, lhs: &Scalar, rhs: &Scalar) -> Scalar {
-        if rhs.is_const() {
-            return self.add(lhs, &self.neg(rhs));
-        }
-
         self.scalar(Value::Sum(
             Box::new(lhs.value.clone()),
             Box::new(Value::Negated(Box::new(rhs.value.clone()))),
@@ -402,46 +1247,95 @@ impl EvmLoader {
     }
 
     fn mul(self: &Rc<Self>, lhs: &Scalar, rhs: &Scalar) -> Scalar {
-        if let (Value::Constant(lhs), Value::Constant(rhs)) = (&lhs.value, &rhs.value) {
-            let out = (U512::from(lhs) * U512::from(rhs)) % U512::from(self.scalar_modulus);
-            return self.scalar(Value::Constant(out.try_into().unwrap()));
-        }
-
         self.scalar(Value::Product(Box::new(lhs.value.clone()), Box::new(rhs.value.clone())))
     }
 
     fn neg(self: &Rc<Self>, scalar: &Scalar) -> Scalar {
-        if let Value::Constant(constant) = scalar.value {
-            return self.scalar(Value::Constant(self.scalar_modulus - constant));
-        }
-
         self.scalar(Value::Negated(Box::new(scalar.value.clone())))
     }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "evm_gas_profiling"))]
 impl EvmLoader {
+    /// Enables gas metering of each section started by
+    /// [`Loader::start_cost_metering`](crate::loader::Loader::start_cost_metering),
+    /// which is disabled by default. It has to be called before any section is
+    /// started, and is only available with feature `evm_gas_profiling`.
+    ///
+    /// Each metered section ends with a `LOG2`, which reverts when the verifier
+    /// is called by `STATICCALL`, so the generated verifier is only meant to be
+    /// profiled by [`EvmLoader::profile_gas`] and never to be deployed.
+    pub fn enable_gas_metering(self: &Rc<Self>) {
+        assert!(self.gas_metering_ids.borrow().is_empty(), "gas metering is enabled too late");
+        *self.gas_metering.borrow_mut() = true;
+    }
+
+    /// Returns whether gas metering is enabled by [`EvmLoader::enable_gas_metering`].
+    pub fn is_gas_metering_enabled(&self) -> bool {
+        *self.gas_metering.borrow()
+    }
+
     fn start_gas_metering(self: &Rc<Self>, identifier: &str) {
-        self.gas_metering_ids.borrow_mut().push(identifier.to_string());
-        let code = format!("let {identifier} := gas()");
+        if !self.is_gas_metering_enabled() {
+            return;
+        }
+        let idx = self.gas_metering_ids.borrow().len();
+        let depth = self.gas_metering_stack.borrow().len();
+        self.gas_metering_ids.borrow_mut().push((identifier.to_string(), depth));
+        self.gas_metering_stack.borrow_mut().push(idx);
+        let code = format!("let gas_metering_{idx} := gas()");
         self.code.borrow_mut().runtime_append(code);
     }
 
     fn end_gas_metering(self: &Rc<Self>) {
-        let code =
-            format!("log1(0, 0, sub({}, gas()))", self.gas_metering_ids.borrow().last().unwrap());
+        if !self.is_gas_metering_enabled() {
+            return;
+        }
+        let idx = self.gas_metering_stack.borrow_mut().pop().expect("no gas metering started");
+        let code = format!("log2(0, 0, {idx}, sub(gas_metering_{idx}, gas()))");
         self.code.borrow_mut().runtime_append(code);
     }
 
-    pub fn print_gas_metering(self: &Rc<Self>, costs: Vec<u64>) {
-        for (identifier, cost) in self.gas_metering_ids.borrow().iter().zip(costs) {
-            println!("{identifier}: {cost}");
+    fn gas_profile(self: &Rc<Self>, accepted: bool, gas_used: u64, logs: &[Log]) -> GasProfile {
+        assert!(self.is_gas_metering_enabled(), "gas metering is not enabled");
+        assert!(self.gas_metering_stack.borrow().is_empty(), "gas metering is not ended");
+
+        let mut costs = vec![None; self.gas_metering_ids.borrow().len()];
+        for log in logs {
+            if let [idx, cost] = log.topics.as_slice() {
+                let idx = U256::from_big_endian(idx.as_bytes()).as_usize();
+                costs[idx] = Some(U256::from_big_endian(cost.as_bytes()).as_u64());
+            }
         }
+        let sections = self
+            .gas_metering_ids
+            .borrow()
+            .iter()
+            .zip(costs)
+            .map(|((identifier, depth), gas)| GasSection {
+                identifier: identifier.clone(),
+                depth: *depth,
+                gas,
+            })
+            .collect();
+
+        GasProfile { accepted, gas_used, sections }
+    }
+
+    /// Deploys `deployment_code` compiled from [`EvmLoader::yul_code`], calls it
+    /// with `calldata` in the bundled executor, and returns [`GasProfile`] of
+    /// the call. Gas metering must be enabled by [`EvmLoader::enable_gas_metering`]
+    /// before generating the verifier.
+    pub fn profile_gas(self: &Rc<Self>, deployment_code: Vec<u8>, calldata: Vec<u8>) -> GasProfile {
+        let caller = Address::from_low_u64_be(0xfe);
+        let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+        let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+        let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+        self.gas_profile(!result.reverted, result.gas_used, &result.logs)
     }
 }
 
 /// Elliptic curve point.
-#[derive(Clone)]
 pub struct EcPoint {
     loader: Rc<EvmLoader>,
     value: Value<(U256, U256)>,
@@ -464,6 +1358,19 @@ impl EcPoint {
     }
 }
 
+impl Clone for EcPoint {
+    fn clone(&self) -> Self {
+        self.loader.retain(&self.value);
+        Self { loader: self.loader.clone(), value: self.value.clone() }
+    }
+}
+
+impl Drop for EcPoint {
+    fn drop(&mut self) {
+        self.loader.release(&self.value);
+    }
+}
+
 impl Debug for EcPoint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("EcPoint").field("value", &self.value).finish()
@@ -489,7 +1396,6 @@ where
 }
 
 /// Field element.
-#[derive(Clone)]
 pub struct Scalar {
     loader: Rc<EvmLoader>,
     value: Value<U256>,
@@ -504,10 +1410,6 @@ impl Scalar {
         self.value.clone()
     }
 
-    pub(crate) fn is_const(&self) -> bool {
-        matches!(self.value, Value::Constant(_))
-    }
-
     pub(crate) fn ptr(&self) -> usize {
         match self.value {
             Value::Memory(ptr) => ptr,
@@ -516,6 +1418,19 @@ impl Scalar {
     }
 }
 
+impl Clone for Scalar {
+    fn clone(&self) -> Self {
+        self.loader.retain(&self.value);
+        Self { loader: self.loader.clone(), value: self.value.clone() }
+    }
+}
+
+impl Drop for Scalar {
+    fn drop(&mut self) {
+        self.loader.release(&self.value);
+    }
+}
+
 impl Debug for Scalar {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Scalar").field("value", &self.value).finish()
@@ -648,14 +1563,28 @@ where
         self.ec_point(Value::Constant((x, y)))
     }
 
-    fn ec_point_assert_eq(&self, _: &str, _: &EcPoint, _: &EcPoint) {
-        unimplemented!()
+    fn ec_point_assert_eq(&self, annotation: &str, lhs: &EcPoint, rhs: &EcPoint) {
+        if let (Value::Constant(lhs), Value::Constant(rhs)) = (&lhs.value, &rhs.value) {
+            assert_eq!(lhs, rhs, "{:?}", Error::AssertionFailure(annotation.to_string()));
+            return;
+        }
+
+        let [(lhs_x, lhs_y), (rhs_x, rhs_y)] = [lhs, rhs].map(|ec_point| match &ec_point.value {
+            Value::Constant((x, y)) => (hex_encode_u256(x), hex_encode_u256(y)),
+            Value::Memory(ptr) => (format!("mload({ptr:#x})"), format!("mload({:#x})", ptr + 0x20)),
+            _ => unreachable!(),
+        });
+        let condition = format!("and(eq({lhs_x}, {rhs_x}), eq({lhs_y}, {rhs_y}))");
+        let code = self.check(CheckKind::AssertEq, annotation, &condition);
+        self.code.borrow_mut().runtime_append(code);
     }
 
     fn multi_scalar_multiplication(
         pairs: &[(&<Self as ScalarLoader<C::Scalar>>::LoadedScalar, &EcPoint)],
     ) -> EcPoint {
-        pairs
+        let loader = &pairs.first().expect("pairs should not be empty").1.loader;
+        loader.start_label("msm");
+        let output = pairs
             .iter()
             .cloned()
             .map(|(scalar, ec_point)| match scalar.value {
@@ -663,7 +1592,9 @@ where
                 _ => ec_point.loader.ec_point_scalar_mul(ec_point, scalar),
             })
             .reduce(|acc, ec_point| acc.loader.ec_point_add(&acc, &ec_point))
-            .expect("pairs should not be empty")
+            .unwrap();
+        loader.end_label();
+        output
     }
 }
 
@@ -674,8 +1605,16 @@ impl<F: PrimeField<Repr = [u8; 0x20]>> ScalarLoader<F> for Rc<EvmLoader> {
         self.scalar(Value::Constant(fe_to_u256(*value)))
     }
 
-    fn assert_eq(&self, _: &str, _: &Scalar, _: &Scalar) {
-        unimplemented!()
+    fn assert_eq(&self, annotation: &str, lhs: &Scalar, rhs: &Scalar) {
+        if let (Value::Constant(lhs), Value::Constant(rhs)) = (&lhs.value, &rhs.value) {
+            assert_eq!(lhs, rhs, "{:?}", Error::AssertionFailure(annotation.to_string()));
+            return;
+        }
+
+        let lhs = self.push(lhs);
+        let rhs = self.push(rhs);
+        let code = self.check(CheckKind::AssertEq, annotation, &format!("eq({lhs}, {rhs})"));
+        self.code.borrow_mut().runtime_append(code);
     }
 
     fn sum_with_coeff_and_const(&self, values: &[(F, &Scalar)], constant: F) -> Scalar {
@@ -712,7 +1651,7 @@ impl<F: PrimeField<Repr = [u8; 0x20]>> ScalarLoader<F> for Rc<EvmLoader> {
             code.push_str(addend.as_str());
         }
 
-        let ptr = self.allocate(0x20);
+        let ptr = self.allocate_value(0x20);
         code.push_str(format!("mstore({ptr}, result)").as_str());
         self.code.borrow_mut().runtime_append(format!(
             "{{
@@ -777,7 +1716,7 @@ impl<F: PrimeField<Repr = [u8; 0x20]>> ScalarLoader<F> for Rc<EvmLoader> {
             code.push_str(addend.as_str());
         }
 
-        let ptr = self.allocate(0x20);
+        let ptr = self.allocate_value(0x20);
         code.push_str(format!("mstore({ptr}, result)").as_str());
         self.code.borrow_mut().runtime_append(format!(
             "{{
@@ -797,12 +1736,16 @@ impl<F: PrimeField<Repr = [u8; 0x20]>> ScalarLoader<F> for Rc<EvmLoader> {
     // 3. v_n <- values[n]
     // 4. values[n] <- products[n - 1] * inv (values[n]^{-1})
     // 5. inv <- v_n * inv
+    //
+    // Inverses are written into newly allocated memory instead of in place,
+    // since memory of values might be shared with other scalars or cached
+    // expressions.
     fn batch_invert<'a>(values: impl IntoIterator<Item = &'a mut Scalar>) {
-        let values = values.into_iter().collect_vec();
-        let loader = &values.first().unwrap().loader;
+        let mut values = values.into_iter().collect_vec();
+        let loader = values.first().unwrap().loader.clone();
         let products = iter::once(values[0].clone())
             .chain(
-                iter::repeat_with(|| loader.allocate(0x20))
+                iter::repeat_with(|| loader.allocate_value(0x20))
                     .map(|ptr| loader.scalar(Value::Memory(ptr)))
                     .take(values.len() - 1),
             )
@@ -829,34 +1772,42 @@ impl<F: PrimeField<Repr = [u8; 0x20]>> ScalarLoader<F> for Rc<EvmLoader> {
         }}"
         ));
 
-        let inv = loader.push(&loader.invert(products.last().unwrap()));
+        // keep the inverse alive until the code reading it is appended
+        let inv = loader.invert(products.last().unwrap());
+        let inverses = iter::repeat_with(|| loader.allocate_value(0x20))
+            .map(|ptr| loader.scalar(Value::Memory(ptr)))
+            .take(values.len())
+            .collect_vec();
 
         let mut code = format!(
             "
-            let inv := {inv}
+            let inv := {}
             let v
-        "
+        ",
+            loader.push(&inv)
         );
-        for (value, product) in
-            values.iter().rev().zip(products.iter().rev().skip(1).map(Some).chain(iter::once(None)))
+        for ((value, inverse), product) in values
+            .iter()
+            .rev()
+            .zip(inverses.iter().rev())
+            .zip(products.iter().rev().skip(1).map(Some).chain(iter::once(None)))
         {
+            let inv_ptr = inverse.ptr();
             if let Some(product) = product {
-                let val_ptr = value.ptr();
-                let prod_ptr = product.ptr();
                 let v = loader.push(value);
+                let prod = loader.push(product);
                 code.push_str(
                     format!(
                         "
                     v := {v}
-                    mstore({val_ptr}, mulmod(mload({prod_ptr:#x}), inv, f_q))
+                    mstore({inv_ptr:#x}, mulmod({prod}, inv, f_q))
                     inv := mulmod(v, inv, f_q)
                 "
                     )
                     .as_str(),
                 );
             } else {
-                let ptr = value.ptr();
-                code.push_str(format!("mstore({ptr:#x}, inv)\n").as_str());
+                code.push_str(format!("mstore({inv_ptr:#x}, inv)\n").as_str());
             }
         }
         loader.code.borrow_mut().runtime_append(format!(
@@ -864,6 +1815,10 @@ impl<F: PrimeField<Repr = [u8; 0x20]>> ScalarLoader<F> for Rc<EvmLoader> {
             {code}
         }}"
         ));
+
+        for (value, inverse) in values.iter_mut().zip(inverses) {
+            **value = inverse;
+        }
     }
 }
 
@@ -872,13 +1827,268 @@ where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 0x20]>,
 {
-    #[cfg(test)]
     fn start_cost_metering(&self, identifier: &str) {
-        self.start_gas_metering(identifier)
+        self.start_label(identifier);
+        #[cfg(any(test, feature = "evm_gas_profiling"))]
+        self.start_gas_metering(identifier);
     }
 
-    #[cfg(test)]
     fn end_cost_metering(&self) {
-        self.end_gas_metering()
+        #[cfg(any(test, feature = "evm_gas_profiling"))]
+        self.end_gas_metering();
+        self.end_label();
+    }
+}
+
+#[test]
+fn test_simplify() {
+    use crate::halo2_curves::bn256::{Fq, Fr};
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let a = loader.calldataload_scalar(0);
+    let b = loader.calldataload_scalar(0x20);
+    let zero = ScalarLoader::<Fr>::load_zero(&loader);
+    let one = ScalarLoader::<Fr>::load_one(&loader);
+
+    assert_eq!((a.clone() - &a).value(), zero.value());
+    assert_eq!((a.clone() + &zero).value(), a.value());
+    assert_eq!((a.clone() * &one).value(), a.value());
+    assert_eq!((a.clone() * &zero).value(), zero.value());
+    assert_eq!((-(-a.clone())).value(), a.value());
+    assert_eq!((-zero.clone()).value(), zero.value());
+    assert_eq!((a.clone() + &b).value(), (b.clone() + &a).value());
+    assert_eq!((a.clone() * &b).value(), (b * &a).value());
+}
+
+#[test]
+fn test_route() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr},
+        loader::evm::{encode_calldata_route, execute_yul},
+    };
+
+    // route 1 accepts x with x^2 = 4, and route 2 accepts x with x + 1 = 4
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let four = Fr::from(4);
+    loader.begin_route(1);
+    {
+        let x = loader.calldataload_scalar(0x04);
+        let four = ScalarLoader::<Fr>::load_const(&loader, &four);
+        ScalarLoader::<Fr>::assert_eq(&loader, "", &(x.clone() * &x), &four);
     }
+    loader.end_route();
+    loader.begin_route(2);
+    {
+        let x = loader.calldataload_scalar(0x04);
+        let one = ScalarLoader::<Fr>::load_one(&loader);
+        let four = ScalarLoader::<Fr>::load_const(&loader, &four);
+        ScalarLoader::<Fr>::assert_eq(&loader, "", &(x + &one), &four);
+    }
+    loader.end_route();
+    let (code, labels) = loader.yul_code_with_labels();
+
+    let call = |id: u32, x: u64| {
+        execute_yul(&code, &labels, encode_calldata_route(id, &[vec![Fr::from(x)]], &[])).0
+    };
+
+    assert!(call(1, 2));
+    assert!(!call(1, 3));
+    assert!(call(2, 3));
+    assert!(!call(2, 2));
+    assert!(!call(3, 2));
+    assert!(!call(3, 3));
+}
+
+#[test]
+fn test_record_instances() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr},
+        loader::evm::{assemble_yul, encode_calldata},
+    };
+    use sha3::{Digest, Keccak256};
+
+    // instances scattered in memory are hashed as if they were contiguous
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let a = loader.calldataload_scalar(0);
+    let b = loader.calldataload_scalar(0x20);
+    loader.allocate(0x20);
+    let c = loader.calldataload_scalar(0x40);
+    loader.record_instances([&a, &b, &c]);
+    assert_eq!(*loader.instances.borrow(), [(a.ptr(), 0x40), (c.ptr(), 0x20)]);
+    let deployment_code = assemble_yul(&loader.yul_code()).unwrap();
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let calldata = encode_calldata(&[vec![Fr::from(1), Fr::from(2), Fr::from(3)]], &[]);
+    let result = evm.call_raw(caller, verifier, calldata.clone().into(), 0.into());
+    assert!(!result.reverted);
+    assert_eq!(result.result.as_ref(), Keccak256::digest(&calldata).as_slice());
+}
+
+#[test]
+fn test_assert_eq() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine},
+        loader::evm::{assemble_yul, encode_calldata, fe_to_u256},
+    };
+
+    // accepts scalar x and point p with 2x = 6 and p + p = 2G
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let x = loader.calldataload_scalar(0);
+    let p = loader.calldataload_ec_point(0x20);
+    let six = ScalarLoader::<Fr>::load_const(&loader, &Fr::from(6));
+    ScalarLoader::<Fr>::assert_eq(&loader, "2x = 6", &(x.clone() + &x), &six);
+    let g = G1Affine::generator();
+    let two_g_affine: G1Affine = (g + g).into();
+    let two_g = EcPointLoader::<G1Affine>::ec_point_load_const(&loader, &two_g_affine);
+    let p_plus_p = loader.ec_point_add(&p, &p);
+    EcPointLoader::<G1Affine>::ec_point_assert_eq(&loader, "p + p = 2G", &p_plus_p, &two_g);
+    // equal constants are checked without emitting any code
+    let len = loader.yul_code().len();
+    ScalarLoader::<Fr>::assert_eq(&loader, "", &six, &six.clone());
+    EcPointLoader::<G1Affine>::ec_point_assert_eq(&loader, "", &two_g, &two_g.clone());
+    assert_eq!(loader.yul_code().len(), len);
+    let deployment_code = assemble_yul(&loader.yul_code()).unwrap();
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let call = |x: u64, p: G1Affine| {
+        let coordinates = p.coordinates().unwrap();
+        let p = [*coordinates.x(), *coordinates.y()]
+            .into_iter()
+            .flat_map(|coordinate| {
+                let mut bytes = [0; 32];
+                fe_to_u256(coordinate).to_big_endian(&mut bytes);
+                bytes
+            })
+            .collect::<Vec<_>>();
+        let calldata = encode_calldata(&[vec![Fr::from(x)]], &p);
+        !evm.call_raw(caller, verifier, calldata.into(), 0.into()).reverted
+    };
+
+    assert!(call(3, g));
+    assert!(!call(4, g));
+    assert!(!call(3, two_g_affine));
+}
+
+#[test]
+#[should_panic]
+fn test_assert_eq_constant() {
+    use crate::halo2_curves::bn256::{Fq, Fr};
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let one = ScalarLoader::<Fr>::load_one(&loader);
+    let two = ScalarLoader::<Fr>::load_const(&loader, &Fr::from(2));
+    ScalarLoader::<Fr>::assert_eq(&loader, "1 = 2", &one, &two);
+}
+
+#[test]
+fn test_memory_reuse() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr},
+        loader::evm::{assemble_yul, encode_calldata},
+    };
+
+    // computes x^(2^64) and checks it's one, either dropping each intermediate
+    // square right after use or keeping all of them alive
+    let gen = |keep_alive: bool| {
+        let loader = EvmLoader::new::<Fq, Fr>();
+        let x = loader.calldataload_scalar(0);
+        let mut squares = vec![x];
+        for _ in 0..64 {
+            let last = squares.last().unwrap();
+            let square = last.clone() * last;
+            if !keep_alive {
+                squares.clear();
+            }
+            squares.push(square);
+        }
+        let peak = loader.ptr();
+        let one = ScalarLoader::<Fr>::load_one(&loader);
+        ScalarLoader::<Fr>::assert_eq(&loader, "", squares.last().unwrap(), &one);
+        drop(squares);
+        // every square is freed once dropped
+        assert!(loader.live.borrow().is_empty());
+        (peak, assemble_yul(&loader.yul_code()).unwrap())
+    };
+    let (reused_peak, reused_code) = gen(false);
+    let (kept_peak, kept_code) = gen(true);
+    assert!(reused_peak <= 0x60, "{reused_peak:#x}");
+    assert!(kept_peak >= 64 * 0x20, "{kept_peak:#x}");
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let calldata = encode_calldata(&[vec![Fr::one()]], &[]);
+    let [reused_gas, kept_gas] = [reused_code, kept_code].map(|code| {
+        let verifier = evm.deploy(caller, code.into(), 0.into()).address.unwrap();
+        let result = evm.call_raw(caller, verifier, calldata.clone().into(), 0.into());
+        assert!(!result.reverted);
+        result.gas_used
+    });
+    // the memory expansion of 64 words costs at least 3 gas per word
+    assert!(reused_gas + 3 * 60 < kept_gas, "{reused_gas} vs {kept_gas}");
+}
+
+#[test]
+fn test_deferral() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr},
+        loader::evm::{assemble_yul, encode_calldata},
+    };
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let x = loader.calldataload_scalar(0);
+    let [one, two, three] =
+        [1, 2, 3].map(|value| ScalarLoader::<Fr>::load_const(&loader, &Fr::from(value)));
+    let len = loader.yul_code().len();
+    // folded across deferred operations without emitting any code
+    assert_eq!(((x.clone() + &one) + &two).value(), (x.clone() + &three).value());
+    assert_eq!(((x.clone() * &two) * &three).value(), (x.clone() * &(two * &three)).value());
+    assert_eq!(((x.clone() + &one) - &one).value(), x.value());
+    assert_eq!(loader.yul_code().len(), len);
+    // a deferred operation is evaluated once as part of a common subexpression
+    let lhs = (x.clone() + &one) * &x;
+    let rhs = x.clone() * &(x.clone() + &one);
+    assert_eq!(lhs.value(), rhs.value());
+
+    // evaluates (((x + 1) * 2 + 3) * x) repeatedly and checks the result
+    let gen = |deferral: bool| {
+        let loader = EvmLoader::new::<Fq, Fr>();
+        if !deferral {
+            loader.disable_deferral();
+        }
+        let x = loader.calldataload_scalar(0);
+        let [one, two, three] =
+            [1, 2, 3].map(|value| ScalarLoader::<Fr>::load_const(&loader, &Fr::from(value)));
+        let mut acc = x.clone();
+        for _ in 0..16 {
+            acc = ((acc + &one) * &two + &three) * &x;
+        }
+        let expected = {
+            let x = Fr::from(5);
+            (0..16).fold(x, |acc, _| ((acc + Fr::one()) * Fr::from(2) + Fr::from(3)) * x)
+        };
+        let expected = ScalarLoader::<Fr>::load_const(&loader, &expected);
+        ScalarLoader::<Fr>::assert_eq(&loader, "", &acc, &expected);
+        assemble_yul(&loader.yul_code()).unwrap()
+    };
+    let [deferred_code, eager_code] = [true, false].map(gen);
+    assert!(deferred_code.len() < eager_code.len());
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let [deferred_gas, eager_gas] = [deferred_code, eager_code].map(|code| {
+        let verifier = evm.deploy(caller, code.into(), 0.into()).address.unwrap();
+        let call = |x: u64| {
+            let calldata = encode_calldata(&[vec![Fr::from(x)]], &[]);
+            evm.call_raw(caller, verifier, calldata.into(), 0.into())
+        };
+        assert!(call(6).reverted);
+        let result = call(5);
+        assert!(!result.reverted);
+        result.gas_used
+    });
+    assert!(deferred_gas < eager_gas, "{deferred_gas} vs {eager_gas}");
 }
@@ -0,0 +1,103 @@
+use std::fmt::{self, Display};
+
+/// Gas used by a section of the verifier, which is started by
+/// [`Loader::start_cost_metering`](crate::loader::Loader::start_cost_metering)
+/// and ended by [`Loader::end_cost_metering`](crate::loader::Loader::end_cost_metering).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GasSection {
+    /// Identifier of the section, e.g. `read_proof`, `quotient_evaluation`,
+    /// `pcs_verify` or `decide`.
+    pub identifier: String,
+    /// Number of sections enclosing this one.
+    pub depth: usize,
+    /// Gas used by the section, or `None` if the execution didn't reach its
+    /// end.
+    pub gas: Option<u64>,
+}
+
+/// Gas profile of a call to the verifier.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GasProfile {
+    /// Whether the verifier accepted the call.
+    pub accepted: bool,
+    /// Total gas used by the call, including the intrinsic cost.
+    pub gas_used: u64,
+    /// Sections in the order they are started.
+    pub sections: Vec<GasSection>,
+}
+
+impl GasProfile {
+    /// Returns gas used by the first section with given `identifier`.
+    pub fn gas_of(&self, identifier: &str) -> Option<u64> {
+        self.sections.iter().find(|section| section.identifier == identifier)?.gas
+    }
+}
+
+impl Display for GasProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "accepted: {}", self.accepted)?;
+        writeln!(f, "gas_used: {}", self.gas_used)?;
+        for section in self.sections.iter() {
+            let indent = "  ".repeat(section.depth);
+            match section.gas {
+                Some(gas) => writeln!(f, "{indent}{}: {gas}", section.identifier)?,
+                None => writeln!(f, "{indent}{}: -", section.identifier)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_profile_gas() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine},
+        loader::{
+            evm::{assemble_yul, encode_calldata, EvmLoader},
+            Loader, ScalarLoader,
+        },
+    };
+
+    // accepts x with x^2 = 4, with section `square` nested in section `outer`
+    let gen = |gas_metering: bool| {
+        let loader = EvmLoader::new::<Fq, Fr>();
+        if gas_metering {
+            loader.enable_gas_metering();
+        }
+        Loader::<G1Affine>::start_cost_metering(&loader, "outer");
+        let x = loader.calldataload_scalar(0);
+        Loader::<G1Affine>::start_cost_metering(&loader, "square");
+        let x_square = x.clone() * &x;
+        Loader::<G1Affine>::end_cost_metering(&loader);
+        let four = ScalarLoader::<Fr>::load_const(&loader, &Fr::from(4));
+        ScalarLoader::<Fr>::assert_eq(&loader, "", &x_square, &four);
+        Loader::<G1Affine>::end_cost_metering(&loader);
+        let yul_code = loader.yul_code();
+        (loader, yul_code)
+    };
+
+    // gas metering is off by default
+    let (loader, yul_code) = gen(false);
+    assert!(!loader.is_gas_metering_enabled());
+    assert!(!yul_code.contains("log2"));
+
+    let (loader, yul_code) = gen(true);
+    assert!(yul_code.contains("log2"));
+    let deployment_code = assemble_yul(&yul_code).unwrap();
+
+    let profile =
+        loader.profile_gas(deployment_code.clone(), encode_calldata(&[vec![Fr::from(2)]], &[]));
+    assert!(profile.accepted);
+    let sections = profile
+        .sections
+        .iter()
+        .map(|section| (section.identifier.as_str(), section.depth))
+        .collect::<Vec<_>>();
+    assert_eq!(sections, [("outer", 0), ("square", 1)]);
+    let outer = profile.gas_of("outer").unwrap();
+    let square = profile.gas_of("square").unwrap();
+    assert!(0 < square && square < outer && outer < profile.gas_used);
+
+    let profile = loader.profile_gas(deployment_code, encode_calldata(&[vec![Fr::from(3)]], &[]));
+    assert!(!profile.accepted);
+}
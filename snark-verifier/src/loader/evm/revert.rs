@@ -0,0 +1,150 @@
+use crate::loader::evm::{fn_selector, U256};
+use std::fmt::{self, Display};
+
+/// Signature of the custom error a verifier with revert reasons (see
+/// [`EvmLoader::enable_revert_reasons`](super::EvmLoader::enable_revert_reasons))
+/// reverts with, where the arguments are [`CheckKind`] and index of the
+/// [`CheckSite`] that failed.
+pub const CHECK_FAILURE_SIGNATURE: &str = "CheckFailure(uint256,uint256)";
+
+/// Kind of check done by verifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CheckKind {
+    /// Calldata is malformed, e.g. wrong selector, offset or length.
+    Calldata = 1,
+    /// Scalar read from calldata is not canonical.
+    Scalar = 2,
+    /// Elliptic curve point is not on the affine plane.
+    EcPoint = 3,
+    /// Call to precompile fails.
+    Precompile = 4,
+    /// Pairing doesn't hold.
+    Pairing = 5,
+    /// Verifying key contract doesn't match the verifier.
+    VerifyingKey = 6,
+    /// Assertion of equality, e.g. in succinct verification.
+    AssertEq = 7,
+}
+
+impl CheckKind {
+    fn from_code(code: U256) -> Option<Self> {
+        [
+            Self::Calldata,
+            Self::Scalar,
+            Self::EcPoint,
+            Self::Precompile,
+            Self::Pairing,
+            Self::VerifyingKey,
+            Self::AssertEq,
+        ]
+        .into_iter()
+        .find(|kind| U256::from(*kind as usize) == code)
+    }
+}
+
+/// Site of a check in verifier, which is indexed in the order generated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckSite {
+    /// Kind of the check.
+    pub kind: CheckKind,
+    /// Description of the check.
+    pub annotation: String,
+}
+
+/// Failure of check decoded from revert data of verifier with revert reasons.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckFailure {
+    /// Kind of the check that failed.
+    pub kind: CheckKind,
+    /// Index of the [`CheckSite`] that failed.
+    pub site: usize,
+}
+
+impl CheckFailure {
+    /// Decodes revert data as `CheckFailure(uint256 kind, uint256 site)`, or
+    /// returns `None` if it's not.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() != 0x44 || data[..4] != fn_selector(CHECK_FAILURE_SIGNATURE) {
+            return None;
+        }
+        let kind = CheckKind::from_code(U256::from_big_endian(&data[0x04..0x24]))?;
+        let site = U256::from_big_endian(&data[0x24..0x44]);
+        (site <= U256::from(usize::MAX)).then(|| Self { kind, site: site.as_usize() })
+    }
+}
+
+impl Display for CheckFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} check #{} failed", self.kind, self.site)
+    }
+}
+
+#[test]
+fn test_revert_reasons() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine},
+        loader::{
+            evm::{assemble_yul, encode_calldata, modulus, Address, EvmLoader, ExecutorBuilder},
+            ScalarLoader,
+        },
+        system::halo2::transcript::evm::EvmTranscript,
+        util::{
+            hash::{Digest, Keccak256},
+            transcript::{Transcript, TranscriptRead},
+        },
+    };
+    use std::rc::Rc;
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    loader.enable_revert_reasons();
+    let mut transcript = EvmTranscript::<G1Affine, Rc<EvmLoader>, _, _>::new(&loader);
+    let x = transcript.load_instances(vec![1]).remove(0).remove(0);
+    transcript.common_scalar(&x).unwrap();
+    transcript.read_ec_point().unwrap();
+    let four = ScalarLoader::<Fr>::load_const(&loader, &Fr::from(4));
+    ScalarLoader::<Fr>::assert_eq(&loader, "x^2 = 4", &(x.clone() * &x), &four);
+    let deployment_code = assemble_yul(&loader.yul_code()).unwrap();
+    // the instance is checked to be canonical before anything else
+    assert_eq!(
+        loader.check_sites(),
+        [
+            CheckSite { kind: CheckKind::Scalar, annotation: "canonical".to_string() },
+            CheckSite { kind: CheckKind::EcPoint, annotation: "on curve".to_string() },
+            CheckSite { kind: CheckKind::AssertEq, annotation: "x^2 = 4".to_string() },
+        ]
+    );
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let word = |value: u64| {
+        let mut bytes = [0; 32];
+        U256::from(value).to_big_endian(&mut bytes);
+        bytes
+    };
+    let call = |x: u64, (ec_point_x, ec_point_y): (u64, u64)| {
+        let ec_point = [word(ec_point_x), word(ec_point_y)].concat();
+        let calldata = encode_calldata(&[vec![Fr::from(x)]], &ec_point);
+        let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+        (!result.reverted, result.result.to_vec())
+    };
+
+    assert_eq!(call(2, (1, 2)), (true, Keccak256::digest(word(2)).to_vec()));
+    let (accepted, data) = call(3, (1, 2));
+    assert!(!accepted);
+    let failure = CheckFailure { kind: CheckKind::AssertEq, site: 2 };
+    assert_eq!(CheckFailure::decode(&data), Some(failure));
+    let (accepted, data) = call(2, (1, 1));
+    assert!(!accepted);
+    let failure = CheckFailure { kind: CheckKind::EcPoint, site: 1 };
+    assert_eq!(CheckFailure::decode(&data), Some(failure));
+
+    // instance congruent to 2 but not canonical is rejected instead of reduced
+    let mut non_canonical = [0; 32];
+    (modulus::<Fr>() + 2).to_big_endian(&mut non_canonical);
+    let calldata = [non_canonical, word(1), word(2)].concat();
+    let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+    assert!(result.reverted);
+    let failure = CheckFailure { kind: CheckKind::Scalar, site: 0 };
+    assert_eq!(CheckFailure::decode(&result.result), Some(failure));
+}
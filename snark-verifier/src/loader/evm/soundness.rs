@@ -0,0 +1,332 @@
+//! Negative-soundness testing of verifiers generated by
+//! [`EvmLoader`](super::EvmLoader), which applies systematic mutations to
+//! calldata of a valid proof and checks the verifier rejects every one.
+
+use crate::{
+    loader::{
+        evm::{modulus, Address, ExecutorBuilder, U256},
+        native::NativeLoader,
+    },
+    system::halo2::transcript::evm::EvmTranscript,
+    util::{
+        arithmetic::{CurveAffine, PrimeField},
+        transcript::{Transcript, TranscriptRead},
+    },
+    verifier::SnarkVerifier,
+    Error,
+};
+
+/// Kind of item in calldata.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalldataItem {
+    /// Instance as a 32-byte big-endian word.
+    Instance,
+    /// Scalar of proof as a 32-byte big-endian word.
+    Scalar,
+    /// Elliptic curve point of proof as two 32-byte big-endian coordinates.
+    EcPoint,
+}
+
+impl CalldataItem {
+    fn size(&self) -> usize {
+        match self {
+            Self::Instance | Self::Scalar => 0x20,
+            Self::EcPoint => 0x40,
+        }
+    }
+}
+
+/// Mutation of calldata.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    /// Flips the lowest bit of the byte at given offset.
+    FlipByte(usize),
+    /// Swaps the words at given offsets.
+    SwapWords(usize, usize),
+    /// Adds `modulus` to the word at `offset`, which keeps the value modulo
+    /// `modulus` the same but makes it non-canonical.
+    AddModulus {
+        /// Offset of the word.
+        offset: usize,
+        /// Modulus to add.
+        modulus: U256,
+    },
+    /// Replaces the elliptic curve point at given offset with `(1, 1)`, which
+    /// is not on the curve.
+    OffCurve(usize),
+    /// Truncates calldata to given length.
+    Truncate(usize),
+    /// Appends given number of zero bytes.
+    Extend(usize),
+}
+
+impl Mutation {
+    /// Returns `calldata` with the mutation applied.
+    pub fn apply(&self, calldata: &[u8]) -> Vec<u8> {
+        let word =
+            |calldata: &[u8], offset: usize| U256::from_big_endian(&calldata[offset..][..0x20]);
+        let mut mutated = calldata.to_vec();
+        match *self {
+            Mutation::FlipByte(offset) => mutated[offset] ^= 1,
+            Mutation::SwapWords(lhs, rhs) => {
+                let (lhs_word, rhs_word) = (word(calldata, lhs), word(calldata, rhs));
+                lhs_word.to_big_endian(&mut mutated[rhs..][..0x20]);
+                rhs_word.to_big_endian(&mut mutated[lhs..][..0x20]);
+            }
+            Mutation::AddModulus { offset, modulus } => {
+                let value = word(calldata, offset).overflowing_add(modulus).0;
+                value.to_big_endian(&mut mutated[offset..][..0x20]);
+            }
+            Mutation::OffCurve(offset) => {
+                U256::one().to_big_endian(&mut mutated[offset..][..0x20]);
+                U256::one().to_big_endian(&mut mutated[offset + 0x20..][..0x20]);
+            }
+            Mutation::Truncate(len) => mutated.truncate(len),
+            Mutation::Extend(len) => mutated.resize(calldata.len() + len, 0),
+        }
+        mutated
+    }
+}
+
+/// Layout of calldata encoded by
+/// [`encode_calldata`](crate::loader::evm::encode_calldata), which is read by
+/// verifier in the order of instances and then proof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CalldataLayout {
+    /// Offsets and kinds of items in the order they are read.
+    pub items: Vec<(usize, CalldataItem)>,
+    /// Length of calldata read by verifier.
+    pub len: usize,
+    /// Modulus of base field.
+    pub base_modulus: U256,
+    /// Modulus of scalar field.
+    pub scalar_modulus: U256,
+}
+
+impl CalldataLayout {
+    /// Returns layout of calldata of `instances` and `proof`, by reading the
+    /// proof natively by `V` with [`EvmTranscript`], so it works with any
+    /// protocol `V` verifies (e.g. [`PlonkProtocol`] compiled by
+    /// [`compile`](crate::system::halo2::compile)).
+    ///
+    /// [`PlonkProtocol`]: crate::verifier::plonk::PlonkProtocol
+    pub fn new<C, V>(
+        vk: &V::VerifyingKey,
+        protocol: &V::Protocol,
+        instances: &[Vec<C::Scalar>],
+        proof: &[u8],
+    ) -> Result<Self, Error>
+    where
+        C: CurveAffine,
+        C::Base: PrimeField<Repr = [u8; 0x20]>,
+        C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+        V: SnarkVerifier<C, NativeLoader>,
+    {
+        let num_instance = instances.iter().map(Vec::len).sum::<usize>();
+        let mut transcript = LayoutTranscript {
+            inner: EvmTranscript::<C, NativeLoader, _, _>::new(proof),
+            offset: num_instance * 0x20,
+            items: (0..num_instance).map(|idx| (idx * 0x20, CalldataItem::Instance)).collect(),
+        };
+        V::read_proof(vk, protocol, instances, &mut transcript)?;
+
+        Ok(Self {
+            items: transcript.items,
+            len: transcript.offset,
+            base_modulus: modulus::<C::Base>(),
+            scalar_modulus: modulus::<C::Scalar>(),
+        })
+    }
+
+    /// Returns systematic mutations of calldata, which are
+    /// - flipping the lowest and highest byte of every word of every item,
+    /// - swapping adjacent instances,
+    /// - adding modulus to every word of every item,
+    /// - replacing every elliptic curve point with one off the curve,
+    /// - truncating the last byte or word, and
+    /// - appending a byte or word.
+    pub fn mutations(&self) -> Vec<Mutation> {
+        let words = |(offset, item): &(usize, CalldataItem)| {
+            (*offset..offset + item.size()).step_by(0x20).map(move |offset| (offset, *item))
+        };
+        let instances = self
+            .items
+            .iter()
+            .filter(|(_, item)| *item == CalldataItem::Instance)
+            .map(|(offset, _)| *offset)
+            .collect::<Vec<_>>();
+
+        let flips = self
+            .items
+            .iter()
+            .flat_map(words)
+            .flat_map(|(offset, _)| [offset + 0x1f, offset].map(Mutation::FlipByte));
+        let swaps = instances.windows(2).map(|window| Mutation::SwapWords(window[0], window[1]));
+        let non_canonicals = self.items.iter().flat_map(words).map(|(offset, item)| {
+            let modulus = match item {
+                CalldataItem::Instance | CalldataItem::Scalar => self.scalar_modulus,
+                CalldataItem::EcPoint => self.base_modulus,
+            };
+            Mutation::AddModulus { offset, modulus }
+        });
+        let off_curves = self
+            .items
+            .iter()
+            .filter(|(_, item)| *item == CalldataItem::EcPoint)
+            .map(|(offset, _)| Mutation::OffCurve(*offset));
+        let truncations = [1, 0x20]
+            .into_iter()
+            .filter(|len| *len <= self.len)
+            .map(|len| Mutation::Truncate(self.len - len));
+        let extensions = [1, 0x20].map(Mutation::Extend);
+
+        flips
+            .chain(swaps)
+            .chain(non_canonicals)
+            .chain(off_curves)
+            .chain(truncations)
+            .chain(extensions)
+            .collect()
+    }
+}
+
+/// Deploys `deployment_code` in the bundled executor, checks it accepts
+/// `calldata`, then returns the ones of `mutations` which it still accepts, so
+/// it's expected to be empty. Mutations which don't change `calldata` (e.g.
+/// swapping equal instances) are skipped.
+pub fn accepted_mutations(
+    deployment_code: Vec<u8>,
+    calldata: &[u8],
+    mutations: &[Mutation],
+) -> Vec<Mutation> {
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let accept =
+        |calldata: Vec<u8>| !evm.call_raw(caller, verifier, calldata.into(), 0.into()).reverted;
+
+    assert!(accept(calldata.to_vec()), "verifier rejects the unmutated calldata");
+    mutations
+        .iter()
+        .filter(|mutation| {
+            let mutated = mutation.apply(calldata);
+            mutated != calldata && accept(mutated)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Asserts the verifier deployed from `deployment_code` accepts `calldata` but
+/// rejects every mutation of it from [`CalldataLayout::mutations`].
+pub fn assert_rejects_mutations(
+    deployment_code: Vec<u8>,
+    calldata: &[u8],
+    layout: &CalldataLayout,
+) {
+    let accepted = accepted_mutations(deployment_code, calldata, &layout.mutations());
+    assert!(accepted.is_empty(), "verifier accepts mutated calldata: {accepted:?}");
+}
+
+/// Transcript recording offsets and kinds of items read from calldata.
+#[derive(Debug)]
+struct LayoutTranscript<'a, C: CurveAffine> {
+    inner: EvmTranscript<C, NativeLoader, &'a [u8], Vec<u8>>,
+    offset: usize,
+    items: Vec<(usize, CalldataItem)>,
+}
+
+impl<C> LayoutTranscript<'_, C>
+where
+    C: CurveAffine,
+{
+    fn record(&mut self, item: CalldataItem) {
+        self.items.push((self.offset, item));
+        self.offset += item.size();
+    }
+}
+
+impl<C> Transcript<C, NativeLoader> for LayoutTranscript<'_, C>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+{
+    fn loader(&self) -> &NativeLoader {
+        self.inner.loader()
+    }
+
+    fn squeeze_challenge(&mut self) -> C::Scalar {
+        self.inner.squeeze_challenge()
+    }
+
+    fn common_ec_point(&mut self, ec_point: &C) -> Result<(), Error> {
+        self.inner.common_ec_point(ec_point)
+    }
+
+    fn common_scalar(&mut self, scalar: &C::Scalar) -> Result<(), Error> {
+        self.inner.common_scalar(scalar)
+    }
+}
+
+impl<C> TranscriptRead<C, NativeLoader> for LayoutTranscript<'_, C>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+{
+    fn read_scalar(&mut self) -> Result<C::Scalar, Error> {
+        self.record(CalldataItem::Scalar);
+        self.inner.read_scalar()
+    }
+
+    fn read_ec_point(&mut self) -> Result<C, Error> {
+        self.record(CalldataItem::EcPoint);
+        self.inner.read_ec_point()
+    }
+}
+
+#[test]
+fn test_accepted_mutations() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine},
+        loader::{
+            evm::{assemble_yul, encode_calldata, EvmLoader},
+            ScalarLoader,
+        },
+    };
+    use std::rc::Rc;
+
+    // accepts instance x with x^2 = 4 and any point on curve as proof
+    let deployment_code = |check_proof_len: bool| {
+        let loader = EvmLoader::new::<Fq, Fr>();
+        let mut transcript = EvmTranscript::<G1Affine, Rc<EvmLoader>, _, _>::new(&loader);
+        let x = transcript.load_instances(vec![1]).remove(0).remove(0);
+        transcript.common_scalar(&x).unwrap();
+        transcript.read_ec_point().unwrap();
+        if check_proof_len {
+            transcript.check_proof_len();
+        }
+        let four = ScalarLoader::<Fr>::load_const(&loader, &Fr::from(4));
+        ScalarLoader::<Fr>::assert_eq(&loader, "", &(x.clone() * &x), &four);
+        assemble_yul(&loader.yul_code()).unwrap()
+    };
+    let generator = [1u64, 2]
+        .into_iter()
+        .flat_map(|coordinate| {
+            let mut bytes = [0; 32];
+            U256::from(coordinate).to_big_endian(&mut bytes);
+            bytes
+        })
+        .collect::<Vec<_>>();
+    let calldata = encode_calldata(&[vec![Fr::from(2)]], &generator);
+    let layout = CalldataLayout {
+        items: vec![(0, CalldataItem::Instance), (0x20, CalldataItem::EcPoint)],
+        len: 0x60,
+        base_modulus: modulus::<Fq>(),
+        scalar_modulus: modulus::<Fr>(),
+    };
+
+    assert_rejects_mutations(deployment_code(true), &calldata, &layout);
+    assert_eq!(
+        accepted_mutations(deployment_code(false), &calldata, &layout.mutations()),
+        [Mutation::Extend(1), Mutation::Extend(0x20)]
+    );
+}
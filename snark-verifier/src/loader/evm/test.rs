@@ -1,8 +1,12 @@
 use crate::{
-    loader::evm::{test::tui::Tui, Address, ExecutorBuilder, U256},
+    loader::evm::{
+        assemble_yul_with_source_map,
+        test::tui::{Source, Tui},
+        Address, ExecutorBuilder, SourceMap, U256,
+    },
     util::Itertools,
 };
-use std::env::var_os;
+use std::{env::var_os, fs, ops::Range, path::PathBuf};
 
 mod tui;
 
@@ -14,6 +18,35 @@ fn debug() -> bool {
 }
 
 pub fn execute(deployment_code: Vec<u8>, calldata: Vec<u8>) -> (bool, u64, Vec<u64>) {
+    execute_with_source(deployment_code, calldata, None)
+}
+
+/// Assembles Yul `code` with its `labels` returned by
+/// [`EvmLoader::yul_code_with_labels`] and executes it as [`execute`], but the
+/// debugger also shows the Yul line and label of each step.
+///
+/// The code is assembled by [`assemble_yul_with_source_map`] instead of `solc`,
+/// since [`SourceMap`] only maps the bytecode it assembles.
+///
+/// [`EvmLoader::yul_code_with_labels`]: crate::loader::evm::EvmLoader::yul_code_with_labels
+pub fn execute_yul(
+    code: &str,
+    labels: &[(Range<usize>, String)],
+    calldata: Vec<u8>,
+) -> (bool, u64, Vec<u64>) {
+    let (deployment_code, source_map) = assemble_yul_with_source_map(code).unwrap();
+    execute_with_source(
+        deployment_code,
+        calldata,
+        Some((code.to_string(), source_map, labels.to_vec())),
+    )
+}
+
+fn execute_with_source(
+    deployment_code: Vec<u8>,
+    calldata: Vec<u8>,
+    source: Option<(String, SourceMap, Vec<(Range<usize>, String)>)>,
+) -> (bool, u64, Vec<u64>) {
     assert!(
         deployment_code.len() <= 0x6000,
         "Contract size {} exceeds the limit 24576",
@@ -21,27 +54,40 @@ pub fn execute(deployment_code: Vec<u8>, calldata: Vec<u8>) -> (bool, u64, Vec<u
     );
 
     let debug = debug();
+    // Path without extension to export trace as `.json` and `.folded`
+    let trace = var_os("TRACE").map(PathBuf::from);
     let caller = Address::from_low_u64_be(0xfe);
 
     let mut evm = ExecutorBuilder::default()
         .with_gas_limit(u64::MAX.into())
-        .set_debugger(debug)
+        .set_debugger(debug || trace.is_some())
         .build();
 
-    let contract = evm
-        .deploy(caller, deployment_code.into(), 0.into())
-        .address
-        .unwrap();
+    let contract = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
     let result = evm.call_raw(caller, contract, calldata.into(), 0.into());
 
     let costs = result
         .logs
-        .into_iter()
-        .map(|log| U256::from_big_endian(log.topics[0].as_bytes()).as_u64())
+        .iter()
+        .map(|log| U256::from_big_endian(log.topics.last().unwrap().as_bytes()).as_u64())
         .collect_vec();
 
+    let source =
+        source.map(|(code, source_map, labels)| Source::new(contract, code, source_map, labels));
+    if let Some(trace) = trace {
+        fs::write(trace.with_extension("json"), result.trace_json().unwrap()).unwrap();
+        let folded = result.debug.as_ref().unwrap().folded_stacks(|address, pc| {
+            source.as_ref().map(|source| source.frames(address, pc)).unwrap_or_default()
+        });
+        fs::write(trace.with_extension("folded"), folded).unwrap();
+    }
+
     if debug {
-        Tui::new(result.debug.unwrap().flatten(0), 0).start();
+        let mut tui = Tui::new(result.debug.as_ref().unwrap().flatten(0), 0);
+        if let Some(source) = source {
+            tui = tui.with_source(source);
+        }
+        tui.start();
     }
 
     (!result.reverted, result.gas_used, costs)
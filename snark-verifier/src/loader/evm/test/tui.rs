@@ -2,7 +2,7 @@
 
 use crate::loader::evm::{
     util::executor::{CallKind, DebugStep},
-    Address,
+    Address, SourceMap,
 };
 use crossterm::{
     event::{
@@ -16,6 +16,7 @@ use revm::opcode;
 use std::{
     cmp::{max, min},
     io,
+    ops::Range,
     sync::mpsc,
     thread,
     time::{Duration, Instant},
@@ -30,11 +31,66 @@ use tui::{
     Terminal,
 };
 
+/// Yul source of the contract at `address`, to show the statement and label
+/// (see [`EvmLoader::start_label`](crate::loader::evm::EvmLoader::start_label))
+/// of the instruction being executed.
+pub struct Source {
+    address: Address,
+    code: String,
+    source_map: SourceMap,
+    labels: Vec<(Range<usize>, String)>,
+}
+
+impl Source {
+    pub fn new(
+        address: Address,
+        code: String,
+        source_map: SourceMap,
+        labels: Vec<(Range<usize>, String)>,
+    ) -> Self {
+        Self {
+            address,
+            code,
+            source_map,
+            labels,
+        }
+    }
+
+    /// Returns line number, trimmed line and label of the statement which
+    /// instruction at `pc` is generated from.
+    fn locate(&self, pc: usize) -> Option<(usize, &str, &str)> {
+        let offset = self.source_map.offset(pc)?;
+        let start = self.code[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+        let end = self.code[offset..]
+            .find('\n')
+            .map_or(self.code.len(), |idx| offset + idx);
+        let line_number = self.code[..offset].matches('\n').count() + 1;
+        let label = self
+            .labels
+            .iter()
+            .find(|(range, _)| range.contains(&offset))
+            .map_or("-", |(_, label)| label.as_str());
+        Some((line_number, self.code[start..end].trim(), label))
+    }
+
+    /// Returns nested labels of instruction at `pc` of the contract at
+    /// `address`, as frames of folded stacks.
+    pub fn frames(&self, address: Address, pc: usize) -> Vec<String> {
+        match self.locate(pc) {
+            Some((_, _, label)) if address == self.address && label != "-" => {
+                label.split(" > ").map(str::to_string).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
 pub struct Tui {
     debug_arena: Vec<(Address, Vec<DebugStep>, CallKind)>,
     terminal: Terminal<CrosstermBackend<io::Stdout>>,
     key_buffer: String,
     current_step: usize,
+    source: Option<Source>,
 }
 
 impl Tui {
@@ -50,9 +106,15 @@ impl Tui {
             terminal,
             key_buffer: String::new(),
             current_step,
+            source: None,
         }
     }
 
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.source = Some(source);
+        self
+    }
+
     pub fn start(mut self) {
         std::panic::set_hook(Box::new(|e| {
             disable_raw_mode().expect("Unable to disable raw mode");
@@ -301,6 +363,10 @@ impl Tui {
                 Interrupt::IntervalElapsed => {}
             }
             let current_step = self.current_step;
+            let source = self
+                .source
+                .as_ref()
+                .filter(|source| source.address == debug_call[draw_memory.inner_call_index].0);
             self.terminal
                 .draw(|f| {
                     Tui::draw_layout(
@@ -312,6 +378,7 @@ impl Tui {
                         &mut draw_memory,
                         stack_labels,
                         mem_utf,
+                        source,
                     )
                 })
                 .unwrap();
@@ -339,6 +406,7 @@ impl Tui {
         draw_memory: &mut DrawMemory,
         stack_labels: bool,
         mem_utf: bool,
+        source: Option<&Source>,
     ) {
         let total_size = f.size();
         if total_size.width < 225 {
@@ -351,6 +419,7 @@ impl Tui {
                 draw_memory,
                 stack_labels,
                 mem_utf,
+                source,
             );
         } else {
             Tui::square_layout(
@@ -362,6 +431,7 @@ impl Tui {
                 draw_memory,
                 stack_labels,
                 mem_utf,
+                source,
             );
         }
     }
@@ -375,6 +445,7 @@ impl Tui {
         draw_memory: &mut DrawMemory,
         stack_labels: bool,
         mem_utf: bool,
+        source: Option<&Source>,
     ) {
         let total_size = f.size();
         if let [app, footer] = Layout::default()
@@ -402,6 +473,7 @@ impl Tui {
                     opcode_list,
                     current_step,
                     draw_memory,
+                    source,
                     op_pane,
                 );
                 Tui::draw_stack(
@@ -437,6 +509,7 @@ impl Tui {
         draw_memory: &mut DrawMemory,
         stack_labels: bool,
         mem_utf: bool,
+        source: Option<&Source>,
     ) {
         let total_size = f.size();
 
@@ -463,6 +536,7 @@ impl Tui {
                         opcode_list,
                         current_step,
                         draw_memory,
+                        source,
                         left_pane,
                     );
                     Tui::draw_stack(
@@ -513,8 +587,25 @@ impl Tui {
         opcode_list: &[String],
         current_step: usize,
         draw_memory: &mut DrawMemory,
+        source: Option<&Source>,
         area: Rect,
     ) {
+        let area = match source {
+            Some(source) => {
+                if let [op_area, source_area] = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(4)].as_ref())
+                    .split(area)[..]
+                {
+                    let pc = debug_steps.get(current_step).map(|step| step.pc);
+                    Tui::draw_source(f, source, pc, source_area);
+                    op_area
+                } else {
+                    panic!("unable to create op list / source panes")
+                }
+            }
+            None => area,
+        };
         let block_source_code = Block::default()
             .title(format!(
                 "Address: {:?} | PC: {} | Gas used in call: {}",
@@ -604,6 +695,29 @@ impl Tui {
         f.render_widget(paragraph, area);
     }
 
+    fn draw_source<B: Backend>(f: &mut Frame<B>, source: &Source, pc: Option<usize>, area: Rect) {
+        let (title, text) = match pc.and_then(|pc| source.locate(pc)) {
+            Some((line_number, line, label)) => (
+                format!("Yul line: {line_number}"),
+                vec![
+                    Spans::from(vec![
+                        Span::styled("Phase: ", Style::default().add_modifier(Modifier::DIM)),
+                        Span::styled(label.to_string(), Style::default().fg(Color::Cyan)),
+                    ]),
+                    Spans::from(Span::styled(
+                        line.to_string(),
+                        Style::default().fg(Color::White),
+                    )),
+                ],
+            ),
+            None => ("Yul line: -".to_string(), Vec::new()),
+        };
+        let paragraph = Paragraph::new(text)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .wrap(Wrap { trim: true });
+        f.render_widget(paragraph, area);
+    }
+
     fn draw_stack<B: Backend>(
         f: &mut Frame<B>,
         debug_steps: &[DebugStep],
@@ -1,18 +1,32 @@
 use crate::{
     cost::Cost,
-    util::{arithmetic::PrimeField, Itertools},
+    util::{
+        arithmetic::{Coordinates, CurveAffine, Field, PrimeField},
+        hash::{Digest, Keccak256},
+        Itertools,
+    },
 };
 use std::{
+    fmt::{self, Display},
     io::Write,
     iter,
+    ops::{Add, AddAssign},
     process::{Command, Stdio},
 };
 
 pub use primitive_types::{H160 as Address, H256, U256, U512};
 
+mod assembler;
 pub(crate) mod executor;
+mod gas;
+mod trace;
 
-pub use executor::ExecutorBuilder;
+pub use assembler::{
+    assemble_yul, assemble_yul_with_source_map, estimate_yul_gas, AssemblerError, SourceMap,
+};
+pub use executor::{ExecutorBuilder, PrecompileUsage};
+pub use gas::{estimate_verifier_gas, GasEstimate, GasSchedule};
+pub use trace::StructLog;
 
 /// Memory chunk in EVM.
 #[derive(Debug)]
@@ -48,6 +62,68 @@ impl MemoryChunk {
     }
 }
 
+/// Pointer to calldata, which is either a constant offset, or a constant offset
+/// from a dynamic one decoded from calldata at runtime and kept in memory (e.g.
+/// start of an ABI-encoded `bytes`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CalldataPtr {
+    base: Option<usize>,
+    offset: usize,
+}
+
+impl CalldataPtr {
+    /// Returns pointer to the dynamic offset kept in memory at `base`.
+    pub(crate) fn dynamic(base: usize) -> Self {
+        Self { base: Some(base), offset: 0 }
+    }
+
+    /// Returns whether the pointer depends on a dynamic offset.
+    pub fn is_dynamic(&self) -> bool {
+        self.base.is_some()
+    }
+
+    /// Returns the constant part of the pointer.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the pointer without the constant part.
+    pub fn base(&self) -> Self {
+        Self { base: self.base, offset: 0 }
+    }
+}
+
+impl From<usize> for CalldataPtr {
+    fn from(offset: usize) -> Self {
+        Self { base: None, offset }
+    }
+}
+
+impl Add<usize> for CalldataPtr {
+    type Output = Self;
+
+    fn add(self, rhs: usize) -> Self {
+        Self { base: self.base, offset: self.offset + rhs }
+    }
+}
+
+impl AddAssign<usize> for CalldataPtr {
+    fn add_assign(&mut self, rhs: usize) {
+        self.offset += rhs;
+    }
+}
+
+/// Displays the pointer as yul expression.
+impl Display for CalldataPtr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.base, self.offset) {
+            (None, offset) => write!(f, "{offset:#x}"),
+            (Some(base), 0) => write!(f, "mload({base:#x})"),
+            (Some(base), offset) => write!(f, "add(mload({base:#x}), {offset:#x})"),
+        }
+    }
+}
+
 /// Convert a [`PrimeField`] into a [`U256`].
 /// Assuming fields that implement traits in crate `ff` always have
 /// little-endian representation.
@@ -93,7 +169,97 @@ where
         .collect()
 }
 
-/// Estimate gas cost with given [`Cost`].
+/// Encode instances and proof of several snarks into calldata, in the order
+/// they are verified by a verifier of several protocols.
+pub fn encode_calldata_multi<F>(snarks: &[(Vec<Vec<F>>, Vec<u8>)]) -> Vec<u8>
+where
+    F: PrimeField<Repr = [u8; 32]>,
+{
+    snarks.iter().flat_map(|(instances, proof)| encode_calldata(instances, proof)).collect()
+}
+
+/// Encode instances and proof into calldata of a router generated by
+/// [`EvmLoader::begin_route`](super::EvmLoader::begin_route), prefixed by the
+/// 4-byte big-endian identifier `id` of the route to verify them.
+pub fn encode_calldata_route<F>(id: u32, instances: &[Vec<F>], proof: &[u8]) -> Vec<u8>
+where
+    F: PrimeField<Repr = [u8; 32]>,
+{
+    iter::empty().chain(id.to_be_bytes()).chain(encode_calldata(instances, proof)).collect()
+}
+
+/// Signature of the function a verifier generated in ABI mode (see
+/// [`EvmTranscript::new_abi`](crate::system::halo2::transcript::evm::EvmTranscript::new_abi))
+/// is called as.
+pub const VERIFY_PROOF_SIGNATURE: &str = "verifyProof(bytes,uint256[])";
+
+/// Returns the 4-byte function selector of given `signature`.
+pub fn fn_selector(signature: &str) -> [u8; 4] {
+    Keccak256::digest(signature.as_bytes())[..4].try_into().unwrap()
+}
+
+/// Encode instances and proof into calldata of a call to
+/// `verifyProof(bytes proof, uint256[] instances)` in standard ABI encoding.
+pub fn encode_calldata_abi<F>(instances: &[Vec<F>], proof: &[u8]) -> Vec<u8>
+where
+    F: PrimeField<Repr = [u8; 32]>,
+{
+    let word = |value: usize| {
+        let mut bytes = [0; 32];
+        U256::from(value).to_big_endian(&mut bytes);
+        bytes
+    };
+    let padded_proof_len = (proof.len() + 0x1f) / 0x20 * 0x20;
+    let num_instance = instances.iter().map(Vec::len).sum::<usize>();
+    iter::empty()
+        .chain(fn_selector(VERIFY_PROOF_SIGNATURE))
+        .chain(word(0x40))
+        .chain(word(0x60 + padded_proof_len))
+        .chain(word(proof.len()))
+        .chain(proof.iter().cloned())
+        .chain(iter::repeat(0).take(padded_proof_len - proof.len()))
+        .chain(word(num_instance))
+        .chain(encode_calldata(instances, &[]))
+        .collect()
+}
+
+/// Compress an elliptic curve point into its x-coordinate in big-endian, with
+/// the most significant bit set when its y-coordinate is odd. Returns `None`
+/// for identity.
+///
+/// Proof written by a transcript with compressed points could be passed to
+/// [`encode_calldata`] as is.
+pub fn compress_ec_point<C: CurveAffine>(ec_point: &C) -> Option<<C::Base as PrimeField>::Repr> {
+    assert!(C::Base::NUM_BITS < 256);
+
+    let coordinates = Option::<Coordinates<C>>::from(ec_point.coordinates())?;
+    let mut x = coordinates.x().to_repr();
+    x.as_mut().reverse();
+    if coordinates.y().to_repr().as_ref()[0] & 1 == 1 {
+        x.as_mut()[0] |= 0x80;
+    }
+    Some(x)
+}
+
+/// Decompress an elliptic curve point compressed by [`compress_ec_point`].
+/// Returns `None` if the encoding is invalid.
+pub fn decompress_ec_point<C: CurveAffine>(mut bytes: <C::Base as PrimeField>::Repr) -> Option<C> {
+    let is_odd = bytes.as_ref()[0] >> 7 == 1;
+    bytes.as_mut()[0] &= 0x7f;
+    bytes.as_mut().reverse();
+
+    let x = Option::<C::Base>::from(C::Base::from_repr(bytes))?;
+    let y = Option::<C::Base>::from((x.square() * x + C::a() * x + C::b()).sqrt())?;
+    let y = if (y.to_repr().as_ref()[0] & 1 == 1) == is_odd { y } else { -y };
+    if is_odd && y == C::Base::zero() {
+        return None;
+    }
+    Option::from(C::from_xy(x, y))
+}
+
+/// Estimate gas cost with given [`Cost`] roughly, see
+/// [`estimate_verifier_gas`] for an estimation close to the actual cost.
+#[deprecated(note = "prices calldata and precompiles at flat rates, use `estimate_verifier_gas`")]
 pub fn estimate_gas(cost: Cost) -> usize {
     let proof_size = cost.num_commitment * 64 + (cost.num_evaluation + cost.num_instance) * 32;
 
@@ -104,14 +270,54 @@ pub fn estimate_gas(cost: Cost) -> usize {
     intrinsic_cost + calldata_cost + ec_operation_cost
 }
 
+/// Maximum size of runtime code of a contract in bytes by EIP-170.
+pub const MAX_RUNTIME_SIZE: usize = 0x6000;
+
+/// Error of generating a contract whose runtime code exceeds
+/// [`MAX_RUNTIME_SIZE`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvmCodeSizeError {
+    /// Size of the runtime code in bytes.
+    pub runtime_size: usize,
+    /// The deployment code, which is still deployable on chains not enforcing
+    /// EIP-170.
+    pub deployment_code: Vec<u8>,
+}
+
+impl Display for EvmCodeSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "runtime code of {} bytes exceeds the limit {MAX_RUNTIME_SIZE} of EIP-170",
+            self.runtime_size
+        )
+    }
+}
+
+impl std::error::Error for EvmCodeSizeError {}
+
 /// Compile given yul `code` into deployment bytecode.
+///
+/// It requires `solc` in `PATH`, see [`assemble_yul`] for an in-process
+/// alternative.
 pub fn compile_yul(code: &str) -> Vec<u8> {
+    solc(&["--bin", "--yul", "-"], code)
+}
+
+/// Compile given Solidity `code` of a single contract, e.g. one calling the
+/// verifier by [`solidity_abi`](crate::loader::evm::solidity_abi), into
+/// deployment bytecode, without constructor arguments.
+///
+/// It requires `solc` in `PATH`.
+pub fn compile_solidity(code: &str) -> Vec<u8> {
+    solc(&["--bin", "-"], code)
+}
+
+fn solc(args: &[&str], code: &str) -> Vec<u8> {
     let mut cmd = Command::new("solc")
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
-        .arg("--bin")
-        .arg("--yul")
-        .arg("-")
+        .args(args)
         .spawn()
         .unwrap();
     cmd.stdin.take().unwrap().write_all(code.as_bytes()).unwrap();
@@ -152,3 +358,39 @@ fn test_split_by_ascii_whitespace_2() {
     let split = split_by_ascii_whitespace(bytes);
     assert_eq!(split, [b"123456789abc"]);
 }
+
+#[test]
+fn test_encode_calldata_abi() {
+    use crate::halo2_curves::bn256::Fr;
+
+    assert_eq!(fn_selector("transfer(address,uint256)"), [0xa9, 0x05, 0x9c, 0xbb]);
+
+    let instances = vec![vec![Fr::from(1), Fr::from(2)], vec![Fr::from(3)]];
+    let proof = vec![0xff; 0x41];
+    let calldata = encode_calldata_abi(&instances, &proof);
+    let word = |offset: usize| U256::from_big_endian(&calldata[offset..offset + 0x20]);
+
+    assert_eq!(calldata[..4], fn_selector(VERIFY_PROOF_SIGNATURE));
+    assert_eq!(calldata.len(), 0x04 + 0x40 + 0x20 + 0x60 + 0x20 + 0x60);
+    let proof_offset = 0x04 + word(0x04).as_usize();
+    let instances_offset = 0x04 + word(0x24).as_usize();
+    assert_eq!(word(proof_offset).as_usize(), proof.len());
+    assert_eq!(calldata[proof_offset + 0x20..][..proof.len()], proof);
+    assert_eq!(word(instances_offset).as_usize(), 3);
+    assert_eq!(calldata[instances_offset + 0x20..], encode_calldata(&instances, &[]));
+}
+
+#[test]
+fn test_compress_ec_point() {
+    use crate::{
+        halo2_curves::bn256::{G1Affine, G1},
+        util::arithmetic::{Curve, Group},
+    };
+    use rand::rngs::OsRng;
+
+    for _ in 0..10 {
+        let ec_point = G1::random(OsRng).to_affine();
+        let compressed = compress_ec_point(&ec_point).unwrap();
+        assert_eq!(decompress_ec_point::<G1Affine>(compressed), Some(ec_point));
+    }
+}
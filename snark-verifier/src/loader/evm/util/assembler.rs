@@ -0,0 +1,1743 @@
+//! Assembler of Yul generated by [`EvmLoader`](crate::loader::evm::EvmLoader)
+//! into EVM bytecode, without the external `solc` binary.
+//!
+//! It supports the subset of Yul in the typed EVM dialect of `solc --yul` the
+//! loader emits, checks the types of `u256` and `bool` as `solc` does, and
+//! assembles it straightforwardly, without optimization.
+//! Variables live in stack, and the ones initialized with a literal and never
+//! assigned are inlined when they are too deep in stack.
+
+use crate::loader::evm::{
+    util::gas::{GasEstimate, GasSchedule},
+    Address, Precompiled, U256,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Display},
+};
+
+/// Error while assembling Yul into bytecode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AssemblerError {
+    /// Unexpected input at given byte offset of the source.
+    Syntax { offset: usize, message: String },
+    /// Identifier which is not a variable, function or builtin in scope.
+    UndefinedIdentifier(String),
+    /// Syntax or builtin that is not supported.
+    Unsupported(String),
+    /// Call with unexpected number of arguments or return values.
+    ArityMismatch(String),
+    /// Value of unexpected type in the call, assignment or condition of given
+    /// name.
+    TypeMismatch(String),
+    /// Variable too deep in stack to be reached by `DUP16` or `SWAP16`.
+    StackTooDeep(String),
+    /// Code too large to be addressed by 2-byte jump destinations.
+    CodeTooLarge(usize),
+}
+
+impl Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax { offset, message } => write!(f, "syntax error at {offset}: {message}"),
+            Self::UndefinedIdentifier(name) => write!(f, "undefined identifier `{name}`"),
+            Self::Unsupported(what) => write!(f, "unsupported {what}"),
+            Self::ArityMismatch(name) => write!(f, "arity mismatch of `{name}`"),
+            Self::TypeMismatch(name) => write!(f, "type mismatch of `{name}`"),
+            Self::StackTooDeep(name) => write!(f, "variable `{name}` is too deep in stack"),
+            Self::CodeTooLarge(size) => write!(f, "code size {size} exceeds 65535"),
+        }
+    }
+}
+
+impl std::error::Error for AssemblerError {}
+
+/// Assemble Yul `code` (e.g. from
+/// [`EvmLoader::yul_code`](crate::loader::evm::EvmLoader::yul_code)) into
+/// deployment bytecode, as [`compile_yul`](super::compile_yul) does but in
+/// process.
+///
+/// The bytecode behaves the same as the one compiled by `solc`, but costs more
+/// gas since it's not optimized.
+pub fn assemble_yul(code: &str) -> Result<Vec<u8>, AssemblerError> {
+    assemble_yul_with_source_map(code).map(|(code, _)| code)
+}
+
+/// Map from program counter of assembled code to byte offset in the Yul
+/// source of the statement the instruction is generated from.
+///
+/// It's only valid for the code returned by [`assemble_yul_with_source_map`]
+/// along with it, not for the code compiled from the same source by `solc`,
+/// whose optimizer reorders and inlines the statements.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap(BTreeMap<usize, usize>);
+
+impl SourceMap {
+    /// Returns byte offset of the statement the instruction at `pc` is
+    /// generated from.
+    pub fn offset(&self, pc: usize) -> Option<usize> {
+        self.0.range(..=pc).next_back().map(|(_, offset)| *offset)
+    }
+}
+
+/// Assemble Yul `code` as [`assemble_yul`] does, and also returns
+/// [`SourceMap`] of the runtime code, which is the first sub-object deployed
+/// by the deployment code.
+pub fn assemble_yul_with_source_map(code: &str) -> Result<(Vec<u8>, SourceMap), AssemblerError> {
+    let object = parse(code)?;
+    let (code, _, source_maps) = assemble_object(&object)?;
+    Ok((code, source_maps.into_iter().next().unwrap_or_default()))
+}
+
+/// Estimate gas cost of calling the runtime code of Yul `code` once assembled
+/// by [`assemble_yul`], priced by `schedule` without assembling it, where the
+/// runtime code is the first sub-object, or `code` itself if there is none.
+///
+/// The code is walked as the assembled one runs, with values computed only
+/// from literals (e.g. loop counters of the Poseidon permutation) tracked, so
+/// `for` runs as many iterations as its condition holds, and `if` is taken as
+/// its condition says. If the condition is unknown, the body of `if` is assumed
+/// to be taken unless it reverts, as checks of
+/// [`EvmLoader`](crate::loader::evm::EvmLoader) do when they fail, and the body
+/// of `for` is assumed to run once. Memory and input of precompiles are only
+/// tracked at known offsets. The costs of transaction and calldata are left
+/// zero.
+pub fn estimate_yul_gas(code: &str, schedule: &GasSchedule) -> Result<GasEstimate, AssemblerError> {
+    let object = parse(code)?;
+    let runtime = object.objects.first().unwrap_or(&object);
+    let mut estimator = GasEstimator {
+        schedule,
+        functions: Vec::new(),
+        vars: Vec::new(),
+        calls: Vec::new(),
+        memory: BTreeMap::new(),
+        memory_size: 0,
+        estimate: GasEstimate::default(),
+    };
+    estimator.block(&runtime.code)?;
+    let mut estimate = estimator.estimate;
+    estimate.memory = schedule.memory(estimator.memory_size);
+    Ok(estimate)
+}
+
+fn parse(code: &str) -> Result<Object, AssemblerError> {
+    let tokens = lex(code)?;
+    let mut parser = Parser { tokens, idx: 0, end: code.len() };
+    let object = parser.object()?;
+    if let Some((_, offset)) = parser.tokens.get(parser.idx) {
+        return Err(syntax_error(*offset, "trailing input"));
+    }
+    Ok(object)
+}
+
+fn syntax_error(offset: usize, message: impl Into<String>) -> AssemblerError {
+    AssemblerError::Syntax { offset, message: message.into() }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    Comma,
+    Colon,
+    Assign,
+    Arrow,
+    Ident(String),
+    Number(U256),
+    Str(String),
+}
+
+fn lex(code: &str) -> Result<Vec<(Token, usize)>, AssemblerError> {
+    let bytes = code.as_bytes();
+    let mut tokens = Vec::new();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let start = idx;
+        let byte = bytes[idx];
+        let token = match byte {
+            _ if byte.is_ascii_whitespace() => {
+                idx += 1;
+                continue;
+            }
+            b'/' if bytes.get(idx + 1) == Some(&b'/') => {
+                while idx < bytes.len() && bytes[idx] != b'\n' {
+                    idx += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(idx + 1) == Some(&b'*') => {
+                let len = code[idx + 2..]
+                    .find("*/")
+                    .ok_or_else(|| syntax_error(start, "unterminated comment"))?;
+                idx += len + 4;
+                continue;
+            }
+            b'{' => Token::LBrace,
+            b'}' => Token::RBrace,
+            b'(' => Token::LParen,
+            b')' => Token::RParen,
+            b',' => Token::Comma,
+            b':' if bytes.get(idx + 1) == Some(&b'=') => {
+                idx += 1;
+                Token::Assign
+            }
+            b':' => Token::Colon,
+            b'-' if bytes.get(idx + 1) == Some(&b'>') => {
+                idx += 1;
+                Token::Arrow
+            }
+            b'"' => {
+                let len = code[idx + 1..]
+                    .find('"')
+                    .ok_or_else(|| syntax_error(start, "unterminated string"))?;
+                idx += len + 1;
+                Token::Str(code[start + 1..idx].to_string())
+            }
+            b'0'..=b'9' => {
+                while idx + 1 < bytes.len() && bytes[idx + 1].is_ascii_alphanumeric() {
+                    idx += 1;
+                }
+                let literal = &code[start..=idx];
+                let number = match literal.strip_prefix("0x") {
+                    Some(hex) if !hex.is_empty() && hex.len() <= 64 => {
+                        U256::from_str_radix(hex, 16).ok()
+                    }
+                    Some(_) => None,
+                    None => U256::from_dec_str(literal).ok(),
+                };
+                Token::Number(
+                    number.ok_or_else(|| syntax_error(start, format!("invalid `{literal}`")))?,
+                )
+            }
+            _ if byte.is_ascii_alphabetic() || byte == b'_' || byte == b'$' => {
+                while idx + 1 < bytes.len()
+                    && (bytes[idx + 1].is_ascii_alphanumeric()
+                        || matches!(bytes[idx + 1], b'_' | b'$' | b'.'))
+                {
+                    idx += 1;
+                }
+                Token::Ident(code[start..=idx].to_string())
+            }
+            _ => return Err(syntax_error(start, format!("unexpected `{}`", byte as char))),
+        };
+        tokens.push((token, start));
+        idx += 1;
+    }
+    Ok(tokens)
+}
+
+/// Type of values in the typed EVM dialect, where `bool` is represented as
+/// `0` or `1` in stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Type {
+    U256,
+    Bool,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(U256, Type),
+    Str(String),
+    Ident(String),
+    Call(String, Vec<Expr>),
+}
+
+/// Statements with their byte offsets in the source.
+type Block = Vec<(usize, Stmt)>;
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Block(Block),
+    Function(Function),
+    Let(Vec<(String, Type)>, Option<Expr>),
+    Assign(Vec<String>, Expr),
+    If(Expr, Block),
+    For(Block, Expr, Block, Block),
+    Expr(Expr),
+    Break,
+    Continue,
+    Leave,
+}
+
+#[derive(Clone, Debug)]
+struct Function {
+    name: String,
+    params: Vec<(String, Type)>,
+    returns: Vec<(String, Type)>,
+    body: Block,
+}
+
+#[derive(Clone, Debug)]
+struct Object {
+    name: String,
+    code: Block,
+    objects: Vec<Object>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    idx: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.idx).map(|(token, _)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.idx).map(|(_, offset)| *offset).unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Result<Token, AssemblerError> {
+        let token = self.peek().cloned().ok_or_else(|| syntax_error(self.end, "unexpected end"))?;
+        self.idx += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let matched = self.peek() == Some(token);
+        if matched {
+            self.idx += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), AssemblerError> {
+        let offset = self.offset();
+        if self.next()? != token {
+            return Err(syntax_error(offset, format!("expected {token:?}")));
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<String, AssemblerError> {
+        let offset = self.offset();
+        match self.next()? {
+            Token::Ident(ident) => Ok(ident),
+            _ => Err(syntax_error(offset, "expected identifier")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, AssemblerError> {
+        let offset = self.offset();
+        match self.next()? {
+            Token::Str(string) => Ok(string),
+            _ => Err(syntax_error(offset, "expected string")),
+        }
+    }
+
+    /// Parses an optional type, which defaults to `u256`.
+    fn ty(&mut self) -> Result<Type, AssemblerError> {
+        if !self.eat(&Token::Colon) {
+            return Ok(Type::U256);
+        }
+        match self.ident()?.as_str() {
+            "u256" => Ok(Type::U256),
+            "bool" => Ok(Type::Bool),
+            ty => Err(AssemblerError::Unsupported(format!("type `{ty}`"))),
+        }
+    }
+
+    /// Parses an identifier with optional type.
+    fn typed_ident(&mut self) -> Result<(String, Type), AssemblerError> {
+        Ok((self.ident()?, self.ty()?))
+    }
+
+    fn typed_idents(&mut self) -> Result<Vec<(String, Type)>, AssemblerError> {
+        let mut idents = vec![self.typed_ident()?];
+        while self.eat(&Token::Comma) {
+            idents.push(self.typed_ident()?);
+        }
+        Ok(idents)
+    }
+
+    fn object(&mut self) -> Result<Object, AssemblerError> {
+        let offset = self.offset();
+        if self.ident()? != "object" {
+            return Err(syntax_error(offset, "expected object"));
+        }
+        let name = self.string()?;
+        self.expect(Token::LBrace)?;
+        let offset = self.offset();
+        if self.ident()? != "code" {
+            return Err(syntax_error(offset, "expected code"));
+        }
+        let code = self.block()?;
+        let mut objects = Vec::new();
+        while !self.eat(&Token::RBrace) {
+            match self.peek() {
+                Some(Token::Ident(ident)) if ident == "object" => objects.push(self.object()?),
+                Some(Token::Ident(ident)) if ident == "data" => {
+                    return Err(AssemblerError::Unsupported("data section".to_string()))
+                }
+                _ => return Err(syntax_error(self.offset(), "expected object")),
+            }
+        }
+        Ok(Object { name, code, objects })
+    }
+
+    fn block(&mut self) -> Result<Block, AssemblerError> {
+        self.expect(Token::LBrace)?;
+        let mut stmts = Vec::new();
+        while !self.eat(&Token::RBrace) {
+            stmts.push((self.offset(), self.stmt()?));
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, AssemblerError> {
+        let offset = self.offset();
+        let ident = match self.peek() {
+            Some(Token::LBrace) => return Ok(Stmt::Block(self.block()?)),
+            Some(Token::Ident(ident)) => ident.clone(),
+            _ => return Err(syntax_error(offset, "expected statement")),
+        };
+        match ident.as_str() {
+            "function" => {
+                self.idx += 1;
+                let name = self.ident()?;
+                self.expect(Token::LParen)?;
+                let params = if self.eat(&Token::RParen) {
+                    Vec::new()
+                } else {
+                    let params = self.typed_idents()?;
+                    self.expect(Token::RParen)?;
+                    params
+                };
+                let returns =
+                    if self.eat(&Token::Arrow) { self.typed_idents()? } else { Vec::new() };
+                let body = self.block()?;
+                Ok(Stmt::Function(Function { name, params, returns, body }))
+            }
+            "let" => {
+                self.idx += 1;
+                let idents = self.typed_idents()?;
+                let value = if self.eat(&Token::Assign) { Some(self.expr()?) } else { None };
+                Ok(Stmt::Let(idents, value))
+            }
+            "if" => {
+                self.idx += 1;
+                let condition = self.expr()?;
+                Ok(Stmt::If(condition, self.block()?))
+            }
+            "for" => {
+                self.idx += 1;
+                let init = self.block()?;
+                let condition = self.expr()?;
+                let post = self.block()?;
+                Ok(Stmt::For(init, condition, post, self.block()?))
+            }
+            "break" | "continue" | "leave" => {
+                self.idx += 1;
+                Ok(match ident.as_str() {
+                    "break" => Stmt::Break,
+                    "continue" => Stmt::Continue,
+                    _ => Stmt::Leave,
+                })
+            }
+            "switch" | "case" | "default" => Err(AssemblerError::Unsupported(ident)),
+            _ => match self.tokens.get(self.idx + 1).map(|(token, _)| token) {
+                Some(Token::Assign | Token::Comma) => {
+                    let mut idents = vec![self.ident()?];
+                    while self.eat(&Token::Comma) {
+                        idents.push(self.ident()?);
+                    }
+                    self.expect(Token::Assign)?;
+                    Ok(Stmt::Assign(idents, self.expr()?))
+                }
+                _ => Ok(Stmt::Expr(self.expr()?)),
+            },
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, AssemblerError> {
+        let offset = self.offset();
+        let (value, ty) = match self.next()? {
+            Token::Number(number) => (number, Type::U256),
+            Token::Str(string) => return Ok(Expr::Str(string)),
+            Token::Ident(ident) if ident == "true" => (U256::one(), Type::Bool),
+            Token::Ident(ident) if ident == "false" => (U256::zero(), Type::Bool),
+            Token::Ident(ident) => {
+                if !self.eat(&Token::LParen) {
+                    return Ok(Expr::Ident(ident));
+                }
+                let mut args = Vec::new();
+                if !self.eat(&Token::RParen) {
+                    args.push(self.expr()?);
+                    while self.eat(&Token::Comma) {
+                        args.push(self.expr()?);
+                    }
+                    self.expect(Token::RParen)?;
+                }
+                return Ok(Expr::Call(ident, args));
+            }
+            _ => return Err(syntax_error(offset, "expected expression")),
+        };
+        // Numbers are always `u256` and `true` or `false` are always `bool`.
+        if self.peek() == Some(&Token::Colon) && self.ty()? != ty {
+            return Err(AssemblerError::TypeMismatch(value.to_string()));
+        }
+        Ok(Expr::Literal(value, ty))
+    }
+}
+
+mod opcode {
+    pub const STOP: u8 = 0x00;
+    pub const POP: u8 = 0x50;
+    pub const JUMP: u8 = 0x56;
+    pub const JUMPI: u8 = 0x57;
+    pub const JUMPDEST: u8 = 0x5b;
+    pub const PUSH1: u8 = 0x60;
+    pub const PUSH2: u8 = 0x61;
+    pub const DUP1: u8 = 0x80;
+    pub const SWAP1: u8 = 0x90;
+    pub const ISZERO: u8 = 0x15;
+}
+
+/// Returns opcode, number of arguments and number of return values of builtin
+/// function `name` in the typed EVM dialect, where `not` is logical negation
+/// and `bit*` are bitwise operations.
+fn builtin(name: &str) -> Option<(u8, usize, usize)> {
+    Some(match name {
+        "stop" => (0x00, 0, 0),
+        "add" => (0x01, 2, 1),
+        "mul" => (0x02, 2, 1),
+        "sub" => (0x03, 2, 1),
+        "div" => (0x04, 2, 1),
+        "sdiv" => (0x05, 2, 1),
+        "mod" => (0x06, 2, 1),
+        "smod" => (0x07, 2, 1),
+        "addmod" => (0x08, 3, 1),
+        "mulmod" => (0x09, 3, 1),
+        "exp" => (0x0a, 2, 1),
+        "signextend" => (0x0b, 2, 1),
+        "lt" => (0x10, 2, 1),
+        "gt" => (0x11, 2, 1),
+        "slt" => (0x12, 2, 1),
+        "sgt" => (0x13, 2, 1),
+        "eq" => (0x14, 2, 1),
+        "not" => (opcode::ISZERO, 1, 1),
+        "and" | "bitand" => (0x16, 2, 1),
+        "or" | "bitor" => (0x17, 2, 1),
+        "xor" | "bitxor" => (0x18, 2, 1),
+        "bitnot" => (0x19, 1, 1),
+        "byte" => (0x1a, 2, 1),
+        "shl" => (0x1b, 2, 1),
+        "shr" => (0x1c, 2, 1),
+        "sar" => (0x1d, 2, 1),
+        "keccak256" => (0x20, 2, 1),
+        "address" => (0x30, 0, 1),
+        "balance" => (0x31, 1, 1),
+        "origin" => (0x32, 0, 1),
+        "caller" => (0x33, 0, 1),
+        "callvalue" => (0x34, 0, 1),
+        "calldataload" => (0x35, 1, 1),
+        "calldatasize" => (0x36, 0, 1),
+        "calldatacopy" => (0x37, 3, 0),
+        "codesize" => (0x38, 0, 1),
+        "codecopy" | "datacopy" => (0x39, 3, 0),
+        "gasprice" => (0x3a, 0, 1),
+        "extcodesize" => (0x3b, 1, 1),
+        "extcodecopy" => (0x3c, 4, 0),
+        "returndatasize" => (0x3d, 0, 1),
+        "returndatacopy" => (0x3e, 3, 0),
+        "extcodehash" => (0x3f, 1, 1),
+        "blockhash" => (0x40, 1, 1),
+        "coinbase" => (0x41, 0, 1),
+        "timestamp" => (0x42, 0, 1),
+        "number" => (0x43, 0, 1),
+        "difficulty" => (0x44, 0, 1),
+        "gaslimit" => (0x45, 0, 1),
+        "chainid" => (0x46, 0, 1),
+        "selfbalance" => (0x47, 0, 1),
+        "basefee" => (0x48, 0, 1),
+        "pop" | "popbool" => (opcode::POP, 1, 0),
+        "mload" => (0x51, 1, 1),
+        "mstore" => (0x52, 2, 0),
+        "mstore8" => (0x53, 2, 0),
+        "sload" => (0x54, 1, 1),
+        "sstore" => (0x55, 2, 0),
+        "msize" => (0x59, 0, 1),
+        "gas" => (0x5a, 0, 1),
+        "log0" => (0xa0, 2, 0),
+        "log1" => (0xa1, 3, 0),
+        "log2" => (0xa2, 4, 0),
+        "log3" => (0xa3, 5, 0),
+        "log4" => (0xa4, 6, 0),
+        "create" => (0xf0, 3, 1),
+        "call" => (0xf1, 7, 1),
+        "callcode" => (0xf2, 7, 1),
+        "return" => (0xf3, 2, 0),
+        "delegatecall" => (0xf4, 6, 1),
+        "create2" => (0xf5, 4, 1),
+        "staticcall" => (0xfa, 6, 1),
+        "revert" => (0xfd, 2, 0),
+        "invalid" => (0xfe, 0, 0),
+        "selfdestruct" => (0xff, 1, 0),
+        _ => return None,
+    })
+}
+
+/// Returns types of arguments and return values of builtin function `name`
+/// with given arity, where comparisons return `bool`, logical operations take
+/// and return `bool`, and all the others take and return `u256`.
+fn builtin_types(name: &str, num_args: usize, num_returns: usize) -> (Vec<Type>, Vec<Type>) {
+    match name {
+        "lt" | "gt" | "slt" | "sgt" | "eq" => (vec![Type::U256; num_args], vec![Type::Bool]),
+        "not" | "and" | "or" | "xor" => (vec![Type::Bool; num_args], vec![Type::Bool]),
+        "popbool" => (vec![Type::Bool], Vec::new()),
+        _ => (vec![Type::U256; num_args], vec![Type::U256; num_returns]),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Item {
+    Op(u8),
+    Push(U256),
+    PushLabel(usize),
+    Label(usize),
+    /// Size of sub-object with given index.
+    PushDataSize(usize),
+    /// Offset of sub-object with given index.
+    PushDataOffset(usize),
+    /// Start of code generated from the statement at given source offset.
+    Source(usize),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Var {
+    slot: usize,
+    ty: Type,
+    constant: Option<U256>,
+}
+
+impl Var {
+    fn new(slot: usize, ty: Type) -> Self {
+        Self { slot, ty, constant: None }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct FunctionInfo {
+    label: usize,
+    params: Vec<Type>,
+    returns: Vec<Type>,
+}
+
+struct Loop {
+    continue_label: usize,
+    break_label: usize,
+    height: usize,
+}
+
+struct Frame {
+    height: usize,
+    vars: Vec<(String, Var)>,
+    loops: Vec<Loop>,
+    exit: Option<(usize, usize)>,
+}
+
+struct Codegen<'a> {
+    items: Vec<Item>,
+    num_labels: usize,
+    objects: &'a [Object],
+    functions: Vec<HashMap<String, FunctionInfo>>,
+    deferred: Vec<(usize, usize, Function, Vec<HashMap<String, FunctionInfo>>)>,
+    frame: Frame,
+}
+
+/// Returns code of `object`, with source maps of it and its sub-objects.
+fn assemble_object(
+    object: &Object,
+) -> Result<(Vec<u8>, SourceMap, Vec<SourceMap>), AssemblerError> {
+    let mut codegen = Codegen {
+        items: Vec::new(),
+        num_labels: 0,
+        objects: &object.objects,
+        functions: Vec::new(),
+        deferred: Vec::new(),
+        frame: Frame { height: 0, vars: Vec::new(), loops: Vec::new(), exit: None },
+    };
+    codegen.block(&object.code)?;
+    codegen.op(opcode::STOP);
+    while let Some((label, offset, function, functions)) = codegen.deferred.pop() {
+        codegen.functions = functions;
+        codegen.function(label, offset, &function)?;
+    }
+
+    let mut objects = Vec::new();
+    let mut source_maps = Vec::new();
+    for object in object.objects.iter() {
+        let (code, source_map, _) = assemble_object(object)?;
+        objects.push(code);
+        source_maps.push(source_map);
+    }
+
+    let push_len = |value: &U256| 1 + ((value.bits() + 7) / 8).max(1);
+    let mut labels = vec![0; codegen.num_labels];
+    let mut source_map = BTreeMap::new();
+    let mut code_len = 0;
+    for item in codegen.items.iter() {
+        code_len += match item {
+            Item::Op(_) => 1,
+            Item::Push(value) => push_len(value),
+            Item::PushLabel(_) | Item::PushDataSize(_) | Item::PushDataOffset(_) => 3,
+            Item::Label(label) => {
+                labels[*label] = code_len;
+                1
+            }
+            Item::Source(offset) => {
+                source_map.insert(code_len, *offset);
+                0
+            }
+        };
+    }
+    let size = code_len + objects.iter().map(Vec::len).sum::<usize>();
+    if size > 0xffff {
+        return Err(AssemblerError::CodeTooLarge(size));
+    }
+
+    let mut code = Vec::with_capacity(size);
+    for item in codegen.items.iter() {
+        let push2 = |code: &mut Vec<u8>, value: usize| {
+            code.push(opcode::PUSH2);
+            code.extend((value as u16).to_be_bytes());
+        };
+        match *item {
+            Item::Op(op) => code.push(op),
+            Item::Push(value) => {
+                let len = push_len(&value) - 1;
+                let mut bytes = [0; 32];
+                value.to_big_endian(&mut bytes);
+                code.push(opcode::PUSH1 + len as u8 - 1);
+                code.extend_from_slice(&bytes[32 - len..]);
+            }
+            Item::PushLabel(label) => push2(&mut code, labels[label]),
+            Item::Label(_) => code.push(opcode::JUMPDEST),
+            Item::PushDataSize(idx) => push2(&mut code, objects[idx].len()),
+            Item::PushDataOffset(idx) => {
+                push2(&mut code, code_len + objects[..idx].iter().map(Vec::len).sum::<usize>())
+            }
+            Item::Source(_) => {}
+        }
+    }
+    code.extend(objects.into_iter().flatten());
+    Ok((code, SourceMap(source_map), source_maps))
+}
+
+/// Collects names assigned in `stmts`, excluding the ones in nested functions
+/// which can't refer to outer variables.
+fn collect_assigned(stmts: &[(usize, Stmt)], names: &mut HashSet<String>) {
+    for (_, stmt) in stmts {
+        match stmt {
+            Stmt::Assign(idents, _) => names.extend(idents.iter().cloned()),
+            Stmt::Block(stmts) | Stmt::If(_, stmts) => collect_assigned(stmts, names),
+            Stmt::For(init, _, post, body) => {
+                for stmts in [init, post, body] {
+                    collect_assigned(stmts, names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Codegen<'_> {
+    fn op(&mut self, op: u8) {
+        self.items.push(Item::Op(op));
+    }
+
+    fn label(&mut self) -> usize {
+        self.num_labels += 1;
+        self.num_labels - 1
+    }
+
+    fn jump(&mut self, label: usize) {
+        self.items.push(Item::PushLabel(label));
+        self.op(opcode::JUMP);
+    }
+
+    fn pop_to(&mut self, height: usize) {
+        for _ in height..self.frame.height {
+            self.op(opcode::POP);
+        }
+    }
+
+    fn var(&self, name: &str) -> Result<Var, AssemblerError> {
+        self.frame
+            .vars
+            .iter()
+            .rev()
+            .find(|(var, _)| var == name)
+            .map(|(_, var)| *var)
+            .ok_or_else(|| AssemblerError::UndefinedIdentifier(name.to_string()))
+    }
+
+    fn function_info(&self, name: &str) -> Option<FunctionInfo> {
+        self.functions.iter().rev().find_map(|functions| functions.get(name)).cloned()
+    }
+
+    fn block(&mut self, stmts: &[(usize, Stmt)]) -> Result<(), AssemblerError> {
+        let (height, num_vars) = (self.frame.height, self.frame.vars.len());
+
+        let functions = stmts
+            .iter()
+            .filter_map(|(_, stmt)| match stmt {
+                Stmt::Function(function) => Some(function),
+                _ => None,
+            })
+            .map(|function| {
+                let types = |idents: &[(String, Type)]| idents.iter().map(|(_, ty)| *ty).collect();
+                let info = FunctionInfo {
+                    label: self.label(),
+                    params: types(&function.params),
+                    returns: types(&function.returns),
+                };
+                (function.name.clone(), info)
+            })
+            .collect::<HashMap<_, _>>();
+        self.functions.push(functions);
+        let mut assigned = HashSet::new();
+        collect_assigned(stmts, &mut assigned);
+
+        for (offset, stmt) in stmts {
+            self.stmt(*offset, stmt, &assigned)?;
+        }
+
+        self.pop_to(height);
+        self.frame.height = height;
+        self.frame.vars.truncate(num_vars);
+        self.functions.pop();
+        Ok(())
+    }
+
+    fn stmt(
+        &mut self,
+        offset: usize,
+        stmt: &Stmt,
+        assigned: &HashSet<String>,
+    ) -> Result<(), AssemblerError> {
+        self.items.push(Item::Source(offset));
+        match stmt {
+            Stmt::Block(stmts) => self.block(stmts)?,
+            Stmt::Function(function) => {
+                let label = self.function_info(&function.name).unwrap().label;
+                self.deferred.push((label, offset, function.clone(), self.functions.clone()));
+            }
+            Stmt::Let(idents, value) => {
+                match value {
+                    Some(value) => {
+                        let types = self.expr_multi(value, idents.len())?;
+                        if idents.iter().zip(types).any(|((_, ty), value_ty)| *ty != value_ty) {
+                            return Err(AssemblerError::TypeMismatch(idents[0].0.clone()));
+                        }
+                    }
+                    None => {
+                        for _ in idents {
+                            self.items.push(Item::Push(U256::zero()));
+                            self.frame.height += 1;
+                        }
+                    }
+                }
+                let slots = self.frame.height - idents.len()..self.frame.height;
+                for ((ident, ty), slot) in idents.iter().zip(slots) {
+                    let constant = match value {
+                        Some(Expr::Literal(value, _)) if !assigned.contains(ident) => Some(*value),
+                        _ => None,
+                    };
+                    self.frame.vars.push((ident.clone(), Var { slot, ty: *ty, constant }));
+                }
+            }
+            Stmt::Assign(idents, value) => {
+                let types = self.expr_multi(value, idents.len())?;
+                for (ident, ty) in idents.iter().zip(types) {
+                    if self.var(ident)?.ty != ty {
+                        return Err(AssemblerError::TypeMismatch(ident.clone()));
+                    }
+                }
+                for ident in idents.iter().rev() {
+                    let depth = self.frame.height - 1 - self.var(ident)?.slot;
+                    if depth > 16 {
+                        return Err(AssemblerError::StackTooDeep(ident.clone()));
+                    }
+                    self.op(opcode::SWAP1 + depth as u8 - 1);
+                    self.op(opcode::POP);
+                    self.frame.height -= 1;
+                }
+            }
+            Stmt::If(condition, body) => {
+                let end = self.label();
+                self.condition(condition, "if")?;
+                self.op(opcode::ISZERO);
+                self.items.push(Item::PushLabel(end));
+                self.op(opcode::JUMPI);
+                self.frame.height -= 1;
+                self.block(body)?;
+                self.items.push(Item::Source(offset));
+                self.items.push(Item::Label(end));
+            }
+            Stmt::For(init, condition, post, body) => {
+                let (height, num_vars) = (self.frame.height, self.frame.vars.len());
+                let [start, continue_label, break_label] = [(); 3].map(|_| self.label());
+
+                self.functions.push(HashMap::new());
+                for (offset, stmt) in init {
+                    if matches!(stmt, Stmt::Function(_)) {
+                        return Err(AssemblerError::Unsupported("function in loop".to_string()));
+                    }
+                    self.stmt(*offset, stmt, assigned)?;
+                }
+
+                self.items.push(Item::Source(offset));
+                self.items.push(Item::Label(start));
+                self.condition(condition, "for")?;
+                self.op(opcode::ISZERO);
+                self.items.push(Item::PushLabel(break_label));
+                self.op(opcode::JUMPI);
+                self.frame.height -= 1;
+
+                let height_in_loop = self.frame.height;
+                self.frame.loops.push(Loop { continue_label, break_label, height: height_in_loop });
+                self.block(body)?;
+                self.frame.loops.pop();
+                self.items.push(Item::Label(continue_label));
+                self.block(post)?;
+                self.items.push(Item::Source(offset));
+                self.jump(start);
+                self.items.push(Item::Label(break_label));
+
+                self.pop_to(height);
+                self.frame.height = height;
+                self.frame.vars.truncate(num_vars);
+                self.functions.pop();
+            }
+            Stmt::Expr(expr) => {
+                self.expr_multi(expr, 0)?;
+            }
+            Stmt::Break | Stmt::Continue => {
+                let (label, height) = self
+                    .frame
+                    .loops
+                    .last()
+                    .map(|Loop { continue_label, break_label, height }| match stmt {
+                        Stmt::Break => (*break_label, *height),
+                        _ => (*continue_label, *height),
+                    })
+                    .ok_or_else(|| AssemblerError::Unsupported("jump outside loop".into()))?;
+                let current = self.frame.height;
+                self.pop_to(height);
+                self.jump(label);
+                // Following code is unreachable, keep the height as is.
+                self.frame.height = current;
+            }
+            Stmt::Leave => {
+                let (label, height) = self
+                    .frame
+                    .exit
+                    .ok_or_else(|| AssemblerError::Unsupported("leave outside function".into()))?;
+                let current = self.frame.height;
+                self.pop_to(height);
+                self.jump(label);
+                self.frame.height = current;
+            }
+        }
+        Ok(())
+    }
+
+    /// Generates a function called with stack `[return label, args in reverse]`
+    /// and returning to the label with stack `[return value]`.
+    fn function(
+        &mut self,
+        label: usize,
+        offset: usize,
+        function: &Function,
+    ) -> Result<(), AssemblerError> {
+        let (num_params, num_returns) = (function.params.len(), function.returns.len());
+        if num_returns > 1 {
+            return Err(AssemblerError::Unsupported("multiple return values".into()));
+        }
+
+        let exit = self.label();
+        let height = 1 + num_params + num_returns;
+        let vars = iter_params(function).collect();
+        self.frame = Frame { height, vars, loops: Vec::new(), exit: Some((exit, height)) };
+
+        self.items.push(Item::Source(offset));
+        self.items.push(Item::Label(label));
+        for _ in 0..num_returns {
+            self.items.push(Item::Push(U256::zero()));
+        }
+        self.block(&function.body)?;
+        self.items.push(Item::Label(exit));
+        for _ in 0..num_params {
+            if num_returns == 1 {
+                self.op(opcode::SWAP1);
+            }
+            self.op(opcode::POP);
+        }
+        if num_returns == 1 {
+            self.op(opcode::SWAP1);
+        }
+        self.op(opcode::JUMP);
+        Ok(())
+    }
+
+    /// Generates `condition` of `if` or `for`, which must be `bool`.
+    fn condition(&mut self, condition: &Expr, name: &str) -> Result<(), AssemblerError> {
+        match self.expr(condition)? {
+            Type::Bool => Ok(()),
+            Type::U256 => Err(AssemblerError::TypeMismatch(name.to_string())),
+        }
+    }
+
+    /// Generates `expr` which pushes `num_values` values, and returns their
+    /// types.
+    fn expr_multi(&mut self, expr: &Expr, num_values: usize) -> Result<Vec<Type>, AssemblerError> {
+        let (name, args) = match expr {
+            Expr::Call(name, args) => (name, args),
+            _ if num_values == 1 => return Ok(vec![self.expr(expr)?]),
+            _ => return Err(AssemblerError::ArityMismatch(format!("{expr:?}"))),
+        };
+        let height = self.frame.height;
+        let types = match name.as_str() {
+            "datasize" | "dataoffset" => {
+                let object = match args.as_slice() {
+                    [Expr::Str(object)] => object,
+                    _ => return Err(AssemblerError::ArityMismatch(name.clone())),
+                };
+                let idx = self
+                    .objects
+                    .iter()
+                    .position(|sub| &sub.name == object)
+                    .ok_or_else(|| AssemblerError::UndefinedIdentifier(object.clone()))?;
+                self.items.push(if name == "datasize" {
+                    Item::PushDataSize(idx)
+                } else {
+                    Item::PushDataOffset(idx)
+                });
+                self.frame.height += 1;
+                vec![Type::U256]
+            }
+            _ => {
+                let info = self.function_info(name);
+                let (params, returns) = match (&info, builtin(name)) {
+                    (Some(info), _) => (info.params.clone(), info.returns.clone()),
+                    (None, Some((_, num_args, num_returns))) => {
+                        builtin_types(name, num_args, num_returns)
+                    }
+                    (None, None) => return Err(AssemblerError::UndefinedIdentifier(name.clone())),
+                };
+                if args.len() != params.len() {
+                    return Err(AssemblerError::ArityMismatch(name.clone()));
+                }
+                let return_label = info.as_ref().map(|_| self.label());
+                if let Some(return_label) = return_label {
+                    self.items.push(Item::PushLabel(return_label));
+                    self.frame.height += 1;
+                }
+                for (arg, ty) in args.iter().zip(params.iter()).rev() {
+                    if self.expr(arg)? != *ty {
+                        return Err(AssemblerError::TypeMismatch(name.clone()));
+                    }
+                }
+                match (info, return_label) {
+                    (Some(info), Some(return_label)) => {
+                        self.jump(info.label);
+                        self.items.push(Item::Label(return_label));
+                    }
+                    _ => self.op(builtin(name).unwrap().0),
+                }
+                self.frame.height = height + returns.len();
+                returns
+            }
+        };
+        if self.frame.height != height + num_values {
+            return Err(AssemblerError::ArityMismatch(name.clone()));
+        }
+        Ok(types)
+    }
+
+    /// Generates `expr` which pushes a value, and returns its type.
+    fn expr(&mut self, expr: &Expr) -> Result<Type, AssemblerError> {
+        Ok(match expr {
+            Expr::Literal(value, ty) => {
+                self.items.push(Item::Push(*value));
+                self.frame.height += 1;
+                *ty
+            }
+            Expr::Ident(name) => {
+                let var = self.var(name)?;
+                let depth = self.frame.height - var.slot;
+                match var.constant {
+                    Some(value) if depth > 16 => self.items.push(Item::Push(value)),
+                    _ if depth > 16 => return Err(AssemblerError::StackTooDeep(name.clone())),
+                    _ => self.op(opcode::DUP1 + depth as u8 - 1),
+                }
+                self.frame.height += 1;
+                var.ty
+            }
+            Expr::Str(string) => return Err(AssemblerError::Unsupported(format!("\"{string}\""))),
+            Expr::Call(..) => self.expr_multi(expr, 1)?[0],
+        })
+    }
+}
+
+/// Maximum number of iterations of a loop [`GasEstimator`] walks through.
+const MAX_LOOP_ITERATIONS: usize = 1 << 16;
+
+/// Where [`GasEstimator`] continues after a statement.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Leave,
+}
+
+/// Walker of Yul pricing it as the code generated by [`Codegen`] runs.
+struct GasEstimator<'a> {
+    schedule: &'a GasSchedule,
+    functions: Vec<HashMap<&'a str, &'a Function>>,
+    /// Variables in scope of the function being walked, with their values if
+    /// known.
+    vars: Vec<HashMap<&'a str, Option<U256>>>,
+    /// Functions being called, to reject recursion.
+    calls: Vec<&'a str>,
+    /// Words known in memory, which are written with literals at literal
+    /// offsets.
+    memory: BTreeMap<u64, U256>,
+    /// Size of memory touched at literal offsets.
+    memory_size: u64,
+    estimate: GasEstimate,
+}
+
+impl<'a> GasEstimator<'a> {
+    fn charge(&mut self, ops: &[u8]) {
+        self.estimate.execution += ops.iter().map(|op| self.schedule.opcode(*op)).sum::<u64>();
+    }
+
+    fn function(&self, name: &str) -> Option<&'a Function> {
+        self.functions.iter().rev().find_map(|functions| functions.get(name)).copied()
+    }
+
+    fn var(&self, name: &str) -> Option<U256> {
+        self.vars.iter().rev().find_map(|vars| vars.get(name)).copied().flatten()
+    }
+
+    fn assign(&mut self, name: &'a str, value: Option<U256>) {
+        if let Some(var) = self.vars.iter_mut().rev().find_map(|vars| vars.get_mut(name)) {
+            *var = value;
+        }
+    }
+
+    /// Returns whether `stmts` always end by reverting, e.g. a failed check,
+    /// looking into at most `depth` nested calls.
+    fn reverts(&self, stmts: &[(usize, Stmt)], depth: usize) -> bool {
+        match stmts.last() {
+            Some((_, Stmt::Expr(Expr::Call(name, _)))) => {
+                matches!(name.as_str(), "revert" | "invalid")
+                    || (depth > 0
+                        && self
+                            .function(name)
+                            .map_or(false, |function| self.reverts(&function.body, depth - 1)))
+            }
+            Some((_, Stmt::Block(stmts))) => self.reverts(stmts, depth),
+            _ => false,
+        }
+    }
+
+    /// Expands memory to cover `memory[offset..offset+len]`.
+    fn touch(&mut self, offset: Option<u64>, len: Option<u64>) {
+        if let (Some(offset), Some(len)) = (offset, len) {
+            if len > 0 {
+                self.memory_size = self.memory_size.max(offset.saturating_add(len));
+            }
+        }
+    }
+
+    /// Forgets known words overlapping `memory[offset..offset+len]`, or all of
+    /// them if the range is unknown.
+    fn write(&mut self, offset: Option<u64>, len: Option<u64>) {
+        match (offset, len) {
+            (_, Some(0)) => {}
+            (Some(offset), Some(len)) => self
+                .memory
+                .retain(|word, _| word.saturating_add(0x20) <= offset || *word >= offset + len),
+            _ => self.memory.clear(),
+        }
+    }
+
+    fn block(&mut self, stmts: &'a [(usize, Stmt)]) -> Result<Flow, AssemblerError> {
+        let functions = stmts
+            .iter()
+            .filter_map(|(_, stmt)| match stmt {
+                Stmt::Function(function) => Some((function.name.as_str(), function)),
+                _ => None,
+            })
+            .collect();
+        self.functions.push(functions);
+        self.vars.push(HashMap::new());
+        let mut flow = Flow::Next;
+        for (_, stmt) in stmts {
+            flow = self.stmt(stmt)?;
+            if flow != Flow::Next {
+                break;
+            }
+        }
+        // Variables declared in the block are popped at the end, or before
+        // jumping out of it.
+        for _ in 0..num_vars(stmts) {
+            self.charge(&[opcode::POP]);
+        }
+        self.vars.pop();
+        self.functions.pop();
+        Ok(flow)
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) -> Result<Flow, AssemblerError> {
+        match stmt {
+            Stmt::Block(stmts) => return self.block(stmts),
+            Stmt::Expr(value) => {
+                self.expr(value)?;
+            }
+            Stmt::Let(idents, Some(value)) => {
+                let value = self.expr(value)?.filter(|_| idents.len() == 1);
+                let vars = self.vars.last_mut().unwrap();
+                vars.extend(idents.iter().map(|(ident, _)| (ident.as_str(), value)));
+            }
+            Stmt::Let(idents, None) => {
+                for (ident, _) in idents {
+                    self.charge(&[opcode::PUSH1]);
+                    self.vars.last_mut().unwrap().insert(ident.as_str(), Some(U256::zero()));
+                }
+            }
+            Stmt::Assign(idents, value) => {
+                let value = self.expr(value)?.filter(|_| idents.len() == 1);
+                for ident in idents {
+                    self.charge(&[opcode::SWAP1, opcode::POP]);
+                    self.assign(ident, value);
+                }
+            }
+            Stmt::If(condition, body) => {
+                let condition = self.expr(condition)?;
+                self.charge(&[opcode::ISZERO, opcode::PUSH2, opcode::JUMPI]);
+                let taken = match condition {
+                    Some(condition) => !condition.is_zero(),
+                    None => !self.reverts(body, 8),
+                };
+                if taken {
+                    let flow = self.block(body)?;
+                    if flow != Flow::Next {
+                        return Ok(flow);
+                    }
+                }
+                self.charge(&[opcode::JUMPDEST]);
+            }
+            Stmt::For(init, condition, post, body) => {
+                self.functions.push(HashMap::new());
+                self.vars.push(HashMap::new());
+                for (_, stmt) in init {
+                    self.stmt(stmt)?;
+                }
+                let mut iterations = 0;
+                let mut flow = Flow::Next;
+                loop {
+                    match self.loop_condition(condition)? {
+                        Some(condition) if condition.is_zero() => break,
+                        // Unknown condition is assumed to hold only once.
+                        None if iterations > 0 => break,
+                        _ if iterations == MAX_LOOP_ITERATIONS => {
+                            return Err(AssemblerError::Unsupported(format!(
+                                "loop of more than {MAX_LOOP_ITERATIONS} iterations"
+                            )))
+                        }
+                        _ => {}
+                    }
+                    match self.block(body)? {
+                        Flow::Break => break,
+                        Flow::Leave => {
+                            flow = Flow::Leave;
+                            break;
+                        }
+                        Flow::Next | Flow::Continue => {}
+                    }
+                    self.charge(&[opcode::JUMPDEST]);
+                    self.block(post)?;
+                    self.charge(&[opcode::PUSH2, opcode::JUMP]);
+                    iterations += 1;
+                }
+                self.charge(&[opcode::JUMPDEST]);
+                for _ in 0..num_vars(init) {
+                    self.charge(&[opcode::POP]);
+                }
+                self.vars.pop();
+                self.functions.pop();
+                return Ok(flow);
+            }
+            Stmt::Function(_) => {}
+            Stmt::Break => return Ok(Flow::Break),
+            Stmt::Continue => return Ok(Flow::Continue),
+            Stmt::Leave => return Ok(Flow::Leave),
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Prices condition of loop, and returns its value if it's known.
+    fn loop_condition(&mut self, condition: &'a Expr) -> Result<Option<U256>, AssemblerError> {
+        self.charge(&[opcode::JUMPDEST]);
+        let condition = self.expr(condition)?;
+        self.charge(&[opcode::ISZERO, opcode::PUSH2, opcode::JUMPI]);
+        Ok(condition)
+    }
+
+    /// Prices `expr`, and returns its value if it's known, i.e. computed only
+    /// from literals by arithmetic or memory written at known offsets.
+    fn expr(&mut self, expr: &'a Expr) -> Result<Option<U256>, AssemblerError> {
+        let (name, args) = match expr {
+            Expr::Literal(value, _) => {
+                self.charge(&[opcode::PUSH1]);
+                return Ok(Some(*value));
+            }
+            Expr::Ident(ident) => {
+                self.charge(&[opcode::DUP1]);
+                return Ok(self.var(ident));
+            }
+            Expr::Str(string) => return Err(AssemblerError::Unsupported(format!("\"{string}\""))),
+            Expr::Call(name, args) => (name.as_str(), args),
+        };
+        if matches!(name, "datasize" | "dataoffset") {
+            self.charge(&[opcode::PUSH2]);
+            return Ok(None);
+        }
+        if let Some(function) = self.function(name) {
+            return self.call(function, args);
+        }
+
+        let (op, num_args, _) =
+            builtin(name).ok_or_else(|| AssemblerError::UndefinedIdentifier(name.to_string()))?;
+        if args.len() != num_args {
+            return Err(AssemblerError::ArityMismatch(name.to_string()));
+        }
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args.iter().rev() {
+            values.push(self.expr(arg)?);
+        }
+        values.reverse();
+        Ok(self.builtin(name, op, &values))
+    }
+
+    /// Prices a call to `function`, which jumps into it and back, and returns
+    /// its value if it returns a single known one.
+    fn call(
+        &mut self,
+        function: &'a Function,
+        args: &'a [Expr],
+    ) -> Result<Option<U256>, AssemblerError> {
+        let name = function.name.as_str();
+        if self.calls.contains(&name) {
+            return Err(AssemblerError::Unsupported(format!("recursion of `{name}`")));
+        }
+        let (num_params, num_returns) = (function.params.len(), function.returns.len());
+        if args.len() != num_params {
+            return Err(AssemblerError::ArityMismatch(name.to_string()));
+        }
+
+        self.charge(&[opcode::PUSH2]);
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args.iter().rev() {
+            values.push(self.expr(arg)?);
+        }
+        values.reverse();
+        self.charge(&[opcode::PUSH2, opcode::JUMP, opcode::JUMPDEST]);
+        for _ in 0..num_returns {
+            self.charge(&[opcode::PUSH1]);
+        }
+        // Function only sees its parameters and return values.
+        let params = function.params.iter().map(|(param, _)| param.as_str()).zip(values);
+        let returns = function.returns.iter().map(|(ret, _)| (ret.as_str(), Some(U256::zero())));
+        let vars = std::mem::replace(&mut self.vars, vec![params.chain(returns).collect()]);
+        self.calls.push(name);
+        self.block(&function.body)?;
+        self.calls.pop();
+        let vars = std::mem::replace(&mut self.vars, vars);
+        let value = match function.returns.as_slice() {
+            [(ret, _)] => vars[0][ret.as_str()],
+            _ => None,
+        };
+        self.charge(&[opcode::JUMPDEST]);
+        for _ in 0..num_params {
+            if num_returns == 1 {
+                self.charge(&[opcode::SWAP1]);
+            }
+            self.charge(&[opcode::POP]);
+        }
+        if num_returns == 1 {
+            self.charge(&[opcode::SWAP1]);
+        }
+        self.charge(&[opcode::JUMP, opcode::JUMPDEST]);
+        Ok(value)
+    }
+
+    /// Prices builtin `name` of opcode `op` given its arguments if known, and
+    /// returns its value if it's known.
+    fn builtin(&mut self, name: &str, op: u8, args: &[Option<U256>]) -> Option<U256> {
+        let schedule = self.schedule;
+        let arg =
+            |idx: usize| args[idx].filter(|value| value.bits() <= 64).map(|value| value.as_u64());
+        let words = |len: Option<u64>| len.map_or(0, |len| (len + 31) / 32);
+
+        if name == "keccak256" {
+            self.touch(arg(0), arg(1));
+            self.estimate.keccak += schedule.opcode(op) + schedule.keccak_word * words(arg(1));
+            return None;
+        }
+        self.charge(&[op]);
+        match name {
+            "mload" => {
+                self.touch(arg(0), Some(0x20));
+                return arg(0).and_then(|offset| self.memory.get(&offset)).copied();
+            }
+            "mstore" | "mstore8" => {
+                let len = if name == "mstore" { 0x20 } else { 1 };
+                self.touch(arg(0), Some(len));
+                self.write(arg(0), Some(len));
+                if let (Some(offset), Some(value), "mstore") = (arg(0), args[1], name) {
+                    self.memory.insert(offset, value);
+                }
+            }
+            "calldatacopy" | "codecopy" | "datacopy" | "returndatacopy" => {
+                self.touch(arg(0), arg(2));
+                self.write(arg(0), arg(2));
+                self.estimate.execution += schedule.copy_word * words(arg(2));
+            }
+            "return" | "revert" => self.touch(arg(0), arg(1)),
+            "log0" | "log1" | "log2" | "log3" | "log4" => {
+                self.touch(arg(0), arg(1));
+                self.estimate.execution += schedule.log_data_byte * arg(1).unwrap_or(0);
+            }
+            "exp" => {
+                let len = args[1].map_or(0x20, |exponent| (exponent.bits() as u64 + 7) / 8);
+                self.estimate.execution += schedule.exp_byte * len;
+            }
+            "call" | "callcode" => {
+                self.touch(arg(3), arg(4));
+                self.touch(arg(5), arg(6));
+                self.write(arg(5), arg(6));
+            }
+            "delegatecall" | "staticcall" => {
+                self.touch(arg(2), arg(3));
+                self.touch(arg(4), arg(5));
+                let precompile = arg(1).filter(|_| name == "staticcall").and_then(|address| {
+                    Precompiled::from_address(Address::from_low_u64_be(address))
+                });
+                if let Some(precompile) = precompile {
+                    let gas = self.precompile(precompile, arg(2), arg(3));
+                    let usage = self.estimate.precompiles.entry(precompile).or_default();
+                    usage.calls += 1;
+                    usage.gas += gas;
+                }
+                self.write(arg(4), arg(5));
+            }
+            _ => return eval(name, args),
+        }
+        None
+    }
+
+    /// Returns cost of `precompile` with input `memory[input..input+len]`,
+    /// assuming the unknown lengths of `modexp` are 32 bytes and its unknown
+    /// exponent has 256 bits, and `blake2f` runs 12 rounds if unknown.
+    fn precompile(&self, precompile: Precompiled, input: Option<u64>, len: Option<u64>) -> u64 {
+        let schedule = self.schedule;
+        let word =
+            |offset: u64| input.and_then(|input| self.memory.get(&(input + offset))).copied();
+        match precompile {
+            Precompiled::BigModExp => {
+                let len = |offset: u64| {
+                    word(offset).filter(|len| len.bits() <= 64).map_or(0x20, |len| len.as_u64())
+                };
+                let (base_len, exp_len, mod_len) = (len(0), len(0x20), len(0x40));
+                let exp_head = match word(0x60 + base_len) {
+                    _ if exp_len == 0 => U256::zero(),
+                    Some(word) => word >> (8 * (32 - exp_len.min(32)) as usize),
+                    None => U256::MAX,
+                };
+                schedule.modexp(base_len, exp_len, mod_len, exp_head)
+            }
+            Precompiled::Bn254Add => schedule.ec_add,
+            Precompiled::Bn254ScalarMul => schedule.ec_mul,
+            Precompiled::Bn254Pairing => {
+                schedule.ec_pairing + schedule.ec_pairing_pair * len.unwrap_or(0) / 0xc0
+            }
+            Precompiled::Blake2f => {
+                let rounds = word(0).map_or(12, |word| (word >> 224usize).low_u64());
+                schedule.blake2f_round * rounds
+            }
+        }
+    }
+}
+
+/// Returns value of pure builtin `name` given its arguments, or `None` if any
+/// of them is unknown or it's not pure arithmetic.
+fn eval(name: &str, args: &[Option<U256>]) -> Option<U256> {
+    let args = args.iter().copied().collect::<Option<Vec<_>>>()?;
+    let bool = |value: bool| Some(U256::from(value as u64));
+    Some(match (name, args.as_slice()) {
+        ("add", [lhs, rhs]) => lhs.overflowing_add(*rhs).0,
+        ("sub", [lhs, rhs]) => lhs.overflowing_sub(*rhs).0,
+        ("mul", [lhs, rhs]) => lhs.overflowing_mul(*rhs).0,
+        ("div", [lhs, rhs]) => lhs.checked_div(*rhs).unwrap_or_default(),
+        ("mod", [lhs, rhs]) => lhs.checked_rem(*rhs).unwrap_or_default(),
+        ("shl", [shift, value]) => {
+            if *shift < U256::from(256) {
+                *value << shift.as_usize()
+            } else {
+                U256::zero()
+            }
+        }
+        ("shr", [shift, value]) => {
+            if *shift < U256::from(256) {
+                *value >> shift.as_usize()
+            } else {
+                U256::zero()
+            }
+        }
+        ("bitand", [lhs, rhs]) => *lhs & *rhs,
+        ("bitor", [lhs, rhs]) => *lhs | *rhs,
+        ("bitxor", [lhs, rhs]) => *lhs ^ *rhs,
+        ("bitnot", [value]) => !*value,
+        ("lt", [lhs, rhs]) => return bool(lhs < rhs),
+        ("gt", [lhs, rhs]) => return bool(lhs > rhs),
+        ("eq", [lhs, rhs]) => return bool(lhs == rhs),
+        ("iszero", [value]) => return bool(value.is_zero()),
+        ("and", [lhs, rhs]) => return bool(!lhs.is_zero() && !rhs.is_zero()),
+        ("or", [lhs, rhs]) => return bool(!lhs.is_zero() || !rhs.is_zero()),
+        ("xor", [lhs, rhs]) => return bool(lhs.is_zero() != rhs.is_zero()),
+        ("not", [value]) => return bool(value.is_zero()),
+        _ => return None,
+    })
+}
+
+/// Returns number of variables declared directly in `stmts`.
+fn num_vars(stmts: &[(usize, Stmt)]) -> usize {
+    stmts
+        .iter()
+        .map(|(_, stmt)| match stmt {
+            Stmt::Let(idents, _) => idents.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Returns variables of parameters and return values of `function` in stack
+/// `[return label, params in reverse, return values]`.
+fn iter_params(function: &Function) -> impl Iterator<Item = (String, Var)> + '_ {
+    let num_params = function.params.len();
+    function
+        .params
+        .iter()
+        .enumerate()
+        .map(move |(idx, (param, ty))| (param.clone(), Var::new(num_params - idx, *ty)))
+        .chain(
+            function
+                .returns
+                .iter()
+                .enumerate()
+                .map(move |(idx, (ret, ty))| (ret.clone(), Var::new(1 + num_params + idx, *ty))),
+        )
+}
+
+#[test]
+fn test_assemble_yul() {
+    use crate::loader::evm::{Address, ExecutorBuilder};
+
+    let code = "
+    object \"test\" {
+        code {
+            let size := datasize(\"Runtime\")
+            datacopy(0, dataoffset(\"Runtime\"), size)
+            return(0, size)
+        }
+        object \"Runtime\" {
+            code {
+                let success:bool := true
+                let f_q := 0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001
+                function pow(base, exponent) -> result {
+                    result := 1
+                    for { let i := 0 } lt(i, exponent) { i := add(i, 1) } {
+                        if eq(i, 10) { break }
+                        result := mul(result, base)
+                    }
+                }
+                let x := calldataload(0)
+                success := and(not(eq(x, 0)), success)
+                /* x^3 capped at x^10 */
+                mstore(0x00, mod(pow(x, 3), f_q))
+                mstore(0x20, pow(2, 20))
+                mstore(0x40, bitand(bitnot(0), 0xff))
+                if not(success) { revert(0, 0) }
+                return(0, 0x60)
+            }
+        }
+    }";
+    let deployment_code = assemble_yul(code).unwrap();
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let call = |x: u64| {
+        let mut calldata = [0; 32];
+        U256::from(x).to_big_endian(&mut calldata);
+        evm.call_raw(caller, verifier, calldata.to_vec().into(), 0.into())
+    };
+
+    let result = call(7);
+    assert!(!result.reverted);
+    let words = result.result.chunks(32).map(U256::from_big_endian).collect::<Vec<_>>();
+    assert_eq!(words, [U256::from(343), U256::from(1024), U256::from(0xff)]);
+    assert!(call(0).reverted);
+
+    // loops run as many iterations as their known conditions hold, and break
+    let schedule = GasSchedule::default();
+    let mut estimate = estimate_yul_gas(code, &schedule).unwrap();
+    let mut calldata = [0; 32];
+    U256::from(7).to_big_endian(&mut calldata);
+    estimate.transaction = schedule.transaction;
+    estimate.calldata = schedule.calldata(&calldata);
+    let diff = estimate.total().abs_diff(result.gas_used);
+    assert!(diff * 100 <= result.gas_used, "{estimate:?} deviates from {}", result.gas_used);
+
+    assert!(matches!(
+        assemble_yul("object \"test\" { code { mstore(0, undefined) } }"),
+        Err(AssemblerError::UndefinedIdentifier(_))
+    ));
+    assert!(matches!(
+        assemble_yul("object \"test\" { code { let x := } }"),
+        Err(AssemblerError::Syntax { .. })
+    ));
+    // logical operations and conditions take `bool`, and bitwise ones `u256`
+    for code in [
+        "let x:bool := not(1)",
+        "let x:bool := and(true, calldataload(0))",
+        "let x := bitand(true, 1)",
+        "let x := lt(1, 2)",
+        "let x:bool := 1",
+        "let x := true:u256",
+        "if calldataload(0) { }",
+        "let x:bool := true x := 0",
+        "function f(x:bool) {} f(1)",
+    ] {
+        let code = format!("object \"test\" {{ code {{ {code} }} }}");
+        assert!(matches!(assemble_yul(&code), Err(AssemblerError::TypeMismatch(_))), "{code}");
+    }
+}
+
+#[test]
+fn test_assemble_loader_yul() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine},
+        loader::{
+            evm::{encode_calldata, Address, EvmLoader, ExecutorBuilder},
+            EcPointLoader, ScalarLoader,
+        },
+        util::{
+            arithmetic::{Coordinates, Curve, CurveAffine, FieldOps, PrimeField},
+            Itertools,
+        },
+    };
+    use std::rc::Rc;
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let scalar = loader.calldataload_scalar(0);
+    let ec_point = loader.calldataload_ec_point(0x20);
+    let one = ScalarLoader::<Fr>::load_one(&loader);
+    let product = scalar.invert().unwrap() * &scalar;
+    ScalarLoader::<Fr>::assert_eq(&loader, "", &product, &one);
+    let generator = EcPointLoader::<G1Affine>::ec_point_load_const(&loader, &G1Affine::generator());
+    let product = <Rc<EvmLoader> as EcPointLoader<G1Affine>>::multi_scalar_multiplication(&[(
+        &scalar, &generator,
+    )]);
+    EcPointLoader::<G1Affine>::ec_point_assert_eq(&loader, "", &product, &ec_point);
+    let deployment_code = assemble_yul(&loader.yul_code()).unwrap();
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let call = |scalar: Fr, ec_point: G1Affine| {
+        let coordinates = Option::<Coordinates<G1Affine>>::from(ec_point.coordinates()).unwrap();
+        let ec_point = [coordinates.x(), coordinates.y()]
+            .into_iter()
+            .flat_map(|coordinate| coordinate.to_repr().into_iter().rev())
+            .collect_vec();
+        let calldata = encode_calldata(&[vec![scalar]], &ec_point);
+        !evm.call_raw(caller, verifier, calldata.into(), 0.into()).reverted
+    };
+
+    let scalar = Fr::from(7);
+    let ec_point = (G1Affine::generator() * scalar).to_affine();
+    assert!(call(scalar, ec_point));
+    assert!(!call(Fr::from(8), ec_point));
+}
+
+#[test]
+fn test_assemble_yul_source_map() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine},
+        loader::{
+            evm::{encode_calldata, Address, EvmLoader, ExecutorBuilder},
+            EcPointLoader, Loader,
+        },
+        util::arithmetic::CurveAffine,
+    };
+    use std::rc::Rc;
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let scalar = loader.calldataload_scalar(0);
+    Loader::<G1Affine>::start_cost_metering(&loader, "phase");
+    let generator = EcPointLoader::<G1Affine>::ec_point_load_const(&loader, &G1Affine::generator());
+    <Rc<EvmLoader> as EcPointLoader<G1Affine>>::multi_scalar_multiplication(&[(
+        &scalar, &generator,
+    )]);
+    Loader::<G1Affine>::end_cost_metering(&loader);
+    let (code, labels) = loader.yul_code_with_labels();
+    let (deployment_code, source_map) = assemble_yul_with_source_map(&code).unwrap();
+    assert_eq!(
+        labels.iter().map(|(_, label)| label.as_str()).collect::<Vec<_>>(),
+        ["phase", "phase > msm", "phase"]
+    );
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm =
+        ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).set_debugger(true).build();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let calldata = encode_calldata(&[vec![Fr::from(7)]], &[]);
+    let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+    assert!(!result.reverted);
+
+    let steps = result
+        .debug
+        .unwrap()
+        .flatten(0)
+        .into_iter()
+        .find(|(address, _, _)| *address == verifier)
+        .unwrap()
+        .1;
+    let locate = |op: u8| {
+        let step = steps.iter().find(|step| step.instruction.0 == op).unwrap();
+        let offset = source_map.offset(step.pc).unwrap();
+        let label = labels
+            .iter()
+            .find(|(range, _)| range.contains(&offset))
+            .map(|(_, label)| label.as_str());
+        (code[offset..].lines().next().unwrap(), label)
+    };
+
+    let (line, label) = locate(builtin("calldataload").unwrap().0);
+    assert!(line.contains("calldataload(0x0)"));
+    assert_eq!(label, None);
+    let (line, label) = locate(builtin("staticcall").unwrap().0);
+    assert!(line.starts_with("success := and(eq(staticcall(gas(), 0x7"));
+    assert_eq!(label, Some("phase > msm"));
+}
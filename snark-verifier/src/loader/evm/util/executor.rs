@@ -1,7 +1,7 @@
 //! Copied and modified from
 //! <https://github.com/foundry-rs/foundry/blob/master/evm/src/executor/mod.rs>
 
-use crate::loader::evm::{Address, H256, U256};
+use crate::loader::evm::{Address, Precompiled, H256, U256};
 use bytes::Bytes;
 use revm::{
     evm_inner, opcode, spec_opcode_gas, Account, BlockEnv, CallInputs, CallScheme, CreateInputs,
@@ -9,7 +9,12 @@ use revm::{
     InMemoryDB, Inspector, Interpreter, Memory, OpCode, Return, TransactOut, TransactTo, TxEnv,
 };
 use sha3::{Digest, Keccak256};
-use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    rc::Rc,
+};
 
 macro_rules! return_ok {
     () => {
@@ -104,6 +109,49 @@ impl<DB: Database> Inspector<DB> for LogCollector {
     }
 }
 
+/// Calls and gas used of a precompile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PrecompileUsage {
+    /// Number of calls.
+    pub calls: usize,
+    /// Gas used by the calls, excluding the cost of the call opcodes.
+    pub gas: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+struct PrecompileCollector {
+    usages: BTreeMap<Precompiled, PrecompileUsage>,
+}
+
+impl<DB: Database> Inspector<DB> for PrecompileCollector {
+    fn call(
+        &mut self,
+        _: &mut EVMData<'_, DB>,
+        call: &mut CallInputs,
+        _: bool,
+    ) -> (Return, Gas, Bytes) {
+        (Return::Continue, Gas::new(call.gas_limit), Bytes::new())
+    }
+
+    fn call_end(
+        &mut self,
+        _: &mut EVMData<'_, DB>,
+        call: &CallInputs,
+        gas: Gas,
+        status: Return,
+        retdata: Bytes,
+        _: bool,
+    ) -> (Return, Gas, Bytes) {
+        if let Some(precompile) = Precompiled::from_address(call.context.code_address) {
+            let usage = self.usages.entry(precompile).or_default();
+            usage.calls += 1;
+            usage.gas += call.gas_limit.saturating_sub(gas.remaining());
+        }
+
+        (status, gas, retdata)
+    }
+}
+
 #[derive(Clone, Debug, Copy)]
 pub enum CallKind {
     Call,
@@ -216,6 +264,12 @@ pub struct DebugStep {
     pub push_bytes: Option<Vec<u8>>,
     pub pc: usize,
     pub total_gas_used: u64,
+    /// Gas remaining in the call frame before the step.
+    pub gas_remaining: u64,
+    /// Gas charged by the step, including the gas used by the call it makes.
+    pub gas_cost: u64,
+    /// Index of the step in the whole execution across calls.
+    pub index: usize,
 }
 
 impl Default for DebugStep {
@@ -227,6 +281,9 @@ impl Default for DebugStep {
             push_bytes: None,
             pc: 0,
             total_gas_used: 0,
+            gas_remaining: 0,
+            gas_cost: 0,
+            index: 0,
         }
     }
 }
@@ -270,6 +327,11 @@ struct Debugger {
     head: usize,
     context: Address,
     gas_inspector: Rc<RefCell<GasInspector>>,
+    /// Steps waiting for `step_end` to know their gas cost, as journal depth,
+    /// node and index of the step.
+    pending: Vec<(usize, usize, usize)>,
+    /// Number of steps recorded in all nodes.
+    num_steps: usize,
 }
 
 impl Debugger {
@@ -279,6 +341,8 @@ impl Debugger {
             head: Default::default(),
             context: Default::default(),
             gas_inspector,
+            pending: Default::default(),
+            num_steps: 0,
         }
     }
 
@@ -341,14 +405,42 @@ impl<DB: Database> Inspector<DB> for Debugger {
         let spent = interpreter.gas.limit() - self.gas_inspector.borrow().gas_remaining();
         let total_gas_used = spent - (interpreter.gas.refunded() as u64).min(spent / 5);
 
-        self.arena.arena[self.head].steps.push(DebugStep {
+        let depth = data.journaled_state.depth() as usize;
+        let steps = &mut self.arena.arena[self.head].steps;
+        self.pending.push((depth, self.head, steps.len()));
+        steps.push(DebugStep {
             pc,
             stack: interpreter.stack().data().clone(),
             memory: interpreter.memory.clone(),
             instruction: Instruction(op),
             push_bytes,
             total_gas_used,
+            gas_remaining: interpreter.gas.remaining(),
+            gas_cost: 0,
+            index: self.num_steps,
         });
+        self.num_steps += 1;
+
+        Return::Continue
+    }
+
+    fn step_end(
+        &mut self,
+        interpreter: &mut Interpreter,
+        data: &mut EVMData<'_, DB>,
+        _is_static: bool,
+        _status: Return,
+    ) -> Return {
+        // Drop steps of frames already returned, in case their last step
+        // doesn't end.
+        let depth = data.journaled_state.depth() as usize;
+        while matches!(self.pending.last(), Some((pending, _, _)) if *pending > depth) {
+            self.pending.pop();
+        }
+        if let Some((_, node, idx)) = self.pending.pop() {
+            let step = &mut self.arena.arena[node].steps[idx];
+            step.gas_cost = step.gas_remaining.saturating_sub(interpreter.gas.remaining());
+        }
 
         Return::Continue
     }
@@ -429,6 +521,7 @@ macro_rules! call_inspectors {
 
 struct InspectorData {
     logs: Vec<Log>,
+    precompiles: BTreeMap<Precompiled, PrecompileUsage>,
     debug: Option<DebugArena>,
 }
 
@@ -436,6 +529,7 @@ struct InspectorData {
 struct InspectorStack {
     gas: Option<Rc<RefCell<GasInspector>>>,
     logs: Option<LogCollector>,
+    precompiles: Option<PrecompileCollector>,
     debugger: Option<Debugger>,
 }
 
@@ -443,6 +537,10 @@ impl InspectorStack {
     fn collect_inspector_states(self) -> InspectorData {
         InspectorData {
             logs: self.logs.map(|logs| logs.logs).unwrap_or_default(),
+            precompiles: self
+                .precompiles
+                .map(|precompiles| precompiles.usages)
+                .unwrap_or_default(),
             debug: self.debugger.map(|debugger| debugger.arena),
         }
     }
@@ -548,6 +646,7 @@ impl<DB: Database> Inspector<DB> for InspectorStack {
             [
                 &mut self.gas.as_deref().map(|gas| gas.borrow_mut()),
                 &mut self.logs,
+                &mut self.precompiles,
                 &mut self.debugger
             ],
             {
@@ -576,6 +675,7 @@ impl<DB: Database> Inspector<DB> for InspectorStack {
             [
                 &mut self.gas.as_deref().map(|gas| gas.borrow_mut()),
                 &mut self.logs,
+                &mut self.precompiles,
                 &mut self.debugger
             ],
             {
@@ -684,6 +784,8 @@ pub struct RawCallResult {
     pub gas_refunded: u64,
     /// Logs emitted during the call
     pub logs: Vec<Log>,
+    /// Calls and gas used of each precompile called
+    pub precompiles: BTreeMap<Precompiled, PrecompileUsage>,
     /// Debug information if any
     pub debug: Option<DebugArena>,
     /// State changes if any
@@ -813,7 +915,11 @@ impl Executor {
             TransactOut::Call(ref data) => data.to_owned(),
             _ => Bytes::default(),
         };
-        let InspectorData { logs, debug } = inspector.collect_inspector_states();
+        let InspectorData {
+            logs,
+            precompiles,
+            debug,
+        } = inspector.collect_inspector_states();
 
         RawCallResult {
             exit_reason,
@@ -822,6 +928,7 @@ impl Executor {
             gas_used,
             gas_refunded,
             logs: logs.to_vec(),
+            precompiles,
             debug,
             state_changeset: Some(state_changeset.into_iter().collect()),
             env,
@@ -839,6 +946,7 @@ impl Executor {
     fn inspector(&self) -> InspectorStack {
         let mut stack = InspectorStack {
             logs: Some(LogCollector::default()),
+            precompiles: Some(PrecompileCollector::default()),
             ..Default::default()
         };
         if self.debugger {
@@ -873,3 +981,57 @@ impl Executor {
         }
     }
 }
+
+#[test]
+fn test_precompile_usage() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine},
+        loader::{
+            evm::{assemble_yul, encode_calldata, EvmLoader},
+            EcPointLoader,
+        },
+        util::arithmetic::{CurveAffine, FieldOps},
+    };
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let scalar = loader.calldataload_scalar(0);
+    let inverse = scalar.invert().unwrap();
+    let generator =
+        EcPointLoader::<G1Affine>::ec_point_load_const(&loader, &G1Affine::generator());
+    <Rc<EvmLoader> as EcPointLoader<G1Affine>>::multi_scalar_multiplication(&[
+        (&scalar, &generator),
+        (&(inverse.clone() * &inverse), &generator),
+    ]);
+    let deployment_code = assemble_yul(&loader.yul_code()).unwrap();
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default()
+        .with_gas_limit(u64::MAX.into())
+        .build();
+    let verifier = evm
+        .deploy(caller, deployment_code.into(), 0.into())
+        .address
+        .unwrap();
+    let calldata = encode_calldata(&[vec![Fr::from(7)]], &[]);
+    let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+    assert!(!result.reverted);
+
+    let usage = |precompile| result.precompiles.get(&precompile).copied().unwrap_or_default();
+    assert_eq!(usage(Precompiled::BigModExp).calls, 1);
+    assert!(usage(Precompiled::BigModExp).gas > 0);
+    assert_eq!(
+        usage(Precompiled::Bn254ScalarMul),
+        PrecompileUsage {
+            calls: 2,
+            gas: 12000
+        }
+    );
+    assert_eq!(
+        usage(Precompiled::Bn254Add),
+        PrecompileUsage {
+            calls: 1,
+            gas: 150
+        }
+    );
+    assert_eq!(usage(Precompiled::Bn254Pairing), PrecompileUsage::default());
+}
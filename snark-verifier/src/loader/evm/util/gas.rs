@@ -0,0 +1,292 @@
+//! Estimation of gas cost of verifier generated by
+//! [`EvmLoader`], by pricing the Yul code it generates with the EVM gas
+//! schedule instead of compiling and running it.
+
+use crate::{
+    loader::evm::{
+        util::{assembler::estimate_yul_gas, executor::PrecompileUsage},
+        EvmLoader, Precompiled, U256,
+    },
+    system::halo2::transcript::evm::EvmTranscript,
+    util::arithmetic::{CurveAffine, PrimeField},
+    verifier::{plonk::PlonkProtocol, SnarkVerifier},
+    Error,
+};
+use std::{collections::BTreeMap, rc::Rc};
+
+/// Gas schedule of EVM, which defaults to the one since Berlin as the bundled
+/// executor runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GasSchedule {
+    /// Base cost of transaction.
+    pub transaction: u64,
+    /// Cost of zero byte of calldata.
+    pub calldata_zero_byte: u64,
+    /// Cost of non-zero byte of calldata.
+    pub calldata_non_zero_byte: u64,
+    /// Linear cost of memory per word.
+    pub memory_word: u64,
+    /// Divisor of quadratic cost of memory, which is `words^2 / divisor`.
+    pub memory_quad_divisor: u64,
+    /// Cost of copying per word, e.g. by `CALLDATACOPY`.
+    pub copy_word: u64,
+    /// Base cost of `KECCAK256`.
+    pub keccak: u64,
+    /// Cost of `KECCAK256` per word hashed.
+    pub keccak_word: u64,
+    /// Cost of `EXP` per byte of exponent.
+    pub exp_byte: u64,
+    /// Base cost of `LOG0`.
+    pub log: u64,
+    /// Cost of `LOG*` per topic.
+    pub log_topic: u64,
+    /// Cost of `LOG*` per byte of data.
+    pub log_data_byte: u64,
+    /// Cost of accessing a warm account or storage slot, which is the cost of
+    /// calling a precompile.
+    pub warm_access: u64,
+    /// Cost of `ecAdd`.
+    pub ec_add: u64,
+    /// Cost of `ecMul`.
+    pub ec_mul: u64,
+    /// Base cost of `ecPairing`.
+    pub ec_pairing: u64,
+    /// Cost of `ecPairing` per pair.
+    pub ec_pairing_pair: u64,
+    /// Minimal cost of `modexp`.
+    pub modexp_min: u64,
+    /// Divisor of cost of `modexp` in EIP-2565.
+    pub modexp_divisor: u64,
+    /// Cost of `blake2f` per round.
+    pub blake2f_round: u64,
+}
+
+impl Default for GasSchedule {
+    fn default() -> Self {
+        Self {
+            transaction: 21000,
+            calldata_zero_byte: 4,
+            calldata_non_zero_byte: 16,
+            memory_word: 3,
+            memory_quad_divisor: 512,
+            copy_word: 3,
+            keccak: 30,
+            keccak_word: 6,
+            exp_byte: 50,
+            log: 375,
+            log_topic: 375,
+            log_data_byte: 8,
+            warm_access: 100,
+            ec_add: 150,
+            ec_mul: 6000,
+            ec_pairing: 45000,
+            ec_pairing_pair: 34000,
+            modexp_min: 200,
+            modexp_divisor: 3,
+            blake2f_round: 1,
+        }
+    }
+}
+
+impl GasSchedule {
+    /// Returns static cost of opcode `op`, assuming accounts and storage slots
+    /// it accesses are warm, or zero if it's priced only dynamically.
+    pub fn opcode(&self, op: u8) -> u64 {
+        match op {
+            0x5b => 1,
+            0x30 | 0x32..=0x34 | 0x36 | 0x38 | 0x3a | 0x3d | 0x41..=0x46 | 0x48 | 0x50
+            | 0x58..=0x5a => 2,
+            0x01 | 0x03 | 0x10..=0x1d | 0x35 | 0x37 | 0x39 | 0x3e | 0x51..=0x53 | 0x60..=0x9f => 3,
+            0x02 | 0x04..=0x07 | 0x0b | 0x47 => 5,
+            0x08 | 0x09 | 0x56 => 8,
+            0x0a | 0x57 => 10,
+            0x40 => 20,
+            0x20 => self.keccak,
+            0x31 | 0x3b | 0x3c | 0x3f | 0x54 | 0xf1 | 0xf2 | 0xf4 | 0xfa => self.warm_access,
+            0xa0..=0xa4 => self.log + self.log_topic * (op - 0xa0) as u64,
+            _ => 0,
+        }
+    }
+
+    /// Returns cost of `calldata`.
+    pub fn calldata(&self, calldata: &[u8]) -> u64 {
+        calldata
+            .iter()
+            .map(|byte| {
+                if *byte == 0 {
+                    self.calldata_zero_byte
+                } else {
+                    self.calldata_non_zero_byte
+                }
+            })
+            .sum()
+    }
+
+    /// Returns cost of expanding memory from empty to `size` bytes.
+    pub fn memory(&self, size: u64) -> u64 {
+        let words = (size + 31) / 32;
+        self.memory_word * words + words * words / self.memory_quad_divisor
+    }
+
+    /// Returns cost of `modexp` with given lengths of operands in bytes and
+    /// the first 32 bytes of exponent, as EIP-2565 specifies.
+    pub fn modexp(&self, base_len: u64, exp_len: u64, mod_len: u64, exp_head: U256) -> u64 {
+        let words = (base_len.max(mod_len) + 7) / 8;
+        let head_bits = (exp_head.bits() as u64).saturating_sub(1);
+        let iterations = if exp_len <= 32 { head_bits } else { 8 * (exp_len - 32) + head_bits };
+        (words * words * iterations.max(1) / self.modexp_divisor).max(self.modexp_min)
+    }
+}
+
+/// Estimated gas cost of a call to verifier, broken down by its source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GasEstimate {
+    /// Base cost of transaction.
+    pub transaction: u64,
+    /// Cost of calldata.
+    pub calldata: u64,
+    /// Cost of executing opcodes, except the ones counted by the fields below.
+    pub execution: u64,
+    /// Cost of expanding memory.
+    pub memory: u64,
+    /// Cost of `KECCAK256`.
+    pub keccak: u64,
+    /// Calls to and gas used by each precompile, excluding the cost of the
+    /// call opcodes as the executor measures.
+    pub precompiles: BTreeMap<Precompiled, PrecompileUsage>,
+}
+
+impl GasEstimate {
+    /// Returns total gas cost.
+    pub fn total(&self) -> u64 {
+        self.transaction
+            + self.calldata
+            + self.execution
+            + self.memory
+            + self.keccak
+            + self.precompiles.values().map(|usage| usage.gas).sum::<u64>()
+    }
+}
+
+/// Estimates gas cost of verifying a proof of `protocol` by the verifier
+/// generated by [`EvmLoader`] with [`EvmTranscript`] for `V` (e.g.
+/// [`PlonkVerifier`](crate::verifier::plonk::PlonkVerifier) with the
+/// multi-open scheme to choose), without compiling it.
+///
+/// If `calldata` is given, it's priced byte by byte. Otherwise only its length
+/// is known from `protocol`, i.e. the instances and proof the verifier reads,
+/// so it's priced as if every byte were non-zero, which is an upper bound.
+///
+/// The verifier is priced as [`estimate_yul_gas`] does, so it's close to what
+/// the executor measures for the code from
+/// [`assemble_yul`](super::assemble_yul). The code compiled by `solc` is
+/// optimized so usually a bit cheaper, within 10% for a small verifier as
+/// `test_estimate_evm_verifier_gas` of `snark-verifier-sdk` checks.
+pub fn estimate_verifier_gas<C, V>(
+    vk: &V::VerifyingKey,
+    protocol: &PlonkProtocol<C>,
+    calldata: Option<&[u8]>,
+    schedule: &GasSchedule,
+) -> Result<GasEstimate, Error>
+where
+    C: CurveAffine,
+    C::Base: PrimeField<Repr = [u8; 0x20]>,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+    V: SnarkVerifier<C, Rc<EvmLoader>, Protocol = PlonkProtocol<C, Rc<EvmLoader>>>,
+{
+    let loader = EvmLoader::new::<C::Base, C::Scalar>();
+    let protocol = protocol.loaded(&loader);
+    let mut transcript = EvmTranscript::<C, Rc<EvmLoader>, _, _>::new(&loader);
+
+    let instances = transcript.load_instances(protocol.num_instance.clone());
+    let proof = V::read_proof(vk, &protocol, &instances, &mut transcript)?;
+    transcript.check_proof_len();
+    V::verify(vk, &protocol, &instances, &proof)?;
+
+    let mut estimate = estimate_yul_gas(&loader.yul_code(), schedule)
+        .expect("Yul generated by EvmLoader should be supported");
+    estimate.transaction = schedule.transaction;
+    estimate.calldata = match calldata {
+        Some(calldata) => schedule.calldata(calldata),
+        None => schedule.calldata_non_zero_byte * transcript.offset().offset() as u64,
+    };
+    Ok(estimate)
+}
+
+#[test]
+fn test_estimate_yul_gas() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine, G2Affine},
+        loader::{
+            evm::{assemble_yul, encode_calldata, fe_to_u256, Address, ExecutorBuilder},
+            EcPointLoader,
+        },
+        util::{
+            arithmetic::FieldOps,
+            transcript::{Transcript, TranscriptRead},
+        },
+    };
+
+    // reads instances and proof as a verifier does, then checks the pairing
+    // e(g1, g2) * e(g1, -g2) = 1
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let mut transcript = EvmTranscript::<G1Affine, Rc<EvmLoader>, _, _>::new(&loader);
+    let instances = transcript.load_instances(vec![2]);
+    let ec_points = [(); 2].map(|_| transcript.read_ec_point().unwrap());
+    let challenge = transcript.squeeze_challenge();
+    let scalar = transcript.read_scalar().unwrap();
+    transcript.check_proof_len();
+    let inverse = (challenge * &scalar + &instances[0][1]).invert().unwrap();
+    <Rc<EvmLoader> as EcPointLoader<G1Affine>>::multi_scalar_multiplication(&[
+        (&inverse, &ec_points[0]),
+        (&instances[0][0], &ec_points[1]),
+    ]);
+    let g1 = EcPointLoader::<G1Affine>::ec_point_load_const(&loader, &G1Affine::generator());
+    let g2_to_u256s = |ec_point: G2Affine| {
+        let coordinates = ec_point.coordinates().unwrap();
+        let [x, y] = [coordinates.x().to_repr(), coordinates.y().to_repr()];
+        (
+            U256::from_little_endian(&x.as_ref()[32..]),
+            U256::from_little_endian(&x.as_ref()[..32]),
+            U256::from_little_endian(&y.as_ref()[32..]),
+            U256::from_little_endian(&y.as_ref()[..32]),
+        )
+    };
+    let g2 = G2Affine::generator();
+    loader.pairing(&g1, g2_to_u256s(g2), &g1, g2_to_u256s(-g2));
+    let yul_code = loader.yul_code();
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let deployment_code = assemble_yul(&yul_code).unwrap();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let proof = [G1Affine::generator(), (G1Affine::generator() + G1Affine::generator()).into()]
+        .iter()
+        .flat_map(|ec_point| {
+            let coordinates = ec_point.coordinates().unwrap();
+            [*coordinates.x(), *coordinates.y()].map(|coordinate| {
+                let mut bytes = [0; 32];
+                fe_to_u256(coordinate).to_big_endian(&mut bytes);
+                bytes
+            })
+        })
+        .flatten()
+        .chain({
+            let mut bytes = [0; 32];
+            fe_to_u256(Fr::from(5)).to_big_endian(&mut bytes);
+            bytes
+        })
+        .collect::<Vec<_>>();
+    let calldata = encode_calldata(&[vec![Fr::from(3), Fr::from(7)]], &proof);
+    let result = evm.call_raw(caller, verifier, calldata.clone().into(), 0.into());
+    assert!(!result.reverted);
+
+    let schedule = GasSchedule::default();
+    let mut estimate = estimate_yul_gas(&yul_code, &schedule).unwrap();
+    estimate.transaction = schedule.transaction;
+    estimate.calldata = schedule.calldata(&calldata);
+    assert_eq!(estimate.precompiles, result.precompiles);
+    assert_eq!(estimate.precompiles[&Precompiled::Bn254Pairing].gas, 45000 + 2 * 34000);
+    let diff = estimate.total().abs_diff(result.gas_used);
+    assert!(diff * 100 <= result.gas_used, "{estimate:?} deviates from {}", result.gas_used);
+}
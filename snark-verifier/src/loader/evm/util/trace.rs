@@ -0,0 +1,324 @@
+//! Non-interactive export of execution trace recorded by the debugger of
+//! [`ExecutorBuilder`](super::ExecutorBuilder), as an alternative to the
+//! interactive one in tests.
+
+use crate::{
+    loader::evm::{
+        util::executor::{DebugArena, RawCallResult},
+        Address, H256, U256,
+    },
+    util::Itertools,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Step of execution in the `structLog` style of `debug_traceTransaction`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructLog {
+    /// Address of the executing contract.
+    pub address: Address,
+    /// Program counter.
+    pub pc: usize,
+    /// Name of the opcode.
+    pub op: String,
+    /// Gas remaining before the step.
+    pub gas: u64,
+    /// Gas charged by the step, including the gas used by the call it makes.
+    pub gas_cost: u64,
+    /// Call depth, which starts from `1`.
+    pub depth: usize,
+    /// Stack before the step, where the top is the last.
+    pub stack: Vec<U256>,
+    /// Words of memory before the step which are changed since the previous
+    /// step in the same call, as offset and value.
+    pub memory_diff: Vec<(usize, H256)>,
+}
+
+impl DebugArena {
+    /// Returns steps of all calls in the order executed, where steps of a
+    /// call follow right after the step making it.
+    pub fn struct_logs(&self) -> Vec<StructLog> {
+        let steps = self
+            .arena
+            .iter()
+            .flat_map(|node| node.steps.iter().map(move |step| (node, step)))
+            .sorted_by_key(|(_, step)| step.index);
+
+        // Memory of the call at each depth, which is reset when a new call is
+        // entered.
+        let mut memories = Vec::<Vec<u8>>::new();
+        let mut struct_logs = Vec::<StructLog>::new();
+        for (node, step) in steps {
+            let depth = node.depth + 1;
+            if struct_logs.last().map_or(true, |last| last.depth < depth) {
+                memories.truncate(depth - 1);
+                memories.resize(depth, Vec::new());
+            } else {
+                memories.truncate(depth);
+            }
+            let memory = step.memory.data();
+            let prev = &memories[depth - 1];
+            let memory_diff = memory
+                .chunks(32)
+                .enumerate()
+                .filter(|(idx, word)| prev.get(idx * 32..(idx + 1) * 32) != Some(*word))
+                .map(|(idx, word)| {
+                    let mut value = [0; 32];
+                    value[..word.len()].copy_from_slice(word);
+                    (idx * 32, H256(value))
+                })
+                .collect();
+            memories[depth - 1] = memory.to_vec();
+
+            struct_logs.push(StructLog {
+                address: node.address,
+                pc: step.pc,
+                op: step.instruction.to_string(),
+                gas: step.gas_remaining,
+                gas_cost: step.gas_cost,
+                depth,
+                stack: step.stack.clone(),
+                memory_diff,
+            });
+        }
+        struct_logs
+    }
+
+    /// Returns gas used by steps in the folded stacks format of flamegraph
+    /// tools, where each line is `frame;...;frame gas`.
+    ///
+    /// The frames of a step are the addresses of calls to it, followed by the
+    /// ones returned by `frames` given address and program counter (e.g. Yul
+    /// labels of [`SourceMap`](super::SourceMap)), and the opcode. Gas of call
+    /// is counted in the callee's steps, except the one not executing code
+    /// like precompile.
+    pub fn folded_stacks(&self, frames: impl Fn(Address, usize) -> Vec<String>) -> String {
+        let struct_logs = self.struct_logs();
+
+        let mut gas = struct_logs.iter().map(|struct_log| struct_log.gas_cost).collect_vec();
+        let mut callers = Vec::<usize>::new();
+        for (idx, struct_log) in struct_logs.iter().enumerate() {
+            while let Some(caller) = callers.last() {
+                if struct_logs[*caller].depth < struct_log.depth {
+                    break;
+                }
+                callers.pop();
+            }
+            if let Some(caller) = callers.last() {
+                gas[*caller] = gas[*caller].saturating_sub(struct_log.gas_cost);
+            }
+            callers.push(idx);
+        }
+
+        let mut calls = Vec::<String>::new();
+        let mut folded = BTreeMap::<String, u64>::new();
+        for (struct_log, gas) in struct_logs.iter().zip(gas) {
+            calls.truncate(struct_log.depth - 1);
+            calls.push(format!("{:?}", struct_log.address));
+            let stack = calls
+                .iter()
+                .cloned()
+                .chain(frames(struct_log.address, struct_log.pc))
+                .chain(Some(struct_log.op.clone()))
+                .join(";");
+            *folded.entry(stack).or_default() += gas;
+        }
+
+        folded
+            .into_iter()
+            .filter(|(_, gas)| *gas > 0)
+            .map(|(stack, gas)| format!("{stack} {gas}\n"))
+            .collect()
+    }
+}
+
+impl RawCallResult {
+    /// Returns trace of the call in the JSON format of `debug_traceTransaction`
+    /// with the default struct logger, where memory of each struct log is
+    /// replaced by `memoryDiff` of [`StructLog`], or `None` if the debugger
+    /// is not enabled.
+    pub fn trace_json(&self) -> Option<String> {
+        let struct_logs = self
+            .debug
+            .as_ref()?
+            .struct_logs()
+            .into_iter()
+            .map(|struct_log| StructLogJson {
+                pc: struct_log.pc,
+                op: struct_log.op,
+                gas: struct_log.gas,
+                gas_cost: struct_log.gas_cost,
+                depth: struct_log.depth,
+                stack: struct_log.stack.iter().map(|value| format!("{value:#x}")).collect(),
+                memory_diff: struct_log
+                    .memory_diff
+                    .iter()
+                    .map(|(offset, value)| (format!("{offset:#x}"), hex::encode(value)))
+                    .collect(),
+            })
+            .collect();
+        let trace = TraceJson {
+            gas: self.gas_used,
+            failed: self.reverted,
+            return_value: hex::encode(&self.result),
+            struct_logs,
+        };
+        Some(serde_json::to_string(&trace).unwrap())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceJson {
+    gas: u64,
+    failed: bool,
+    return_value: String,
+    struct_logs: Vec<StructLogJson>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StructLogJson {
+    pc: usize,
+    op: String,
+    gas: u64,
+    gas_cost: u64,
+    depth: usize,
+    stack: Vec<String>,
+    memory_diff: BTreeMap<String, String>,
+}
+
+#[test]
+fn test_trace() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine},
+        loader::{
+            evm::{assemble_yul, encode_calldata, EvmLoader, ExecutorBuilder},
+            EcPointLoader,
+        },
+        util::arithmetic::CurveAffine,
+    };
+    use std::rc::Rc;
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let scalar = loader.calldataload_scalar(0);
+    let generator = EcPointLoader::<G1Affine>::ec_point_load_const(&loader, &G1Affine::generator());
+    <Rc<EvmLoader> as EcPointLoader<G1Affine>>::multi_scalar_multiplication(&[(
+        &scalar, &generator,
+    )]);
+    let deployment_code = assemble_yul(&loader.yul_code()).unwrap();
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm =
+        ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).set_debugger(true).build();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let calldata = encode_calldata(&[vec![Fr::from(7)]], &[]);
+    let intrinsic_gas =
+        21000 + calldata.iter().map(|byte| if *byte == 0 { 4 } else { 16 }).sum::<u64>();
+    let result = evm.call_raw(caller, verifier, calldata.into(), 0.into());
+    assert!(!result.reverted);
+
+    let struct_logs = result.debug.as_ref().unwrap().struct_logs();
+    assert_eq!((struct_logs[0].pc, struct_logs[0].depth), (0, 1));
+    assert!(struct_logs.iter().all(|struct_log| struct_log.address == verifier));
+    let mstore = struct_logs.iter().position(|struct_log| struct_log.op == "MSTORE").unwrap();
+    let stack = &struct_logs[mstore].stack;
+    let (offset, value) = (stack[stack.len() - 1], stack[stack.len() - 2]);
+    let mut word = [0; 32];
+    value.to_big_endian(&mut word);
+    assert!(struct_logs[mstore + 1].memory_diff.contains(&(offset.as_usize(), H256(word))));
+    let gas_cost = struct_logs.iter().map(|struct_log| struct_log.gas_cost).sum::<u64>();
+    assert_eq!(intrinsic_gas + gas_cost, result.gas_used);
+
+    let folded = result.debug.as_ref().unwrap().folded_stacks(|_, _| Vec::new());
+    let line = folded.lines().find(|line| line.contains("STATICCALL")).unwrap();
+    let (frames, gas) = line.split_once(' ').unwrap();
+    assert_eq!(frames, format!("{verifier:?};STATICCALL"));
+    assert!(gas.parse::<u64>().unwrap() >= 6000);
+    let total = folded.lines().map(|line| line.rsplit_once(' ').unwrap().1.parse::<u64>().unwrap());
+    assert_eq!(total.sum::<u64>(), gas_cost);
+
+    let trace: serde_json::Value = serde_json::from_str(&result.trace_json().unwrap()).unwrap();
+    assert_eq!(trace["gas"], result.gas_used);
+    assert_eq!(trace["structLogs"].as_array().unwrap().len(), struct_logs.len());
+    assert_eq!(trace["structLogs"][mstore]["op"], "MSTORE");
+    assert_eq!(trace["structLogs"][mstore]["stack"][stack.len() - 1], format!("{offset:#x}"));
+}
+
+#[test]
+fn test_trace_nested_calls() {
+    use crate::loader::evm::{assemble_yul, ExecutorBuilder};
+
+    // runtime `code` deployed as is
+    let contract = |code: String| {
+        format!(
+            "object \"contract\" {{
+                code {{
+                    datacopy(0, dataoffset(\"runtime\"), datasize(\"runtime\"))
+                    return(0, datasize(\"runtime\"))
+                }}
+                object \"runtime\" {{ code {{ {code} }} }}
+            }}"
+        )
+    };
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm =
+        ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).set_debugger(true).build();
+    let mut deploy = |code: String| {
+        let deployment_code = assemble_yul(&contract(code)).unwrap();
+        evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap()
+    };
+    // a calls b, which calls c
+    let c = deploy("mstore(0, 3) return(0, 0x20)".to_string());
+    let b = deploy(format!(
+        "pop(staticcall(gas(), {c:?}, 0, 0, 0, 0x20)) mstore(0x20, 4) return(0, 0x40)"
+    ));
+    let a = deploy(format!(
+        "mstore(0, 1) pop(staticcall(gas(), {b:?}, 0, 0, 0, 0x40)) mstore(0x40, 2) return(0, 0x60)"
+    ));
+    let result = evm.call_raw(caller, a, Vec::new().into(), 0.into());
+    assert!(!result.reverted);
+
+    let struct_logs = result.debug.as_ref().unwrap().struct_logs();
+    let addresses = [a, b, c];
+    for (prev, next) in struct_logs.iter().tuple_windows() {
+        assert_eq!(next.address, addresses[next.depth - 1]);
+        match next.depth as isize - prev.depth as isize {
+            // steps of callee follow right after the call
+            1 => {
+                assert_eq!(prev.op, "STATICCALL");
+                assert_eq!(next.pc, 0);
+            }
+            0 => {}
+            -1 => assert_eq!(prev.op, "RETURN"),
+            _ => unreachable!(),
+        }
+    }
+    let depths = struct_logs.iter().map(|struct_log| struct_log.depth).dedup().collect_vec();
+    assert_eq!(depths, [1, 2, 3, 2, 1]);
+
+    // a continues after the call with its own memory, and return data of b
+    let call = struct_logs.iter().position(|struct_log| struct_log.op == "STATICCALL").unwrap();
+    let resumed = struct_logs[call + 1..].iter().find(|struct_log| struct_log.depth == 1).unwrap();
+    assert_eq!(resumed.pc, struct_logs[call].pc + 1);
+    let word = |value: u64| {
+        let mut word = [0; 32];
+        U256::from(value).to_big_endian(&mut word);
+        H256(word)
+    };
+    assert_eq!(resumed.memory_diff, [(0, word(3)), (0x20, word(4))]);
+
+    let gas_cost = struct_logs.iter().map(|struct_log| struct_log.gas_cost).sum::<u64>();
+    let folded = result.debug.as_ref().unwrap().folded_stacks(|_, _| Vec::new());
+    assert!(folded.lines().any(|line| line.starts_with(&format!("{a:?};{b:?};{c:?};MSTORE "))));
+    let total = folded.lines().map(|line| line.rsplit_once(' ').unwrap().1.parse::<u64>().unwrap());
+    assert!(total.sum::<u64>() <= gas_cost);
+
+    let trace: serde_json::Value = serde_json::from_str(&result.trace_json().unwrap()).unwrap();
+    let trace_depths = trace["structLogs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|struct_log| struct_log["depth"].as_u64().unwrap() as usize);
+    assert!(trace_depths.eq(struct_logs.iter().map(|struct_log| struct_log.depth)));
+}
@@ -0,0 +1,172 @@
+use crate::{
+    loader::evm::{fe_to_u256, Address, EvmCodeSizeError, MAX_RUNTIME_SIZE, U256},
+    pcs::kzg::KzgDecidingKey,
+    util::arithmetic::{CurveAffine, MultiMillerLoop, PrimeField},
+    verifier::plonk::PlonkProtocol,
+};
+use std::iter;
+
+/// Constants of a protocol placed in a verifying key contract instead of being
+/// inlined into the verifier.
+///
+/// The verifying key contract forwards every call to the verifier by
+/// `DELEGATECALL`, and the verifier reads the constants appended to the code of
+/// the verifying key contract by `EXTCODECOPY` (see
+/// [`EvmLoader::load_verifying_key`](super::EvmLoader::load_verifying_key)). So
+/// protocols which only differ in these constants could share the same
+/// verifier.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EvmVerifyingKey {
+    /// Prover and verifier common initial state, or zero if there is none.
+    pub transcript_initial_state: U256,
+    /// Size of the domain.
+    pub n: U256,
+    /// Generator of the domain.
+    pub omega: U256,
+    /// Generator on G2.
+    pub g2: (U256, U256, U256, U256),
+    /// Negated generator to the trusted-setup secret on G2.
+    pub minus_s_g2: (U256, U256, U256, U256),
+    /// Commitments of preprocessed polynomials.
+    pub preprocessed: Vec<(U256, U256)>,
+}
+
+impl EvmVerifyingKey {
+    pub(crate) const TRANSCRIPT_INITIAL_STATE_OFFSET: usize = 0x00;
+    pub(crate) const N_OFFSET: usize = 0x20;
+    pub(crate) const OMEGA_OFFSET: usize = 0x40;
+    pub(crate) const G2_OFFSET: usize = 0x60;
+    pub(crate) const MINUS_S_G2_OFFSET: usize = 0xe0;
+    pub(crate) const PREPROCESSED_OFFSET: usize = 0x160;
+
+    /// Initialize an [`EvmVerifyingKey`] with constants of `protocol` and
+    /// deciding key `dk`.
+    pub fn new<M>(protocol: &PlonkProtocol<M::G1Affine>, dk: &KzgDecidingKey<M>) -> Self
+    where
+        M: MultiMillerLoop,
+        M::Scalar: PrimeField<Repr = [u8; 0x20]>,
+    {
+        let preprocessed = protocol
+            .preprocessed
+            .iter()
+            .map(|preprocessed| {
+                let coordinates = preprocessed.coordinates().unwrap();
+                let [x, y] = [coordinates.x(), coordinates.y()]
+                    .map(|coordinate| U256::from_little_endian(coordinate.to_repr().as_ref()));
+                (x, y)
+            })
+            .collect();
+        Self {
+            transcript_initial_state: protocol
+                .transcript_initial_state
+                .map(fe_to_u256)
+                .unwrap_or_default(),
+            n: protocol.domain.n.into(),
+            omega: fe_to_u256(protocol.domain.gen),
+            g2: g2_to_u256s::<M>(dk.g2()),
+            minus_s_g2: g2_to_u256s::<M>(-dk.s_g2()),
+            preprocessed,
+        }
+    }
+
+    /// Returns size in bytes.
+    pub fn size(&self) -> usize {
+        Self::PREPROCESSED_OFFSET + self.preprocessed.len() * 0x40
+    }
+
+    /// Returns constants as big-endian words in the layout read by verifier.
+    pub fn to_words(&self) -> Vec<U256> {
+        let (g2_0, g2_1, g2_2, g2_3) = self.g2;
+        let (minus_s_g2_0, minus_s_g2_1, minus_s_g2_2, minus_s_g2_3) = self.minus_s_g2;
+        iter::empty()
+            .chain([self.transcript_initial_state, self.n, self.omega])
+            .chain([g2_0, g2_1, g2_2, g2_3])
+            .chain([minus_s_g2_0, minus_s_g2_1, minus_s_g2_2, minus_s_g2_3])
+            .chain(self.preprocessed.iter().flat_map(|&(x, y)| [x, y]))
+            .collect()
+    }
+
+    /// Returns deployment code of the verifying key contract, which forwards
+    /// every call to `verifier` by `DELEGATECALL` and has constants appended to
+    /// its runtime code.
+    ///
+    /// Returns [`EvmCodeSizeError`] if the runtime code exceeds
+    /// [`MAX_RUNTIME_SIZE`], then the contract is not deployable on chains
+    /// enforcing EIP-170.
+    pub fn deployment_code(&self, verifier: Address) -> Result<Vec<u8>, EvmCodeSizeError> {
+        // calldatacopy(0, 0, calldatasize())
+        // let success := delegatecall(gas(), verifier, 0, calldatasize(), 0, 0)
+        // returndatacopy(0, 0, returndatasize())
+        // if iszero(success) { revert(0, returndatasize()) }
+        // return(0, returndatasize())
+        let runtime_code = iter::empty()
+            .chain([0x36, 0x60, 0x00, 0x80, 0x37])
+            .chain([0x60, 0x00, 0x60, 0x00, 0x36, 0x60, 0x00, 0x73])
+            .chain(verifier.as_bytes().iter().copied())
+            .chain([0x5a, 0xf4])
+            .chain([0x3d, 0x60, 0x00, 0x80, 0x3e])
+            .chain([0x3d, 0x90, 0x60, 0x00, 0x90, 0x60, 0x31, 0x57, 0xfd, 0x5b, 0xf3])
+            .chain(self.to_words().iter().flat_map(|word| {
+                let mut bytes = [0; 32];
+                word.to_big_endian(&mut bytes);
+                bytes
+            }))
+            .collect::<Vec<u8>>();
+        let runtime_size = runtime_code.len();
+
+        // codecopy(0, 0x0d, len)
+        // return(0, len)
+        let [_, len_hi, len_mid, len_lo] = (runtime_size as u32).to_be_bytes();
+        let deployment_code = iter::empty()
+            .chain([0x62, len_hi, len_mid, len_lo, 0x80, 0x60, 0x0d, 0x60, 0x00, 0x39])
+            .chain([0x60, 0x00, 0xf3])
+            .chain(runtime_code)
+            .collect();
+        if runtime_size > MAX_RUNTIME_SIZE {
+            return Err(EvmCodeSizeError { runtime_size, deployment_code });
+        }
+        Ok(deployment_code)
+    }
+}
+
+/// Returns coordinates of a point on G2 as big-endian words in the order the
+/// pairing precompile takes.
+pub(crate) fn g2_to_u256s<M: MultiMillerLoop>(ec_point: M::G2Affine) -> (U256, U256, U256, U256) {
+    let coordinates = ec_point.coordinates().unwrap();
+    let x = coordinates.x().to_repr();
+    let y = coordinates.y().to_repr();
+    (
+        U256::from_little_endian(&x.as_ref()[32..]),
+        U256::from_little_endian(&x.as_ref()[..32]),
+        U256::from_little_endian(&y.as_ref()[32..]),
+        U256::from_little_endian(&y.as_ref()[..32]),
+    )
+}
+
+#[test]
+fn test_deployment_code_size() {
+    use crate::loader::evm::ExecutorBuilder;
+
+    let vk = |num_preprocessed: usize| EvmVerifyingKey {
+        preprocessed: vec![Default::default(); num_preprocessed],
+        ..Default::default()
+    };
+    let verifier = Address::from_low_u64_be(0x1234);
+    // 0x33 bytes of forwarding code followed by the constants
+    let runtime_size = |num_preprocessed: usize| 0x33 + vk(num_preprocessed).size();
+    let max_num_preprocessed = (MAX_RUNTIME_SIZE - runtime_size(0)) / 0x40;
+
+    let deployment_code = vk(max_num_preprocessed).deployment_code(verifier).unwrap();
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let caller = Address::from_low_u64_be(0xfe);
+    let deployment = evm.deploy(caller, deployment_code.into(), 0.into());
+    assert!(!deployment.reverted);
+    // forwarded to the verifier, which has no code
+    let result = evm.call_raw(caller, deployment.address.unwrap(), vec![].into(), 0.into());
+    assert!(!result.reverted);
+
+    let err = vk(max_num_preprocessed + 1).deployment_code(verifier).unwrap_err();
+    assert_eq!(err.runtime_size, runtime_size(max_num_preprocessed + 1));
+    assert!(err.runtime_size > MAX_RUNTIME_SIZE);
+    assert_eq!(err.deployment_code.len(), 0x0d + err.runtime_size);
+}
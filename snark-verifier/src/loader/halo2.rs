@@ -1,9 +1,11 @@
 //! `Loader` implementation for generating verifier in [`halo2_proofs`] circuit.
 
 pub(crate) mod loader;
+mod profile;
 mod shim;
 
 pub use loader::{EcPoint, Halo2Loader, Scalar};
+pub use profile::{CellProfile, CellSection, CellUsage};
 pub use shim::{EccInstructions, IntegerInstructions};
 pub use util::Valuetools;
 
@@ -1,6 +1,9 @@
 use crate::{
     loader::{
-        halo2::shim::{EccInstructions, IntegerInstructions},
+        halo2::{
+            shim::{EccInstructions, IntegerInstructions},
+            CellProfile, CellSection, CellUsage,
+        },
         EcPointLoader, LoadedEcPoint, LoadedScalar, Loader, ScalarLoader,
     },
     util::{
@@ -9,7 +12,8 @@ use crate::{
     },
 };
 use std::{
-    cell::{Ref, RefCell, RefMut},
+    cell::{Cell, Ref, RefCell, RefMut},
+    convert::Infallible,
     fmt::{self, Debug},
     marker::PhantomData,
     ops::{Add, AddAssign, Deref, Mul, MulAssign, Neg, Sub, SubAssign},
@@ -23,10 +27,10 @@ pub struct Halo2Loader<C: CurveAffine, EccChip: EccInstructions<C>> {
     ctx: RefCell<EccChip::Context>,
     num_scalar: RefCell<usize>,
     num_ec_point: RefCell<usize>,
+    num_msm: RefCell<usize>,
+    cell_meterings: RefCell<Vec<(usize, Vec<CellUsage>, usize)>>,
+    cell_sections: RefCell<Vec<CellSection>>,
     _marker: PhantomData<C>,
-    #[cfg(test)]
-    #[allow(dead_code)]
-    row_meterings: RefCell<Vec<(String, usize)>>,
 }
 
 impl<C: CurveAffine, EccChip: EccInstructions<C>> Halo2Loader<C, EccChip> {
@@ -38,8 +42,9 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> Halo2Loader<C, EccChip> {
             ctx: RefCell::new(ctx),
             num_scalar: RefCell::default(),
             num_ec_point: RefCell::default(),
-            #[cfg(test)]
-            row_meterings: RefCell::default(),
+            num_msm: RefCell::default(),
+            cell_meterings: RefCell::default(),
+            cell_sections: RefCell::default(),
             _marker: PhantomData,
         })
     }
@@ -74,6 +79,38 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> Halo2Loader<C, EccChip> {
         self.ctx.borrow_mut()
     }
 
+    /// Returns cells used by the sections started by
+    /// [`Loader::start_cost_metering`] so far, as reported by
+    /// [`EccInstructions::cell_usage`] of the context.
+    pub fn cell_profile(&self) -> CellProfile {
+        CellProfile { sections: self.cell_sections.borrow().clone() }
+    }
+
+    fn start_cell_metering(&self, identifier: &str) {
+        let cells = self.ecc_chip().cell_usage(&self.ctx());
+        let mut sections = self.cell_sections.borrow_mut();
+        let mut meterings = self.cell_meterings.borrow_mut();
+        sections.push(CellSection {
+            identifier: identifier.to_string(),
+            depth: meterings.len(),
+            ..Default::default()
+        });
+        meterings.push((sections.len() - 1, cells, *self.num_msm.borrow()));
+    }
+
+    fn end_cell_metering(&self) {
+        let (idx, start, num_msm) =
+            self.cell_meterings.borrow_mut().pop().expect("no cost metering started");
+        let cells = self.ecc_chip().cell_usage(&self.ctx());
+        let section = &mut self.cell_sections.borrow_mut()[idx];
+        section.cells = cells
+            .iter()
+            .enumerate()
+            .map(|(phase, cells)| *cells - start.get(phase).copied().unwrap_or_default())
+            .collect();
+        section.num_msm = *self.num_msm.borrow() - num_msm;
+    }
+
     fn assign_const_scalar(self: &Rc<Self>, constant: C::Scalar) -> EccChip::AssignedScalar {
         self.scalar_chip().assign_constant(&mut self.ctx_mut(), constant)
     }
@@ -92,10 +129,7 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> Halo2Loader<C, EccChip> {
         self.scalar(Value::Assigned(assigned))
     }
 
-    fn scalar(
-        self: &Rc<Self>,
-        value: Value<C::Scalar, EccChip::AssignedScalar>,
-    ) -> Scalar<C, EccChip> {
+    fn scalar(self: &Rc<Self>, value: ScalarValue<C, EccChip>) -> Scalar<C, EccChip> {
         let index = *self.num_scalar.borrow();
         *self.num_scalar.borrow_mut() += 1;
         Scalar { loader: self.clone(), index, value: value.into() }
@@ -125,30 +159,81 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> Halo2Loader<C, EccChip> {
         EcPoint { loader: self.clone(), index, value: value.into() }
     }
 
+    fn scalar_from_expression(
+        self: &Rc<Self>,
+        expression: Expression<C::Scalar, EccChip::AssignedScalar>,
+    ) -> Scalar<C, EccChip> {
+        let section = self.cell_meterings.borrow().last().map(|(idx, _, _)| *idx);
+        self.scalar(expression.into_value(section))
+    }
+
+    /// Fuses deferred arithmetic of a scalar, or returns the one fused
+    /// already by any of its clones.
+    fn fuse_scalar(
+        &self,
+        deferred: &Deferred<C::Scalar, EccChip::AssignedScalar>,
+    ) -> EccChip::AssignedScalar {
+        if let Some(assigned) = deferred.fused.borrow().as_ref() {
+            return assigned.clone();
+        }
+        let start = self.ecc_chip().cell_usage(&self.ctx());
+        let assigned = self.fuse(&deferred.expression);
+        if let Some(idx) = deferred.section {
+            let cells = self.ecc_chip().cell_usage(&self.ctx());
+            let section = &mut self.cell_sections.borrow_mut()[idx];
+            section.deferred.resize(cells.len().max(section.deferred.len()), Default::default());
+            for (phase, cells) in cells.iter().enumerate() {
+                let start = start.get(phase).copied().unwrap_or_default();
+                section.deferred[phase] = section.deferred[phase] + (*cells - start);
+            }
+        }
+        *deferred.fused.borrow_mut() = Some(assigned.clone());
+        assigned
+    }
+
+    /// Assigns deferred arithmetic with the fewest calls to
+    /// [`IntegerInstructions`], which is a single one unless it has both terms
+    /// and products.
+    fn fuse(
+        &self,
+        expression: &Expression<C::Scalar, EccChip::AssignedScalar>,
+    ) -> EccChip::AssignedScalar {
+        let scalar_chip = self.scalar_chip();
+        let mut ctx = self.ctx_mut();
+        let Expression { constant, terms, products } = expression;
+        let terms = terms.iter().map(|(coeff, value)| (*coeff, value)).collect_vec();
+        if products.is_empty() {
+            return scalar_chip.sum_with_coeff_and_const(&mut ctx, &terms, *constant);
+        }
+        let products = products.iter().map(|(coeff, lhs, rhs)| (*coeff, lhs, rhs)).collect_vec();
+        if terms.is_empty() {
+            return scalar_chip.sum_products_with_coeff_and_const(&mut ctx, &products, *constant);
+        }
+        let product =
+            scalar_chip.sum_products_with_coeff_and_const(&mut ctx, &products, C::Scalar::zero());
+        let terms = terms.into_iter().chain(Some((C::Scalar::one(), &product))).collect_vec();
+        scalar_chip.sum_with_coeff_and_const(&mut ctx, &terms, *constant)
+    }
+
+    /// Returns [`Scalar`] with deferred arithmetic fused right away, which is
+    /// for the sums requested explicitly.
+    fn fused_scalar(
+        self: &Rc<Self>,
+        expression: Expression<C::Scalar, EccChip::AssignedScalar>,
+    ) -> Scalar<C, EccChip> {
+        let value = match expression.into_value(None) {
+            Value::Deferred(deferred) => Value::Assigned(self.fuse(&deferred.expression)),
+            value => value,
+        };
+        self.scalar(value)
+    }
+
     fn add(
         self: &Rc<Self>,
         lhs: &Scalar<C, EccChip>,
         rhs: &Scalar<C, EccChip>,
     ) -> Scalar<C, EccChip> {
-        let output = match (lhs.value().deref(), rhs.value().deref()) {
-            (Value::Constant(lhs), Value::Constant(rhs)) => Value::Constant(*lhs + rhs),
-            (Value::Assigned(assigned), Value::Constant(constant))
-            | (Value::Constant(constant), Value::Assigned(assigned)) => {
-                Value::Assigned(self.scalar_chip().sum_with_coeff_and_const(
-                    &mut self.ctx_mut(),
-                    &[(C::Scalar::one(), assigned)],
-                    *constant,
-                ))
-            }
-            (Value::Assigned(lhs), Value::Assigned(rhs)) => {
-                Value::Assigned(self.scalar_chip().sum_with_coeff_and_const(
-                    &mut self.ctx_mut(),
-                    &[(C::Scalar::one(), lhs), (C::Scalar::one(), rhs)],
-                    C::Scalar::zero(),
-                ))
-            }
-        };
-        self.scalar(output)
+        self.scalar_from_expression(lhs.expression().sum(rhs.expression()))
     }
 
     fn sub(
@@ -156,27 +241,9 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> Halo2Loader<C, EccChip> {
         lhs: &Scalar<C, EccChip>,
         rhs: &Scalar<C, EccChip>,
     ) -> Scalar<C, EccChip> {
-        let output = match (lhs.value().deref(), rhs.value().deref()) {
-            (Value::Constant(lhs), Value::Constant(rhs)) => Value::Constant(*lhs - rhs),
-            (Value::Constant(constant), Value::Assigned(assigned)) => {
-                Value::Assigned(self.scalar_chip().sum_with_coeff_and_const(
-                    &mut self.ctx_mut(),
-                    &[(-C::Scalar::one(), assigned)],
-                    *constant,
-                ))
-            }
-            (Value::Assigned(assigned), Value::Constant(constant)) => {
-                Value::Assigned(self.scalar_chip().sum_with_coeff_and_const(
-                    &mut self.ctx_mut(),
-                    &[(C::Scalar::one(), assigned)],
-                    -*constant,
-                ))
-            }
-            (Value::Assigned(lhs), Value::Assigned(rhs)) => Value::Assigned(
-                IntegerInstructions::sub(self.scalar_chip().deref(), &mut self.ctx_mut(), lhs, rhs),
-            ),
-        };
-        self.scalar(output)
+        self.scalar_from_expression(
+            lhs.expression().sum(rhs.expression().scaled(-C::Scalar::one())),
+        )
     }
 
     fn mul(
@@ -184,59 +251,45 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> Halo2Loader<C, EccChip> {
         lhs: &Scalar<C, EccChip>,
         rhs: &Scalar<C, EccChip>,
     ) -> Scalar<C, EccChip> {
-        let output = match (lhs.value().deref(), rhs.value().deref()) {
-            (Value::Constant(lhs), Value::Constant(rhs)) => Value::Constant(*lhs * rhs),
-            (Value::Assigned(assigned), Value::Constant(constant))
-            | (Value::Constant(constant), Value::Assigned(assigned)) => {
-                Value::Assigned(self.scalar_chip().sum_with_coeff_and_const(
-                    &mut self.ctx_mut(),
-                    &[(*constant, assigned)],
-                    C::Scalar::zero(),
-                ))
-            }
-            (Value::Assigned(lhs), Value::Assigned(rhs)) => {
-                Value::Assigned(self.scalar_chip().sum_products_with_coeff_and_const(
-                    &mut self.ctx_mut(),
-                    &[(C::Scalar::one(), lhs, rhs)],
-                    C::Scalar::zero(),
-                ))
-            }
-        };
-        self.scalar(output)
+        self.scalar_from_expression(Expression::product(lhs.atom(), rhs.atom()))
     }
 
     fn neg(self: &Rc<Self>, scalar: &Scalar<C, EccChip>) -> Scalar<C, EccChip> {
-        let output = match scalar.value().deref() {
-            Value::Constant(constant) => Value::Constant(constant.neg()),
-            Value::Assigned(assigned) => Value::Assigned(IntegerInstructions::neg(
-                self.scalar_chip().deref(),
-                &mut self.ctx_mut(),
-                assigned,
-            )),
-        };
-        self.scalar(output)
+        self.scalar_from_expression(scalar.expression().scaled(-C::Scalar::one()))
     }
 
     fn invert(self: &Rc<Self>, scalar: &Scalar<C, EccChip>) -> Scalar<C, EccChip> {
-        let output = match scalar.value().deref() {
-            Value::Constant(constant) => Value::Constant(Field::invert(constant).unwrap()),
-            Value::Assigned(assigned) => Value::Assigned(IntegerInstructions::invert(
-                self.scalar_chip().deref(),
-                &mut self.ctx_mut(),
-                assigned,
-            )),
+        let output = match scalar.maybe_const() {
+            Some(constant) => Value::Constant(Field::invert(&constant).unwrap()),
+            None => {
+                let assigned = scalar.assigned();
+                Value::Assigned(IntegerInstructions::invert(
+                    self.scalar_chip().deref(),
+                    &mut self.ctx_mut(),
+                    &assigned,
+                ))
+            }
         };
         self.scalar(output)
     }
 }
 
+/// Value of [`Scalar`] or [`EcPoint`], where only the former could have
+/// deferred arithmetic `D`, so it's [`Infallible`] for the latter.
 #[derive(Clone, Debug)]
-pub enum Value<T, L> {
+pub enum Value<T, L, D = Infallible> {
     Constant(T),
     Assigned(L),
+    Deferred(D),
 }
 
-impl<T, L> Value<T, L> {
+type ScalarValue<C, EccChip> = Value<
+    <C as CurveAffine>::ScalarExt,
+    <EccChip as EccInstructions<C>>::AssignedScalar,
+    Rc<Deferred<<C as CurveAffine>::ScalarExt, <EccChip as EccInstructions<C>>::AssignedScalar>>,
+>;
+
+impl<T, L, D> Value<T, L, D> {
     fn maybe_const(&self) -> Option<T>
     where
         T: Copy,
@@ -255,12 +308,113 @@ impl<T, L> Value<T, L> {
     }
 }
 
+/// Deferred arithmetic of a [`Scalar`], shared by its clones and dropped with
+/// the last of them, so [`Halo2Loader`] keeps no state of it.
+#[derive(Debug)]
+pub struct Deferred<T, L> {
+    expression: Expression<T, L>,
+    /// Index of the innermost cell section started when it's deferred.
+    section: Option<usize>,
+    /// Whether it's inlined into another one already.
+    inlined: Cell<bool>,
+    /// Assigned value once fused.
+    fused: RefCell<Option<L>>,
+}
+
+impl<T, L> Deferred<T, L> {
+    fn new(expression: Expression<T, L>, section: Option<usize>) -> Self {
+        Self { expression, section, inlined: Cell::new(false), fused: RefCell::new(None) }
+    }
+
+    /// Returns whether it should be inlined into another one, which is only on
+    /// its first use, so the ones used many times are fused once and shared
+    /// instead of being duplicated.
+    fn inline(&self) -> bool {
+        self.fused.borrow().is_none() && !self.inlined.replace(true)
+    }
+}
+
+/// Deferred arithmetic of assigned values as
+/// `constant + Σ coeff * value + Σ coeff * lhs * rhs`, which is fused into
+/// [`IntegerInstructions::sum_with_coeff_and_const`] and
+/// [`IntegerInstructions::sum_products_with_coeff_and_const`] when its value
+/// is needed.
+#[derive(Clone, Debug)]
+pub struct Expression<T, L> {
+    constant: T,
+    terms: Vec<(T, L)>,
+    products: Vec<(T, L, L)>,
+}
+
+impl<T: Field, L: Clone> Expression<T, L> {
+    fn constant(constant: T) -> Self {
+        Self { constant, terms: Vec::new(), products: Vec::new() }
+    }
+
+    fn assigned(assigned: L) -> Self {
+        Self { constant: T::zero(), terms: vec![(T::one(), assigned)], products: Vec::new() }
+    }
+
+    /// Returns whether it has at most one term and no product, which is cheap
+    /// to duplicate and to multiply.
+    fn is_atom(&self) -> bool {
+        self.terms.len() <= 1 && self.products.is_empty()
+    }
+
+    fn sum(mut self, rhs: Self) -> Self {
+        self.constant += rhs.constant;
+        self.terms.extend(rhs.terms);
+        self.products.extend(rhs.products);
+        self
+    }
+
+    fn scaled(mut self, coeff: T) -> Self {
+        self.constant *= coeff;
+        self.terms.iter_mut().for_each(|(term_coeff, _)| *term_coeff *= coeff);
+        self.products.iter_mut().for_each(|(product_coeff, _, _)| *product_coeff *= coeff);
+        self
+    }
+
+    /// Returns product of atoms `lhs` and `rhs`, which are
+    /// `lhs_constant + lhs_coeff * lhs` and `rhs_constant + rhs_coeff * rhs`.
+    fn product(lhs: Self, rhs: Self) -> Self {
+        debug_assert!(lhs.is_atom() && rhs.is_atom());
+        let mut output = Self::constant(lhs.constant * rhs.constant);
+        if let Some((coeff, value)) = lhs.terms.first() {
+            output.terms.push((*coeff * rhs.constant, value.clone()));
+        }
+        if let Some((coeff, value)) = rhs.terms.first() {
+            output.terms.push((lhs.constant * coeff, value.clone()));
+        }
+        if let (Some((lhs_coeff, lhs)), Some((rhs_coeff, rhs))) =
+            (lhs.terms.into_iter().next(), rhs.terms.into_iter().next())
+        {
+            output.products.push((lhs_coeff * rhs_coeff, lhs, rhs));
+        }
+        output
+    }
+
+    /// Returns the value, which is deferred by the cell section indexed by
+    /// `section` if it's not a constant or an assigned one.
+    fn into_value(mut self, section: Option<usize>) -> Value<T, L, Rc<Deferred<T, L>>> {
+        self.terms.retain(|(coeff, _)| !coeff.is_zero_vartime());
+        self.products.retain(|(coeff, _, _)| !coeff.is_zero_vartime());
+        match (self.terms.len(), self.products.len()) {
+            (0, 0) => Value::Constant(self.constant),
+            (1, 0) if self.constant == T::zero() && self.terms[0].0 == T::one() => {
+                Value::Assigned(self.terms.pop().unwrap().1)
+            }
+            _ => Value::Deferred(Rc::new(Deferred::new(self, section))),
+        }
+    }
+}
+
 /// Field element
 #[derive(Clone)]
 pub struct Scalar<C: CurveAffine, EccChip: EccInstructions<C>> {
     loader: Rc<Halo2Loader<C, EccChip>>,
     index: usize,
-    value: RefCell<Value<C::Scalar, EccChip::AssignedScalar>>,
+    value: RefCell<ScalarValue<C, EccChip>>,
 }
 
 impl<C: CurveAffine, EccChip: EccInstructions<C>> Scalar<C, EccChip> {
@@ -274,21 +428,53 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> Scalar<C, EccChip> {
         match self.value.into_inner() {
             Value::Constant(constant) => self.loader.assign_const_scalar(constant),
             Value::Assigned(assigned) => assigned,
+            Value::Deferred(deferred) => self.loader.fuse_scalar(&deferred),
         }
     }
 
     /// Returns reference of [`EccInstructions::AssignedScalar`].
     pub fn assigned(&self) -> Ref<EccChip::AssignedScalar> {
-        if let Some(constant) = self.maybe_const() {
-            *self.value.borrow_mut() = Value::Assigned(self.loader.assign_const_scalar(constant))
+        let assigned = match self.value().deref() {
+            Value::Constant(constant) => Some(self.loader.assign_const_scalar(*constant)),
+            Value::Assigned(_) => None,
+            Value::Deferred(deferred) => Some(self.loader.fuse_scalar(deferred)),
+        };
+        if let Some(assigned) = assigned {
+            *self.value.borrow_mut() = Value::Assigned(assigned)
         }
         Ref::map(self.value.borrow(), Value::assigned)
     }
 
-    fn value(&self) -> Ref<Value<C::Scalar, EccChip::AssignedScalar>> {
+    fn value(&self) -> Ref<ScalarValue<C, EccChip>> {
         self.value.borrow()
     }
 
+    /// Returns [`Expression`] to be used in another one, where deferred
+    /// arithmetic is inlined only if [`Deferred::inline`] allows.
+    fn expression(&self) -> Expression<C::Scalar, EccChip::AssignedScalar> {
+        match self.value().deref() {
+            Value::Constant(constant) => return Expression::constant(*constant),
+            Value::Assigned(assigned) => return Expression::assigned(assigned.clone()),
+            Value::Deferred(deferred) => {
+                if deferred.expression.is_atom() || deferred.inline() {
+                    return deferred.expression.clone();
+                }
+            }
+        }
+        Expression::assigned(self.assigned().clone())
+    }
+
+    /// Returns [`Expression`] to be multiplied, which is fused first if it's
+    /// not an atom.
+    fn atom(&self) -> Expression<C::Scalar, EccChip::AssignedScalar> {
+        let expression = self.expression();
+        if expression.is_atom() {
+            expression
+        } else {
+            Expression::assigned(self.assigned().clone())
+        }
+    }
+
     fn maybe_const(&self) -> Option<C::Scalar> {
         self.value().deref().maybe_const()
     }
@@ -426,6 +612,7 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> EcPoint<C, EccChip> {
         match self.value.into_inner() {
             Value::Constant(constant) => self.loader.assign_const_ec_point(constant),
             Value::Assigned(assigned) => assigned,
+            Value::Deferred(infallible) => match infallible {},
         }
     }
 
@@ -476,7 +663,8 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> ScalarLoader<C::Scalar>
     }
 
     fn assert_eq(&self, _annotation: &str, lhs: &Scalar<C, EccChip>, rhs: &Scalar<C, EccChip>) {
-        self.scalar_chip().assert_equal(&mut self.ctx_mut(), &lhs.assigned(), &rhs.assigned());
+        let (lhs, rhs) = (lhs.assigned(), rhs.assigned());
+        self.scalar_chip().assert_equal(&mut self.ctx_mut(), &lhs, &rhs);
     }
 
     fn sum_with_coeff_and_const(
@@ -484,12 +672,10 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> ScalarLoader<C::Scalar>
         values: &[(C::Scalar, &Scalar<C, EccChip>)],
         constant: C::Scalar,
     ) -> Scalar<C, EccChip> {
-        let values = values.iter().map(|(coeff, value)| (*coeff, value.assigned())).collect_vec();
-        self.scalar(Value::Assigned(self.scalar_chip().sum_with_coeff_and_const(
-            &mut self.ctx_mut(),
-            &values,
-            constant,
-        )))
+        let expression = values.iter().fold(Expression::constant(constant), |acc, (coeff, value)| {
+            acc.sum(value.expression().scaled(*coeff))
+        });
+        self.fused_scalar(expression)
     }
 
     fn sum_products_with_coeff_and_const(
@@ -497,15 +683,41 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> ScalarLoader<C::Scalar>
         values: &[(C::Scalar, &Scalar<C, EccChip>, &Scalar<C, EccChip>)],
         constant: C::Scalar,
     ) -> Scalar<C, EccChip> {
-        let values = values
-            .iter()
-            .map(|(coeff, lhs, rhs)| (*coeff, lhs.assigned(), rhs.assigned()))
-            .collect_vec();
-        self.scalar(Value::Assigned(self.scalar_chip().sum_products_with_coeff_and_const(
-            &mut self.ctx_mut(),
-            &values,
-            constant,
-        )))
+        let expression =
+            values.iter().fold(Expression::constant(constant), |acc, (coeff, lhs, rhs)| {
+                acc.sum(Expression::product(lhs.atom(), rhs.atom()).scaled(*coeff))
+            });
+        self.fused_scalar(expression)
+    }
+
+    /// Inverts constants natively, leaving zero as is, and assigned values by
+    /// [`IntegerInstructions::batch_invert`], which adds the same constraints
+    /// as each [`LoadedScalar::invert`] would, so it can't be satisfied if any
+    /// is zero, but computes the inverses with a single field inversion.
+    fn batch_invert<'a>(values: impl IntoIterator<Item = &'a mut Scalar<C, EccChip>>)
+    where
+        Scalar<C, EccChip>: 'a,
+    {
+        let mut values = values.into_iter().collect_vec();
+        for value in values.iter_mut() {
+            if let Some(constant) = value.maybe_const() {
+                let inverse = Field::invert(&constant).unwrap_or(constant);
+                **value = value.loader.scalar(Value::Constant(inverse));
+            }
+        }
+
+        let assigned =
+            values.into_iter().filter(|value| value.maybe_const().is_none()).collect_vec();
+        if assigned.is_empty() {
+            return;
+        }
+        let loader = assigned[0].loader.clone();
+        let values = assigned.iter().map(|value| value.assigned()).collect_vec();
+        let inverses = loader.scalar_chip().batch_invert(&mut loader.ctx_mut(), &values);
+        drop(values);
+        for (value, inverse) in assigned.into_iter().zip(inverses) {
+            *value = loader.scalar_from_assigned(inverse);
+        }
     }
 }
 
@@ -538,6 +750,7 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> EcPointLoader<C> for Rc<Halo2L
     ) -> EcPoint<C, EccChip> {
         assert!(!pairs.is_empty(), "multi_scalar_multiplication: pairs is empty");
         let loader = &pairs[0].0.loader;
+        *loader.num_msm.borrow_mut() += 1;
 
         let (constant, fixed_base, variable_base_non_scaled, variable_base_scaled) =
             pairs.iter().cloned().fold(
@@ -553,9 +766,7 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> EcPointLoader<C> for Rc<Halo2L
                         (Value::Constant(scalar), Value::Constant(base)) => {
                             constant = (*base * scalar + constant).into()
                         }
-                        (Value::Assigned(_), Value::Constant(base)) => {
-                            fixed_base.push((scalar, *base))
-                        }
+                        (_, Value::Constant(base)) => fixed_base.push((scalar, *base)),
                         (Value::Constant(scalar), Value::Assigned(_))
                             if scalar.eq(&C::Scalar::one()) =>
                         {
@@ -603,4 +814,111 @@ impl<C: CurveAffine, EccChip: EccInstructions<C>> EcPointLoader<C> for Rc<Halo2L
     }
 }
 
-impl<C: CurveAffine, EccChip: EccInstructions<C>> Loader<C> for Rc<Halo2Loader<C, EccChip>> {}
+impl<C: CurveAffine, EccChip: EccInstructions<C>> Loader<C> for Rc<Halo2Loader<C, EccChip>> {
+    fn start_cost_metering(&self, identifier: &str) {
+        self.start_cell_metering(identifier)
+    }
+
+    fn end_cost_metering(&self) {
+        self.end_cell_metering()
+    }
+}
+
+#[test]
+fn test_batch_invert() {
+    use crate::halo2_curves::bn256::{Fr, G1Affine};
+    use halo2_base::gates::{builder::GateThreadBuilder, RangeChip};
+    use halo2_ecc::{bn254::FpChip, ecc::BaseFieldEccChip};
+
+    let range = RangeChip::<Fr>::default(8);
+    let fp_chip = FpChip::<Fr>::new(&range, 88, 3);
+    let loader = Halo2Loader::new(BaseFieldEccChip::new(&fp_chip), GateThreadBuilder::mock());
+    let values = [2, 3, 5, 7].map(|value| loader.assign_scalar(Fr::from(value)));
+
+    Loader::<G1Affine>::start_cost_metering(&loader, "invert");
+    let inverses = values.iter().map(|value| value.invert().unwrap()).collect_vec();
+    Loader::<G1Affine>::end_cost_metering(&loader);
+
+    Loader::<G1Affine>::start_cost_metering(&loader, "batch_invert");
+    let mut batch_inverses = values.to_vec();
+    batch_inverses.push(loader.load_zero());
+    batch_inverses.push(loader.load_const(&Fr::from(11)));
+    <Rc<Halo2Loader<G1Affine, _>> as ScalarLoader<Fr>>::batch_invert(
+        batch_inverses.iter_mut(),
+    );
+    Loader::<G1Affine>::end_cost_metering(&loader);
+
+    let value =
+        |scalar: &Scalar<G1Affine, BaseFieldEccChip<G1Affine>>| *scalar.assigned().value();
+    for (inverse, batch_inverse) in inverses.iter().zip(batch_inverses.iter()) {
+        assert_eq!(value(inverse), value(batch_inverse));
+    }
+    assert_eq!(value(&batch_inverses[4]), Fr::zero());
+    assert_eq!(value(&batch_inverses[5]), Field::invert(&Fr::from(11)).unwrap());
+
+    let profile = loader.cell_profile();
+    assert_eq!(profile.total_of("batch_invert"), profile.total_of("invert"));
+}
+
+#[test]
+fn test_deferred_arithmetic() {
+    use crate::halo2_curves::bn256::{Fr, G1Affine};
+    use halo2_base::gates::{builder::GateThreadBuilder, RangeChip};
+    use halo2_ecc::{bn254::FpChip, ecc::BaseFieldEccChip};
+
+    let range = RangeChip::<Fr>::default(8);
+    let fp_chip = FpChip::<Fr>::new(&range, 88, 3);
+    let loader = Halo2Loader::new(BaseFieldEccChip::new(&fp_chip), GateThreadBuilder::mock());
+    let [a, b, c, d] = [2, 3, 5, 7].map(|value| loader.assign_scalar(Fr::from(value)));
+    let two = loader.load_const(&Fr::from(2));
+    let value =
+        |scalar: &Scalar<G1Affine, BaseFieldEccChip<G1Affine>>| *scalar.assigned().value();
+
+    // arithmetic is deferred until the value is needed, then fused as an
+    // explicit sum of terms and products
+    Loader::<G1Affine>::start_cost_metering(&loader, "operators");
+    let output = -(a.clone() - &(b.clone() * &two)) + &(c.clone() * &-d.clone()) + &two;
+    assert!(matches!(output.value().deref(), Value::Deferred(_)));
+    assert_eq!(value(&output), -Fr::from(29));
+    Loader::<G1Affine>::end_cost_metering(&loader);
+    Loader::<G1Affine>::start_cost_metering(&loader, "explicit");
+    let explicit = loader.sum_products_with_coeff_and_const(
+        &[(-Fr::one(), &a, &loader.load_one()), (Fr::from(2), &b, &loader.load_one())],
+        Fr::from(2),
+    );
+    let explicit = loader.sum_products_with_coeff_and_const(
+        &[(Fr::one(), &explicit, &loader.load_one()), (-Fr::one(), &c, &d)],
+        Fr::zero(),
+    );
+    Loader::<G1Affine>::end_cost_metering(&loader);
+    assert_eq!(value(&explicit), -Fr::from(29));
+    let profile = loader.cell_profile();
+    assert!(profile.total_of("operators").advice < profile.total_of("explicit").advice);
+
+    // deferred arithmetic used many times is fused only once
+    let shared = a.clone() + &b + &c;
+    Loader::<G1Affine>::start_cost_metering(&loader, "shared");
+    let squares = [(); 3].map(|_| shared.clone() * &shared);
+    Loader::<G1Affine>::end_cost_metering(&loader);
+    let once = a.clone() + &b + &c;
+    Loader::<G1Affine>::start_cost_metering(&loader, "once");
+    value(&once);
+    Loader::<G1Affine>::end_cost_metering(&loader);
+    assert!(squares.iter().all(|square| value(square) == Fr::from(100)));
+    let profile = loader.cell_profile();
+    assert_eq!(profile.total_of("shared"), profile.total_of("once"));
+
+    // deferred arithmetic is charged to the section assigning it
+    Loader::<G1Affine>::start_cost_metering(&loader, "defer");
+    let deferred = a.clone() + &b + &c;
+    Loader::<G1Affine>::end_cost_metering(&loader);
+    Loader::<G1Affine>::start_cost_metering(&loader, "force");
+    value(&deferred);
+    Loader::<G1Affine>::end_cost_metering(&loader);
+    let profile = loader.cell_profile();
+    assert_eq!(profile.total_of("defer"), CellUsage::default());
+    assert_eq!(profile.total_of("force"), profile.total_of("once"));
+    // but also reported as deferred by the section doing it
+    assert_eq!(profile.deferred_of("defer"), profile.total_of("once"));
+    assert_eq!(profile.deferred_of("force"), CellUsage::default());
+}
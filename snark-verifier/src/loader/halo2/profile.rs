@@ -0,0 +1,183 @@
+use std::{
+    fmt::{self, Display},
+    ops::{Add, Sub},
+};
+
+/// Cells used in a phase of circuit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CellUsage {
+    /// Number of advice cells.
+    pub advice: usize,
+    /// Number of advice cells copied to lookup advice columns.
+    pub lookup: usize,
+    /// Number of advice cells constrained to be constants.
+    pub constant: usize,
+}
+
+impl CellUsage {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Add for CellUsage {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            advice: self.advice + rhs.advice,
+            lookup: self.lookup + rhs.lookup,
+            constant: self.constant + rhs.constant,
+        }
+    }
+}
+
+impl Sub for CellUsage {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            advice: self.advice.saturating_sub(rhs.advice),
+            lookup: self.lookup.saturating_sub(rhs.lookup),
+            constant: self.constant.saturating_sub(rhs.constant),
+        }
+    }
+}
+
+/// Cells used by a section of the verifier, which is started by
+/// [`Loader::start_cost_metering`](crate::loader::Loader::start_cost_metering)
+/// and ended by [`Loader::end_cost_metering`](crate::loader::Loader::end_cost_metering).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CellSection {
+    /// Identifier of the section, e.g. `read_proof`, `quotient_evaluation` or
+    /// `pcs_verify`.
+    pub identifier: String,
+    /// Number of sections enclosing this one.
+    pub depth: usize,
+    /// Cells used by the section in each phase, or empty if the section is not
+    /// ended yet.
+    pub cells: Vec<CellUsage>,
+    /// Cells used in each phase to assign arithmetic deferred by the section,
+    /// which are also counted in `cells` of the section assigning it.
+    pub deferred: Vec<CellUsage>,
+    /// Number of multi-scalar multiplications done by the section.
+    pub num_msm: usize,
+}
+
+impl CellSection {
+    /// Returns cells used by the section in all phases.
+    pub fn total(&self) -> CellUsage {
+        self.cells.iter().fold(CellUsage::default(), |acc, cells| acc + *cells)
+    }
+
+    /// Returns cells used in all phases to assign arithmetic deferred by the
+    /// section.
+    pub fn total_deferred(&self) -> CellUsage {
+        self.deferred.iter().fold(CellUsage::default(), |acc, cells| acc + *cells)
+    }
+}
+
+/// Cell profile of in-circuit verification.
+///
+/// Cells are counted when they are assigned, but arithmetic of scalars in
+/// [`Halo2Loader`](crate::loader::halo2::Halo2Loader) is deferred until its
+/// value is needed, and then it's fused with whatever arithmetic the section
+/// needing it does, so its cells are counted in `cells` of that section, e.g.
+/// the quotient evaluation is usually fused into the linear combinations of
+/// `pcs_verify`. So the cells of fusing deferred arithmetic are also reported in
+/// `deferred` of the section which deferred it, i.e. the innermost one started
+/// when the arithmetic was done. Deferred arithmetic is not assigned at the end
+/// of each section, since that would make the circuit depend on how it's
+/// metered.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CellProfile {
+    /// Sections in the order they are started.
+    pub sections: Vec<CellSection>,
+}
+
+impl CellProfile {
+    /// Returns cells used in all phases by sections with given `identifier`,
+    /// e.g. by `quotient_evaluation` of all aggregated snarks.
+    pub fn total_of(&self, identifier: &str) -> CellUsage {
+        self.sections
+            .iter()
+            .filter(|section| section.identifier == identifier)
+            .fold(CellUsage::default(), |acc, section| acc + section.total())
+    }
+
+    /// Returns cells used in all phases to assign arithmetic deferred by
+    /// sections with given `identifier`.
+    pub fn deferred_of(&self, identifier: &str) -> CellUsage {
+        self.sections
+            .iter()
+            .filter(|section| section.identifier == identifier)
+            .fold(CellUsage::default(), |acc, section| acc + section.total_deferred())
+    }
+}
+
+impl Display for CellProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for section in self.sections.iter() {
+            let indent = "  ".repeat(section.depth);
+            writeln!(f, "{indent}{} (msm: {})", section.identifier, section.num_msm)?;
+            for (phase, cells) in section.cells.iter().enumerate() {
+                if cells.is_empty() {
+                    continue;
+                }
+                writeln!(
+                    f,
+                    "{indent}  phase {phase}: advice: {}, lookup: {}, constant: {}",
+                    cells.advice, cells.lookup, cells.constant
+                )?;
+            }
+            for (phase, cells) in section.deferred.iter().enumerate() {
+                if cells.is_empty() {
+                    continue;
+                }
+                writeln!(
+                    f,
+                    "{indent}  phase {phase} deferred: advice: {}, lookup: {}, constant: {}",
+                    cells.advice, cells.lookup, cells.constant
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_cell_profile() {
+    use crate::{
+        halo2_curves::bn256::{Fr, G1Affine},
+        loader::{halo2::Halo2Loader, EcPointLoader, Loader},
+    };
+    use halo2_base::gates::{builder::GateThreadBuilder, RangeChip};
+    use halo2_ecc::{bn254::FpChip, ecc::BaseFieldEccChip};
+    use std::rc::Rc;
+
+    let range = RangeChip::<Fr>::default(8);
+    let fp_chip = FpChip::<Fr>::new(&range, 88, 3);
+    let ecc_chip = BaseFieldEccChip::new(&fp_chip);
+    let loader = Halo2Loader::new(ecc_chip, GateThreadBuilder::mock());
+
+    Loader::<G1Affine>::start_cost_metering(&loader, "outer");
+    let a = loader.assign_scalar(Fr::from(3));
+    let b = loader.assign_scalar(Fr::from(5));
+    Loader::<G1Affine>::start_cost_metering(&loader, "inner");
+    let c = a.clone() * &b + &a;
+    let g = loader.assign_ec_point(G1Affine::generator());
+    <Rc<Halo2Loader<_, _>> as EcPointLoader<G1Affine>>::multi_scalar_multiplication(&[
+        (&c, &g),
+    ]);
+    Loader::<G1Affine>::end_cost_metering(&loader);
+    Loader::<G1Affine>::end_cost_metering(&loader);
+
+    let profile = loader.cell_profile();
+    let [outer, inner] = [&profile.sections[0], &profile.sections[1]];
+    assert_eq!((outer.identifier.as_str(), outer.depth), ("outer", 0));
+    assert_eq!((inner.identifier.as_str(), inner.depth), ("inner", 1));
+    assert_eq!((outer.num_msm, inner.num_msm), (1, 1));
+    assert_eq!(outer.total().advice, inner.total().advice + 2);
+    assert!(inner.total().lookup > 0);
+    assert_eq!(profile.total_of("outer"), outer.total());
+}
@@ -1,4 +1,7 @@
-use crate::util::arithmetic::{CurveAffine, FieldExt};
+use crate::{
+    loader::halo2::CellUsage,
+    util::arithmetic::{CurveAffine, FieldExt},
+};
 use std::{fmt::Debug, ops::Deref};
 
 /// Instructions to handle field element operations.
@@ -58,6 +61,18 @@ pub trait IntegerInstructions<F: FieldExt>: Clone + Debug {
         value: &Self::AssignedInteger,
     ) -> Self::AssignedInteger;
 
+    /// Returns `1/value` of each value with the same constraints as
+    /// [`IntegerInstructions::invert`] on each, so it can't be satisfied if any
+    /// is zero. Implementations could only save the work of computing the
+    /// inverses out of circuit.
+    fn batch_invert(
+        &self,
+        ctx: &mut Self::Context,
+        values: &[impl Deref<Target = Self::AssignedInteger>],
+    ) -> Vec<Self::AssignedInteger> {
+        values.iter().map(|value| self.invert(ctx, value.deref())).collect()
+    }
+
     /// Enforce `lhs` and `rhs` are equal.
     fn assert_equal(
         &self,
@@ -126,19 +141,29 @@ pub trait EccInstructions<C: CurveAffine>: Clone + Debug {
         lhs: &Self::AssignedEcPoint,
         rhs: &Self::AssignedEcPoint,
     );
+
+    /// Returns cells used so far in `ctx` per phase, which is used to meter
+    /// cost of sections of the verifier. Returns empty if `ctx` doesn't
+    /// support it.
+    fn cell_usage(&self, _ctx: &Self::Context) -> Vec<CellUsage> {
+        Vec::new()
+    }
 }
 
 mod halo2_lib {
     use crate::halo2_proofs::halo2curves::CurveAffineExt;
     use crate::{
-        loader::halo2::{EccInstructions, IntegerInstructions},
-        util::arithmetic::CurveAffine,
+        loader::halo2::{CellUsage, EccInstructions, IntegerInstructions},
+        util::{
+            arithmetic::{batch_invert, CurveAffine},
+            Itertools,
+        },
     };
     use halo2_base::{
         self,
         gates::{builder::GateThreadBuilder, GateChip, GateInstructions, RangeInstructions},
-        AssignedValue,
-        QuantumCell::{Constant, Existing},
+        AssignedValue, Context,
+        QuantumCell::{Constant, Existing, Witness},
     };
     use halo2_ecc::bigint::ProperCrtUint;
     use halo2_ecc::{
@@ -150,6 +175,19 @@ mod halo2_lib {
     type AssignedInteger<C> = ProperCrtUint<<C as CurveAffine>::ScalarExt>;
     type AssignedEcPoint<C> = EcPoint<<C as CurveAffine>::ScalarExt, AssignedInteger<C>>;
 
+    /// Assigns `inverse` constrained by `value * inverse = 1`, which can't be
+    /// satisfied if `value` is zero, as [`GateInstructions::div_unsafe`] does
+    /// but without computing the inverse.
+    fn assign_inverse<F: PrimeField>(
+        ctx: &mut Context<F>,
+        value: &AssignedValue<F>,
+        inverse: F,
+    ) -> AssignedValue<F> {
+        let cells = [Constant(F::zero()), Existing(*value), Witness(inverse), Constant(F::one())];
+        ctx.assign_region(cells, [0]);
+        ctx.get(-2)
+    }
+
     impl<F: PrimeField> IntegerInstructions<F> for GateChip<F> {
         type Context = GateThreadBuilder<F>;
         type AssignedCell = AssignedValue<F>;
@@ -218,10 +256,35 @@ mod halo2_lib {
             ctx: &mut Self::Context,
             a: &Self::AssignedInteger,
         ) -> Self::AssignedInteger {
-            // make sure scalar != 0
-            let is_zero = self.is_zero(ctx.main(0), *a);
-            self.assert_is_const(ctx.main(0), &is_zero, &F::zero());
-            GateInstructions::div_unsafe(self, ctx.main(0), Constant(F::one()), Existing(*a))
+            // witness zero as inverse of zero to not panic, which fails `a * inverse = 1`
+            assign_inverse(ctx.main(0), a, a.value().invert().unwrap_or(F::zero()))
+        }
+
+        fn batch_invert(
+            &self,
+            ctx: &mut Self::Context,
+            values: &[impl Deref<Target = Self::AssignedInteger>],
+        ) -> Vec<Self::AssignedInteger> {
+            // constrain each as `invert` does, but witness inverses with a single field inversion
+            // by running products, skipping zeros to not panic
+            let mut inverses = values
+                .iter()
+                .map(|value| *value.value())
+                .filter(|value| !value.is_zero_vartime())
+                .collect_vec();
+            batch_invert(&mut inverses);
+            let mut inverses = inverses.into_iter();
+            values
+                .iter()
+                .map(|value| {
+                    let inverse = if value.value().is_zero_vartime() {
+                        F::zero()
+                    } else {
+                        inverses.next().unwrap()
+                    };
+                    assign_inverse(ctx.main(0), value.deref(), inverse)
+                })
+                .collect()
         }
 
         fn assert_equal(
@@ -328,5 +391,23 @@ mod halo2_lib {
         ) {
             self.assert_equal(ctx.main(0), a.clone(), b.clone());
         }
+
+        fn cell_usage(&self, builder: &Self::Context) -> Vec<CellUsage> {
+            // constants are counted only when `builder` is not witness generation only,
+            // which is the case in mock and keygen
+            builder
+                .threads
+                .iter()
+                .map(|threads| {
+                    threads.iter().fold(CellUsage::default(), |acc, ctx| {
+                        acc + CellUsage {
+                            advice: ctx.advice.len(),
+                            lookup: ctx.cells_to_lookup.len(),
+                            constant: ctx.constant_equality_constraints.len(),
+                        }
+                    })
+                })
+                .collect()
+        }
     }
 }
@@ -15,6 +15,8 @@ pub use multiopen::{Bdfg21, Bdfg21Proof, Gwc19, Gwc19Proof};
 
 #[cfg(feature = "loader_halo2")]
 pub use accumulator::LimbsEncodingInstructions;
+#[cfg(feature = "loader_halo2")]
+pub use decider::PairingInstructions;
 
 /// KZG succinct verifying key.
 #[derive(Clone, Copy, Debug)]
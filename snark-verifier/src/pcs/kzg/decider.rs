@@ -21,6 +21,16 @@ impl<M: MultiMillerLoop> KzgDecidingKey<M> {
     ) -> Self {
         Self { svk: svk.into(), g2, s_g2, _marker: PhantomData }
     }
+
+    /// Returns generator on G2.
+    pub fn g2(&self) -> M::G2Affine {
+        self.g2
+    }
+
+    /// Returns generator to the trusted-setup secret on G2.
+    pub fn s_g2(&self) -> M::G2Affine {
+        self.s_g2
+    }
 }
 
 impl<M: MultiMillerLoop> From<(M::G1Affine, M::G2Affine, M::G2Affine)> for KzgDecidingKey<M> {
@@ -85,7 +95,7 @@ mod native {
 mod evm {
     use crate::{
         loader::{
-            evm::{loader::Value, EvmLoader, U256},
+            evm::{loader::Value, vk::g2_to_u256s, EvmLoader},
             LoadedScalar,
         },
         pcs::{
@@ -93,7 +103,7 @@ mod evm {
             AccumulationDecider,
         },
         util::{
-            arithmetic::{CurveAffine, MultiMillerLoop, PrimeField},
+            arithmetic::{MultiMillerLoop, PrimeField},
             msm::Msm,
         },
         Error,
@@ -113,17 +123,7 @@ mod evm {
             KzgAccumulator { lhs, rhs }: KzgAccumulator<M::G1Affine, Rc<EvmLoader>>,
         ) -> Result<(), Error> {
             let loader = lhs.loader();
-            let [g2, minus_s_g2] = [dk.g2, -dk.s_g2].map(|ec_point| {
-                let coordinates = ec_point.coordinates().unwrap();
-                let x = coordinates.x().to_repr();
-                let y = coordinates.y().to_repr();
-                (
-                    U256::from_little_endian(&x.as_ref()[32..]),
-                    U256::from_little_endian(&x.as_ref()[..32]),
-                    U256::from_little_endian(&y.as_ref()[32..]),
-                    U256::from_little_endian(&y.as_ref()[..32]),
-                )
-            });
+            let [g2, minus_s_g2] = [dk.g2, -dk.s_g2].map(g2_to_u256s::<M>);
             loader.pairing(&lhs, g2, &rhs, minus_s_g2);
             Ok(())
         }
@@ -138,15 +138,21 @@ mod evm {
                 accumulators.pop().unwrap()
             } else {
                 let loader = accumulators[0].lhs.loader();
+                let ptr = loader.allocate(accumulators.len() * 0x80);
                 let (lhs, rhs) = accumulators
                     .iter()
-                    .map(|KzgAccumulator { lhs, rhs }| {
-                        let [lhs, rhs] = [&lhs, &rhs].map(|ec_point| loader.dup_ec_point(ec_point));
+                    .enumerate()
+                    .map(|(idx, KzgAccumulator { lhs, rhs })| {
+                        let [lhs, rhs] = [(lhs, 0), (rhs, 0x40)].map(|(ec_point, offset)| {
+                            let ptr = ptr + idx * 0x80 + offset;
+                            loader.copy_ec_point(ec_point, ptr);
+                            loader.ec_point(Value::Memory(ptr))
+                        });
                         (lhs, rhs)
                     })
                     .unzip::<_, _, Vec<_>, Vec<_>>();
 
-                let hash_ptr = loader.keccak256(lhs[0].ptr(), lhs.len() * 0x80);
+                let hash_ptr = loader.keccak256(ptr, lhs.len() * 0x80);
                 let challenge_ptr = loader.allocate(0x20);
                 let code = format!("mstore({challenge_ptr}, mod(mload({hash_ptr}), f_q))");
                 loader.code_mut().runtime_append(code);
@@ -170,3 +176,112 @@ mod evm {
         }
     }
 }
+
+#[cfg(feature = "loader_halo2")]
+pub use halo2::PairingInstructions;
+
+#[cfg(feature = "loader_halo2")]
+mod halo2 {
+    use crate::{
+        loader::{
+            halo2::{EccInstructions, Halo2Loader},
+            LoadedEcPoint,
+        },
+        pcs::{
+            kzg::{KzgAccumulator, KzgAs, KzgDecidingKey},
+            AccumulationDecider,
+        },
+        util::{arithmetic::MultiMillerLoop, Itertools},
+        Error,
+    };
+    use std::{fmt::Debug, ops::Deref, rc::Rc};
+
+    /// Instructions to check pairings in circuit.
+    pub trait PairingInstructions<M: MultiMillerLoop>: EccInstructions<M::G1Affine> {
+        /// Enforce product of pairings of assigned points on G1 and constant
+        /// points on G2 to be identity.
+        fn assert_pairing_product_is_identity(
+            &self,
+            ctx: &mut Self::Context,
+            pairs: &[(impl Deref<Target = Self::AssignedEcPoint>, M::G2Affine)],
+        );
+    }
+
+    impl<M, MOS, EccChip> AccumulationDecider<M::G1Affine, Rc<Halo2Loader<M::G1Affine, EccChip>>>
+        for KzgAs<M, MOS>
+    where
+        M: MultiMillerLoop,
+        MOS: Clone + Debug,
+        EccChip: PairingInstructions<M>,
+    {
+        type DecidingKey = KzgDecidingKey<M>;
+
+        fn decide(
+            dk: &Self::DecidingKey,
+            KzgAccumulator { lhs, rhs }: KzgAccumulator<
+                M::G1Affine,
+                Rc<Halo2Loader<M::G1Affine, EccChip>>,
+            >,
+        ) -> Result<(), Error> {
+            let loader = lhs.loader();
+            let pairs = [(lhs.assigned(), dk.g2), (rhs.assigned(), -dk.s_g2)];
+            loader.ecc_chip().assert_pairing_product_is_identity(&mut loader.ctx_mut(), &pairs);
+            Ok(())
+        }
+
+        fn decide_all(
+            dk: &Self::DecidingKey,
+            accumulators: Vec<KzgAccumulator<M::G1Affine, Rc<Halo2Loader<M::G1Affine, EccChip>>>>,
+        ) -> Result<(), Error> {
+            assert!(!accumulators.is_empty());
+            // Unlike the EVM one, accumulators are not combined into one pairing by powers of a
+            // challenge, since there is no transcript here to squeeze it from soundly, and a
+            // single product of all pairings could let failing accumulators cancel each other.
+            // So it costs a full pairing per accumulator, and many accumulators should be
+            // accumulated by `AccumulationScheme` first to be decided once, as the aggregation
+            // circuit does.
+            accumulators
+                .into_iter()
+                .map(|accumulator| Self::decide(dk, accumulator))
+                .try_collect::<_, Vec<_>, _>()?;
+            Ok(())
+        }
+    }
+
+    mod halo2_lib {
+        use super::*;
+        use crate::halo2_curves::bn256::{Bn256, Fq12, G1Affine, G2Affine};
+        use crate::util::arithmetic::Field;
+        use halo2_ecc::{
+            bn254::{pairing::PairingChip, Fp12Chip, Fp2Chip},
+            ecc::{BaseFieldEccChip, EccChip},
+            fields::FieldChip,
+        };
+
+        impl<'chip> PairingInstructions<Bn256> for BaseFieldEccChip<'chip, G1Affine> {
+            fn assert_pairing_product_is_identity(
+                &self,
+                ctx: &mut Self::Context,
+                pairs: &[(impl Deref<Target = Self::AssignedEcPoint>, G2Affine)],
+            ) {
+                let fp2_chip = Fp2Chip::new(self.field_chip);
+                let g2_chip = EccChip::new(&fp2_chip);
+                let g2s = pairs
+                    .iter()
+                    .map(|(_, g2)| g2_chip.assign_constant_point(ctx.main(0), *g2))
+                    .collect_vec();
+
+                let pairing_chip = PairingChip::new(self.field_chip);
+                let f = pairing_chip.multi_miller_loop(
+                    ctx.main(0),
+                    pairs.iter().map(|(g1, _)| g1.deref()).zip(g2s.iter()).collect(),
+                );
+                let f = pairing_chip.final_exp(ctx.main(0), f);
+
+                let fp12_chip = Fp12Chip::new(self.field_chip);
+                let one = fp12_chip.load_constant(ctx.main(0), Fq12::one());
+                fp12_chip.assert_equal(ctx.main(0), f, one);
+            }
+        }
+    }
+}
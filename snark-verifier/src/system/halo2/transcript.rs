@@ -12,6 +12,8 @@ use crate::{
 };
 use std::io::{Read, Write};
 
+#[cfg(feature = "loader_evm")]
+pub mod blake2b;
 #[cfg(feature = "loader_evm")]
 pub mod evm;
 
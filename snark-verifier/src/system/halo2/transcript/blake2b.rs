@@ -0,0 +1,520 @@
+//! Transcript for verifier on EVM using blake2b as hasher, which is compatible
+//! with `halo2_proofs::transcript::{Blake2bRead, Blake2bWrite}`.
+
+use crate::{
+    loader::{
+        evm::{loader::Value, modulus, u256_to_fe, EcPoint, EvmLoader, Scalar, U256},
+        native::{self, NativeLoader},
+        Loader,
+    },
+    util::{
+        arithmetic::{Coordinates, CurveAffine, Field, GroupEncoding, PrimeField},
+        transcript::{Transcript, TranscriptRead, TranscriptWrite},
+        Itertools,
+    },
+    Error,
+};
+use blake2b_simd::{Params, State};
+use std::{
+    io::{self, Read, Write},
+    iter,
+    marker::PhantomData,
+    rc::Rc,
+};
+
+const BLAKE2B_PREFIX_CHALLENGE: u8 = 0;
+const BLAKE2B_PREFIX_POINT: u8 = 1;
+const BLAKE2B_PREFIX_SCALAR: u8 = 2;
+
+const BLAKE2B_PERSONAL: &[u8; 16] = b"Halo2-Transcript";
+const BLAKE2B_ROUNDS: usize = 12;
+const BLAKE2B_BLOCK_SIZE: usize = 0x80;
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+/// Transcript for verifier on EVM using blake2b as hasher.
+///
+/// It absorbs data and derives challenges the same way as
+/// `halo2_proofs::transcript::Blake2bWrite` and
+/// `halo2_proofs::transcript::Blake2bRead` with `Challenge255`, so proofs
+/// generated by unmodified halo2 tooling can be verified on EVM. The stream
+/// also follows halo2's format, which encodes scalars in little-endian and
+/// elliptic curve points by `GroupEncoding`.
+///
+/// With [`EvmLoader`] the blake2b compression is performed by `BLAKE2F`
+/// precompile. The input length is known when generating the verifier, so
+/// the hasher state is tracked statically and only the compression of each
+/// block is computed at runtime. Decoding of elliptic curve points assumes
+/// the `GroupEncoding` of bn254 in `halo2curves`.
+#[derive(Debug)]
+pub struct EvmBlake2bTranscript<C: CurveAffine, L: Loader<C>, S, B> {
+    loader: L,
+    stream: S,
+    buf: B,
+    _marker: PhantomData<C>,
+}
+
+/// Hasher state of [`EvmBlake2bTranscript`] in EVM memory.
+///
+/// The memory chunk starts with the 213 bytes input of `BLAKE2F` precompile,
+/// followed by the buffer of not yet compressed message and the output of the
+/// final compression.
+#[derive(Debug)]
+pub struct Blake2bState {
+    ptr: usize,
+    len: usize,
+    compressed: usize,
+}
+
+impl Blake2bState {
+    const STATE_OFFSET: usize = 0x04;
+    const BLOCK_OFFSET: usize = 0x44;
+    const COUNTER_OFFSET: usize = 0xc4;
+    const MESSAGE_OFFSET: usize = 0x100;
+    const OUTPUT_OFFSET: usize = 0x200;
+    const SIZE: usize = 0x240;
+
+    fn ptr(&self, offset: usize) -> usize {
+        self.ptr + offset
+    }
+}
+
+fn reverse_bytes(value: U256) -> U256 {
+    let mut bytes = [0; 32];
+    value.to_big_endian(&mut bytes);
+    U256::from_little_endian(&bytes)
+}
+
+/// Returns word to be stored at the offset of counter in the input of
+/// `BLAKE2F` precompile, which consists of 16 bytes counter in little-endian
+/// and 1 byte final block indicator.
+fn blake2f_counter(counter: usize, last: bool) -> U256 {
+    let mut bytes = [0; 32];
+    bytes[..8].copy_from_slice(&(counter as u64).to_le_bytes());
+    bytes[16] = last as u8;
+    U256::from_big_endian(&bytes)
+}
+
+impl<C> EvmBlake2bTranscript<C, Rc<EvmLoader>, usize, Blake2bState>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+{
+    /// Initialize [`EvmBlake2bTranscript`] given [`Rc<EvmLoader>`] and
+    /// pre-allocate memory for the hasher state.
+    pub fn new(loader: &Rc<EvmLoader>) -> Self {
+        let ptr = loader.allocate(Blake2bState::SIZE);
+        let buf = Blake2bState { ptr, len: 0, compressed: 0 };
+
+        let mut h = BLAKE2B_IV;
+        // digest length 64, key length 0, fanout 1 and depth 1
+        h[0] ^= 0x01010040;
+        h[6] ^= u64::from_le_bytes(BLAKE2B_PERSONAL[..8].try_into().unwrap());
+        h[7] ^= u64::from_le_bytes(BLAKE2B_PERSONAL[8..].try_into().unwrap());
+        let h = h.iter().flat_map(|h| h.to_le_bytes()).collect_vec();
+        let [h_lo, h_hi] = [&h[..0x20], &h[0x20..]].map(U256::from_big_endian);
+        let [rounds_ptr, h_lo_ptr, h_hi_ptr] =
+            [0, Blake2bState::STATE_OFFSET, Blake2bState::STATE_OFFSET + 0x20]
+                .map(|offset| buf.ptr(offset));
+        let rounds = U256::from(BLAKE2B_ROUNDS) << 224;
+        let code = format!(
+            "
+        mstore({rounds_ptr:#x}, {rounds})
+        mstore({h_lo_ptr:#x}, {h_lo})
+        mstore({h_hi_ptr:#x}, {h_hi})"
+        );
+        // absorbed words and squeezed challenges are in little-endian
+        loader.code_mut().require_reverse_bytes();
+        loader.code_mut().runtime_append(code);
+
+        Self { loader: loader.clone(), stream: 0, buf, _marker: PhantomData }
+    }
+
+    /// Load `num_instance` instances from calldata to memory.
+    pub fn load_instances(&mut self, num_instance: Vec<usize>) -> Vec<Vec<Scalar>> {
+        let instances = num_instance
+            .into_iter()
+            .map(|len| {
+                iter::repeat_with(|| {
+                    let scalar = self.loader.calldataload_scalar(self.stream);
+                    self.stream += 0x20;
+                    scalar
+                })
+                .take(len)
+                .collect_vec()
+            })
+            .collect_vec();
+        self.loader.record_instances(instances.iter().flatten());
+        instances
+    }
+
+    /// Checks calldata ends right after what has been read, so nothing could
+    /// be appended to the proof. It should be called after the whole proof is
+    /// read.
+    pub fn check_proof_len(&self) {
+        self.loader.calldata_check_end(self.stream.into());
+    }
+
+    /// Appends `prefix` and `words` to the message, where each word is an
+    /// expression of 32 bytes to be absorbed in the order of memory.
+    fn absorb(&mut self, prefix: u8, words: &[String]) {
+        let ptr = self.buf.ptr(Blake2bState::MESSAGE_OFFSET + self.buf.len);
+        let code = iter::once(format!("mstore8({ptr:#x}, {prefix})"))
+            .chain(
+                words
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, word)| format!("mstore({:#x}, {word})", ptr + 1 + idx * 0x20)),
+            )
+            .join("\n");
+        self.loader.code_mut().runtime_append(code);
+        self.buf.len += 1 + words.len() * 0x20;
+
+        // Blake2b only compresses a full block when there is more message
+        if self.buf.len > BLAKE2B_BLOCK_SIZE {
+            self.compress();
+        }
+    }
+
+    /// Compresses the first block in message buffer into the hasher state, and
+    /// moves the rest to the beginning.
+    fn compress(&mut self) {
+        assert!(self.buf.len <= 2 * BLAKE2B_BLOCK_SIZE);
+        self.buf.compressed += BLAKE2B_BLOCK_SIZE;
+        self.copy_block(BLAKE2B_BLOCK_SIZE);
+        let counter_ptr = self.buf.ptr(Blake2bState::COUNTER_OFFSET);
+        let counter = blake2f_counter(self.buf.compressed, false);
+        self.loader.code_mut().runtime_append(format!("mstore({counter_ptr:#x}, {counter})"));
+        self.loader.blake2f(self.buf.ptr(0), self.buf.ptr(Blake2bState::STATE_OFFSET));
+
+        self.buf.len -= BLAKE2B_BLOCK_SIZE;
+        let msg_ptr = self.buf.ptr(Blake2bState::MESSAGE_OFFSET);
+        let code = (0..self.buf.len)
+            .step_by(0x20)
+            .map(|offset| {
+                let src_ptr = msg_ptr + BLAKE2B_BLOCK_SIZE + offset;
+                format!("mstore({:#x}, mload({src_ptr:#x}))", msg_ptr + offset)
+            })
+            .join("\n");
+        self.loader.code_mut().runtime_append(code);
+    }
+
+    /// Copies first `len` bytes in message buffer into the block of `BLAKE2F`
+    /// input, with the rest of block padded with zeros.
+    fn copy_block(&self, len: usize) {
+        let msg_ptr = self.buf.ptr(Blake2bState::MESSAGE_OFFSET);
+        let block_ptr = self.buf.ptr(Blake2bState::BLOCK_OFFSET);
+        let code = (0..BLAKE2B_BLOCK_SIZE)
+            .step_by(0x20)
+            .map(|offset| {
+                let ptr = block_ptr + offset;
+                let src_ptr = msg_ptr + offset;
+                if offset + 0x20 <= len {
+                    format!("mstore({ptr:#x}, mload({src_ptr:#x}))")
+                } else if offset < len {
+                    let shift = (len - offset) * 8;
+                    format!(
+                        "mstore({ptr:#x}, bitand(mload({src_ptr:#x}), bitnot(shr({shift}, bitnot(0)))))"
+                    )
+                } else {
+                    format!("mstore({ptr:#x}, 0)")
+                }
+            })
+            .join("\n");
+        self.loader.code_mut().runtime_append(code);
+    }
+}
+
+impl<C> Transcript<C, Rc<EvmLoader>> for EvmBlake2bTranscript<C, Rc<EvmLoader>, usize, Blake2bState>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+{
+    fn loader(&self) -> &Rc<EvmLoader> {
+        &self.loader
+    }
+
+    fn squeeze_challenge(&mut self) -> Scalar {
+        self.absorb(BLAKE2B_PREFIX_CHALLENGE, &[]);
+
+        // Finalize a copy of the hasher state as halo2 does
+        self.copy_block(self.buf.len);
+        let counter_ptr = self.buf.ptr(Blake2bState::COUNTER_OFFSET);
+        let counter = blake2f_counter(self.buf.compressed + self.buf.len, true);
+        self.loader.code_mut().runtime_append(format!("mstore({counter_ptr:#x}, {counter})"));
+        let output_ptr = self.buf.ptr(Blake2bState::OUTPUT_OFFSET);
+        self.loader.blake2f(self.buf.ptr(0), output_ptr);
+
+        // Reduce the 64 bytes output in little-endian as `Challenge255` does
+        let challenge_ptr = self.loader.allocate(0x20);
+        let output_hi_ptr = output_ptr + 0x20;
+        let two_to_256 = (U256::MAX % modulus::<C::Scalar>()) + 1;
+        let code = format!(
+            "
+        {{
+            let lo := reverse_bytes(mload({output_ptr:#x}))
+            let hi := reverse_bytes(mload({output_hi_ptr:#x}))
+            mstore({challenge_ptr:#x}, addmod(lo, mulmod(hi, {two_to_256}, f_q), f_q))
+        }}"
+        );
+        self.loader.code_mut().runtime_append(code);
+
+        self.loader.scalar(Value::Memory(challenge_ptr))
+    }
+
+    fn common_ec_point(&mut self, ec_point: &EcPoint) -> Result<(), Error> {
+        let words = match ec_point.value() {
+            Value::Constant((x, y)) => {
+                [x, y].map(|coordinate| reverse_bytes(coordinate).to_string())
+            }
+            Value::Memory(ptr) => {
+                [ptr, ptr + 0x20].map(|ptr| format!("reverse_bytes(mload({ptr:#x}))"))
+            }
+            _ => unreachable!(),
+        };
+        self.absorb(BLAKE2B_PREFIX_POINT, &words);
+        Ok(())
+    }
+
+    fn common_scalar(&mut self, scalar: &Scalar) -> Result<(), Error> {
+        let word = match scalar.value() {
+            Value::Constant(constant) => reverse_bytes(constant).to_string(),
+            _ => format!("reverse_bytes({})", self.loader.push(scalar)),
+        };
+        self.absorb(BLAKE2B_PREFIX_SCALAR, &[word]);
+        Ok(())
+    }
+}
+
+impl<C> TranscriptRead<C, Rc<EvmLoader>>
+    for EvmBlake2bTranscript<C, Rc<EvmLoader>, usize, Blake2bState>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+{
+    fn read_scalar(&mut self) -> Result<Scalar, Error> {
+        let scalar = self.loader.calldataload_scalar_le(self.stream);
+        // Absorb the little-endian representation in calldata as is
+        self.absorb(BLAKE2B_PREFIX_SCALAR, &[format!("calldataload({:#x})", self.stream)]);
+        self.stream += 0x20;
+        Ok(scalar)
+    }
+
+    fn read_ec_point(&mut self) -> Result<EcPoint, Error> {
+        let ec_point = self.loader.calldataload_ec_point_compressed_le(self.stream);
+        self.stream += 0x20;
+        self.common_ec_point(&ec_point)?;
+        Ok(ec_point)
+    }
+}
+
+impl<C, S> EvmBlake2bTranscript<C, NativeLoader, S, State>
+where
+    C: CurveAffine,
+{
+    /// Initialize [`EvmBlake2bTranscript`] given readable or writeable stream
+    /// for verifying or proving with [`NativeLoader`].
+    pub fn new(stream: S) -> Self {
+        let buf = Params::new().hash_length(64).personal(BLAKE2B_PERSONAL).to_state();
+        Self { loader: NativeLoader, stream, buf, _marker: PhantomData }
+    }
+}
+
+impl<C, S> Transcript<C, NativeLoader> for EvmBlake2bTranscript<C, NativeLoader, S, State>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+{
+    fn loader(&self) -> &NativeLoader {
+        &native::LOADER
+    }
+
+    fn squeeze_challenge(&mut self) -> C::Scalar {
+        self.buf.update(&[BLAKE2B_PREFIX_CHALLENGE]);
+        let hash = self.buf.clone().finalize();
+        let [lo, hi] = [&hash.as_bytes()[..0x20], &hash.as_bytes()[0x20..]]
+            .map(|bytes| u256_to_fe::<C::Scalar>(U256::from_little_endian(bytes)));
+        let two_to_256 = u256_to_fe::<C::Scalar>(U256::MAX) + C::Scalar::one();
+        lo + hi * two_to_256
+    }
+
+    fn common_ec_point(&mut self, ec_point: &C) -> Result<(), Error> {
+        let coordinates =
+            Option::<Coordinates<C>>::from(ec_point.coordinates()).ok_or_else(|| {
+                Error::Transcript(io::ErrorKind::Other, "Invalid elliptic curve point".to_string())
+            })?;
+
+        self.buf.update(&[BLAKE2B_PREFIX_POINT]);
+        self.buf.update(coordinates.x().to_repr().as_ref());
+        self.buf.update(coordinates.y().to_repr().as_ref());
+
+        Ok(())
+    }
+
+    fn common_scalar(&mut self, scalar: &C::Scalar) -> Result<(), Error> {
+        self.buf.update(&[BLAKE2B_PREFIX_SCALAR]);
+        self.buf.update(scalar.to_repr().as_ref());
+
+        Ok(())
+    }
+}
+
+impl<C, S> TranscriptRead<C, NativeLoader> for EvmBlake2bTranscript<C, NativeLoader, S, State>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+    S: Read,
+{
+    fn read_scalar(&mut self) -> Result<C::Scalar, Error> {
+        let mut data = <C::Scalar as PrimeField>::Repr::default();
+        self.stream
+            .read_exact(data.as_mut())
+            .map_err(|err| Error::Transcript(err.kind(), err.to_string()))?;
+        let scalar = Option::from(C::Scalar::from_repr(data)).ok_or_else(|| {
+            Error::Transcript(io::ErrorKind::Other, "Invalid scalar encoding in proof".to_string())
+        })?;
+        self.common_scalar(&scalar)?;
+        Ok(scalar)
+    }
+
+    fn read_ec_point(&mut self) -> Result<C, Error> {
+        let mut compressed = <C as GroupEncoding>::Repr::default();
+        self.stream
+            .read_exact(compressed.as_mut())
+            .map_err(|err| Error::Transcript(err.kind(), err.to_string()))?;
+        let ec_point = Option::from(C::from_bytes(&compressed)).ok_or_else(|| {
+            Error::Transcript(
+                io::ErrorKind::Other,
+                "Invalid elliptic curve point encoding in proof".to_string(),
+            )
+        })?;
+        self.common_ec_point(&ec_point)?;
+        Ok(ec_point)
+    }
+}
+
+impl<C, S> TranscriptWrite<C> for EvmBlake2bTranscript<C, NativeLoader, S, State>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+    S: Write,
+{
+    fn write_scalar(&mut self, scalar: C::Scalar) -> Result<(), Error> {
+        self.common_scalar(&scalar)?;
+        self.stream
+            .write_all(scalar.to_repr().as_ref())
+            .map_err(|err| Error::Transcript(err.kind(), err.to_string()))
+    }
+
+    fn write_ec_point(&mut self, ec_point: C) -> Result<(), Error> {
+        self.common_ec_point(&ec_point)?;
+        self.stream
+            .write_all(ec_point.to_bytes().as_ref())
+            .map_err(|err| Error::Transcript(err.kind(), err.to_string()))
+    }
+}
+
+impl<C, S> EvmBlake2bTranscript<C, NativeLoader, S, State>
+where
+    C: CurveAffine,
+    S: Write,
+{
+    /// Returns mutable `stream`.
+    pub fn stream_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Finalize transcript and returns `stream`.
+    pub fn finalize(self) -> S {
+        self.stream
+    }
+}
+
+#[test]
+fn test_evm_blake2b_transcript() {
+    use crate::{
+        halo2_curves::bn256::{Fr, G1Affine, G1},
+        halo2_proofs::transcript::{Blake2bWrite, Challenge255, TranscriptWriterBuffer},
+        util::arithmetic::{Curve, Group},
+    };
+    use rand::rngs::OsRng;
+
+    let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(Vec::new());
+    let challenges = iter::repeat_with(|| {
+        TranscriptWrite::write_scalar(&mut transcript, Fr::random(OsRng)).unwrap();
+        TranscriptWrite::write_ec_point(&mut transcript, G1::random(OsRng).to_affine()).unwrap();
+        Transcript::<G1Affine, NativeLoader>::squeeze_challenge(&mut transcript)
+    })
+    .take(4)
+    .collect_vec();
+    let proof = transcript.finalize();
+
+    let mut transcript =
+        EvmBlake2bTranscript::<G1Affine, NativeLoader, _, _>::new(proof.as_slice());
+    for challenge in challenges {
+        transcript.read_scalar().unwrap();
+        transcript.read_ec_point().unwrap();
+        assert_eq!(transcript.squeeze_challenge(), challenge);
+    }
+}
+
+#[test]
+fn test_evm_blake2b_transcript_on_evm() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine, G1},
+        halo2_proofs::transcript::{Blake2bWrite, Challenge255, TranscriptWriterBuffer},
+        loader::{
+            evm::{encode_calldata, execute_yul},
+            EcPointLoader, ScalarLoader,
+        },
+        util::arithmetic::{Curve, Group},
+    };
+    use rand::rngs::OsRng;
+
+    // enough rounds for the message to span several blocks
+    let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(Vec::new());
+    let rounds = iter::repeat_with(|| {
+        let scalar = Fr::random(OsRng);
+        let ec_point = G1::random(OsRng).to_affine();
+        TranscriptWrite::write_scalar(&mut transcript, scalar).unwrap();
+        TranscriptWrite::write_ec_point(&mut transcript, ec_point).unwrap();
+        let challenge = Transcript::<G1Affine, NativeLoader>::squeeze_challenge(&mut transcript);
+        (scalar, ec_point, challenge)
+    })
+    .take(6)
+    .collect_vec();
+    let proof = transcript.finalize();
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let mut transcript = EvmBlake2bTranscript::<G1Affine, Rc<EvmLoader>, _, _>::new(&loader);
+    for (scalar, ec_point, challenge) in rounds {
+        let loaded_scalar = transcript.read_scalar().unwrap();
+        let loaded_ec_point = transcript.read_ec_point().unwrap();
+        let loaded_challenge = transcript.squeeze_challenge();
+        for (lhs, rhs) in [(loaded_scalar, scalar), (loaded_challenge, challenge)] {
+            let rhs = ScalarLoader::<Fr>::load_const(&loader, &rhs);
+            ScalarLoader::<Fr>::assert_eq(&loader, "", &lhs, &rhs);
+        }
+        let ec_point = EcPointLoader::<G1Affine>::ec_point_load_const(&loader, &ec_point);
+        EcPointLoader::<G1Affine>::ec_point_assert_eq(&loader, "", &loaded_ec_point, &ec_point);
+    }
+    transcript.check_proof_len();
+    let (code, labels) = loader.yul_code_with_labels();
+
+    let verify = |proof: &[u8]| execute_yul(&code, &labels, encode_calldata::<Fr>(&[], proof)).0;
+    assert!(verify(&proof));
+    // the least significant byte of the first scalar
+    let mut tampered_proof = proof.clone();
+    tampered_proof[0] ^= 1;
+    assert!(!verify(&tampered_proof));
+    assert!(!verify(&proof[..proof.len() - 1]));
+    assert!(!verify(&[proof.as_slice(), &[0]].concat()));
+}
@@ -3,7 +3,11 @@
 use crate::halo2_proofs;
 use crate::{
     loader::{
-        evm::{loader::Value, u256_to_fe, util::MemoryChunk, EcPoint, EvmLoader, Scalar, U256},
+        evm::{
+            compress_ec_point, decompress_ec_point, fn_selector, loader::Value, u256_to_fe,
+            util::MemoryChunk, CalldataPtr, EcPoint, EvmLoader, EvmVerifyingKey, Scalar, U256,
+            VERIFY_PROOF_SIGNATURE,
+        },
         native::{self, NativeLoader},
         Loader,
     },
@@ -23,16 +27,50 @@ use std::{
     rc::Rc,
 };
 
+#[cfg(feature = "loader_halo2")]
+mod halo2;
+
+#[cfg(feature = "loader_halo2")]
+pub use halo2::KeccakInstructions;
+
 /// Transcript for verifier on EVM using keccak256 as hasher.
+///
+/// When `COMPRESSED` is `true`, elliptic curve points in the stream are
+/// compressed into 32 bytes by [`compress_ec_point`]. It saves 32 bytes of
+/// calldata (up to 512 gas) per point, but decompression on EVM costs about
+/// 1500 gas per point, so it only pays off where calldata is expensive (e.g.
+/// on rollups), as measured by `test_compressed_ec_point`. The absorbed data
+/// and so the challenges are the same in both modes.
+///
+/// On EVM, instances and proof are read from calldata encoded by
+/// [`encode_calldata`](crate::loader::evm::encode_calldata) by default, or
+/// from calldata of `verifyProof(bytes proof, uint256[] instances)` encoded by
+/// [`encode_calldata_abi`](crate::loader::evm::encode_calldata_abi) if created
+/// by [`EvmTranscript::new_abi`].
 #[derive(Debug)]
-pub struct EvmTranscript<C: CurveAffine, L: Loader<C>, S, B> {
+pub struct EvmTranscript<C: CurveAffine, L: Loader<C>, S, B, const COMPRESSED: bool = false> {
     loader: L,
     stream: S,
     buf: B,
     _marker: PhantomData<C>,
 }
 
-impl<C> EvmTranscript<C, Rc<EvmLoader>, usize, MemoryChunk>
+/// Stream of [`EvmTranscript`] with [`EvmLoader`], which points to the proof
+/// and, if ABI-encoded, also to the instances in calldata.
+#[derive(Clone, Copy, Debug)]
+pub struct CalldataStream {
+    proof: CalldataPtr,
+    instances: Option<CalldataPtr>,
+}
+
+/// [`EvmTranscript`] with compressed elliptic curve points in the stream.
+pub type CompressedEvmTranscript<C, L, S, B> = EvmTranscript<C, L, S, B, true>;
+
+/// [`EvmTranscript`] with [`EvmLoader`], which reads the stream from calldata.
+pub type EvmLoaderTranscript<C> = EvmTranscript<C, Rc<EvmLoader>, CalldataStream, MemoryChunk>;
+
+impl<C, const COMPRESSED: bool>
+    EvmTranscript<C, Rc<EvmLoader>, CalldataStream, MemoryChunk, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 0x20]>,
@@ -40,31 +78,108 @@ where
     /// Initialize [`EvmTranscript`] given [`Rc<EvmLoader>`] and pre-allocate an
     /// u256 for `transcript_initial_state`.
     pub fn new(loader: &Rc<EvmLoader>) -> Self {
+        Self::new_at(loader, 0)
+    }
+
+    /// Initialize [`EvmTranscript`] as [`EvmTranscript::new`] but reading
+    /// calldata from `offset`, so several proofs could be read from the same
+    /// calldata each by its own transcript.
+    pub fn new_at(loader: &Rc<EvmLoader>, offset: impl Into<CalldataPtr>) -> Self {
         let ptr = loader.allocate(0x20);
-        assert_eq!(ptr, 0);
-        let mut buf = MemoryChunk::new(ptr);
-        buf.extend(0x20);
-        Self { loader: loader.clone(), stream: 0, buf, _marker: PhantomData }
+        let buf = MemoryChunk::new(ptr);
+        Self {
+            loader: loader.clone(),
+            stream: CalldataStream { proof: offset.into(), instances: None },
+            buf,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Initialize [`EvmTranscript`] as [`EvmTranscript::new`] but reading
+    /// calldata of `verifyProof(bytes proof, uint256[] instances)` in standard
+    /// ABI encoding, so the verifier could be called as a normal contract
+    /// function.
+    ///
+    /// The offsets of `proof` and `instances` are decoded at runtime, and their
+    /// lengths are checked by [`EvmTranscript::load_instances`] and
+    /// [`EvmTranscript::check_proof_len`] respectively.
+    pub fn new_abi(loader: &Rc<EvmLoader>) -> Self {
+        let [proof, instances] =
+            loader.calldata_abi_head(fn_selector(VERIFY_PROOF_SIGNATURE), 2).try_into().unwrap();
+        let mut transcript = Self::new_at(loader, proof);
+        transcript.stream.instances = Some(instances);
+        transcript
+    }
+
+    /// Returns offset of calldata to be read next.
+    pub fn offset(&self) -> CalldataPtr {
+        self.stream.proof
+    }
+
+    /// Checks length of `proof` is the same as what has been read if created
+    /// by [`EvmTranscript::new_abi`], otherwise checks calldata ends right after
+    /// what has been read. It should be called after the whole proof is read.
+    ///
+    /// Without it, bytes appended to a valid proof would still be accepted, so
+    /// calldata accepted by the verifier wouldn't be unique to the proof.
+    pub fn check_proof_len(&self) {
+        let proof = self.stream.proof;
+        if self.stream.instances.is_some() {
+            let len = proof.offset();
+            self.loader.calldata_abi_check_len(proof.base(), len, len);
+        } else {
+            self.loader.calldata_check_end(proof);
+        }
+    }
+
+    /// Copies `transcript_initial_state` of `vk` from the verifying key contract
+    /// into the pre-allocated u256 and includes it into buffer, so the protocol
+    /// should be loaded without `transcript_initial_state`. It should be called
+    /// before anything is absorbed.
+    pub fn copy_initial_state(&mut self, vk: &EvmVerifyingKey) {
+        assert_eq!(self.buf.len(), 0, "transcript has absorbed something");
+        self.loader.copy_transcript_initial_state(vk, self.buf.ptr());
+        self.buf.extend(0x20);
+    }
+
+    /// Includes the pre-allocated `transcript_initial_state` as zero into
+    /// buffer if nothing has been absorbed yet.
+    fn include_initial_state(&mut self) {
+        if self.buf.len() == 0 {
+            // the pre-allocated u256 might be dirty if freed memory is reused
+            let ptr = self.buf.ptr();
+            self.loader.code_mut().runtime_append(format!("mstore({ptr:#x}, 0)"));
+            self.buf.extend(0x20);
+        }
     }
 
     /// Load `num_instance` instances from calldata to memory.
     pub fn load_instances(&mut self, num_instance: Vec<usize>) -> Vec<Vec<Scalar>> {
-        num_instance
+        let loader = self.loader.clone();
+        let stream = self.stream.instances.as_mut().unwrap_or(&mut self.stream.proof);
+        let instances = num_instance
             .into_iter()
             .map(|len| {
                 iter::repeat_with(|| {
-                    let scalar = self.loader.calldataload_scalar(self.stream);
-                    self.stream += 0x20;
+                    let scalar = loader.calldataload_scalar(*stream);
+                    *stream += 0x20;
                     scalar
                 })
                 .take(len)
                 .collect_vec()
             })
-            .collect()
+            .collect_vec();
+        loader.record_instances(instances.iter().flatten());
+        if let Some(stream) = self.stream.instances {
+            let len = stream.offset() / 0x20;
+            loader.calldata_abi_check_len(stream.base(), len, len * 0x20);
+        }
+        instances
     }
 }
 
-impl<C> Transcript<C, Rc<EvmLoader>> for EvmTranscript<C, Rc<EvmLoader>, usize, MemoryChunk>
+impl<C, const COMPRESSED: bool> Transcript<C, Rc<EvmLoader>>
+    for EvmTranscript<C, Rc<EvmLoader>, CalldataStream, MemoryChunk, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 0x20]>,
@@ -76,6 +191,8 @@ where
     /// Does not allow the input to be a one-byte sequence, because the Transcript trait only supports writing scalars and elliptic curve points.
     /// If the one-byte sequence [0x01] is a valid input to the transcript, the empty input [] will have the same transcript result as [0x01].
     fn squeeze_challenge(&mut self) -> Scalar {
+        self.loader.start_label("squeeze_challenge");
+        self.include_initial_state();
         let len = if self.buf.len() == 0x20 {
             assert_eq!(self.loader.ptr(), self.buf.end());
             let buf_end = self.buf.end();
@@ -100,11 +217,13 @@ where
 
         self.buf.reset(dup_hash_ptr);
         self.buf.extend(0x20);
+        self.loader.end_label();
 
         self.loader.scalar(Value::Memory(challenge_ptr))
     }
 
     fn common_ec_point(&mut self, ec_point: &EcPoint) -> Result<(), Error> {
+        self.include_initial_state();
         if let Value::Memory(ptr) = ec_point.value() {
             assert_eq!(self.buf.end(), ptr);
             self.buf.extend(0x40);
@@ -116,10 +235,12 @@ where
 
     fn common_scalar(&mut self, scalar: &Scalar) -> Result<(), Error> {
         match scalar.value() {
-            Value::Constant(_) if self.buf.ptr() == 0 => {
+            Value::Constant(_) if self.buf.len() == 0 => {
                 self.loader.copy_scalar(scalar, self.buf.ptr());
+                self.buf.extend(0x20);
             }
             Value::Memory(ptr) => {
+                self.include_initial_state();
                 assert_eq!(self.buf.end(), ptr);
                 self.buf.extend(0x20);
             }
@@ -129,27 +250,39 @@ where
     }
 }
 
-impl<C> TranscriptRead<C, Rc<EvmLoader>> for EvmTranscript<C, Rc<EvmLoader>, usize, MemoryChunk>
+impl<C, const COMPRESSED: bool> TranscriptRead<C, Rc<EvmLoader>>
+    for EvmTranscript<C, Rc<EvmLoader>, CalldataStream, MemoryChunk, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 0x20]>,
 {
     fn read_scalar(&mut self) -> Result<Scalar, Error> {
-        let scalar = self.loader.calldataload_scalar(self.stream);
-        self.stream += 0x20;
+        self.loader.start_label("read_scalar");
+        let scalar = self.loader.calldataload_scalar(self.stream.proof);
+        self.stream.proof += 0x20;
+        self.loader.end_label();
         self.common_scalar(&scalar)?;
         Ok(scalar)
     }
 
     fn read_ec_point(&mut self) -> Result<EcPoint, Error> {
-        let ec_point = self.loader.calldataload_ec_point(self.stream);
-        self.stream += 0x40;
+        self.loader.start_label("read_ec_point");
+        let ec_point = if COMPRESSED {
+            let ec_point = self.loader.calldataload_ec_point_compressed(self.stream.proof);
+            self.stream.proof += 0x20;
+            ec_point
+        } else {
+            let ec_point = self.loader.calldataload_ec_point(self.stream.proof);
+            self.stream.proof += 0x40;
+            ec_point
+        };
+        self.loader.end_label();
         self.common_ec_point(&ec_point)?;
         Ok(ec_point)
     }
 }
 
-impl<C, S> EvmTranscript<C, NativeLoader, S, Vec<u8>>
+impl<C, S, const COMPRESSED: bool> EvmTranscript<C, NativeLoader, S, Vec<u8>, COMPRESSED>
 where
     C: CurveAffine,
 {
@@ -160,7 +293,8 @@ where
     }
 }
 
-impl<C, S> Transcript<C, NativeLoader> for EvmTranscript<C, NativeLoader, S, Vec<u8>>
+impl<C, S, const COMPRESSED: bool> Transcript<C, NativeLoader>
+    for EvmTranscript<C, NativeLoader, S, Vec<u8>, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 0x20]>,
@@ -201,7 +335,8 @@ where
     }
 }
 
-impl<C, S> TranscriptRead<C, NativeLoader> for EvmTranscript<C, NativeLoader, S, Vec<u8>>
+impl<C, S, const COMPRESSED: bool> TranscriptRead<C, NativeLoader>
+    for EvmTranscript<C, NativeLoader, S, Vec<u8>, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 0x20]>,
@@ -221,28 +356,36 @@ where
     }
 
     fn read_ec_point(&mut self) -> Result<C, Error> {
-        let [mut x, mut y] = [<C::Base as PrimeField>::Repr::default(); 2];
-        for repr in [&mut x, &mut y] {
+        let ec_point = if COMPRESSED {
+            let mut x = <C::Base as PrimeField>::Repr::default();
             self.stream
-                .read_exact(repr.as_mut())
+                .read_exact(x.as_mut())
                 .map_err(|err| Error::Transcript(err.kind(), err.to_string()))?;
-            repr.as_mut().reverse();
+            decompress_ec_point(x)
+        } else {
+            let [mut x, mut y] = [<C::Base as PrimeField>::Repr::default(); 2];
+            for repr in [&mut x, &mut y] {
+                self.stream
+                    .read_exact(repr.as_mut())
+                    .map_err(|err| Error::Transcript(err.kind(), err.to_string()))?;
+                repr.as_mut().reverse();
+            }
+            let x = Option::from(<C::Base as PrimeField>::from_repr(x));
+            let y = Option::from(<C::Base as PrimeField>::from_repr(y));
+            x.zip(y).and_then(|(x, y)| Option::from(C::from_xy(x, y)))
         }
-        let x = Option::from(<C::Base as PrimeField>::from_repr(x));
-        let y = Option::from(<C::Base as PrimeField>::from_repr(y));
-        let ec_point =
-            x.zip(y).and_then(|(x, y)| Option::from(C::from_xy(x, y))).ok_or_else(|| {
-                Error::Transcript(
-                    io::ErrorKind::Other,
-                    "Invalid elliptic curve point encoding in proof".to_string(),
-                )
-            })?;
+        .ok_or_else(|| {
+            Error::Transcript(
+                io::ErrorKind::Other,
+                "Invalid elliptic curve point encoding in proof".to_string(),
+            )
+        })?;
         self.common_ec_point(&ec_point)?;
         Ok(ec_point)
     }
 }
 
-impl<C, S> EvmTranscript<C, NativeLoader, S, Vec<u8>>
+impl<C, S, const COMPRESSED: bool> EvmTranscript<C, NativeLoader, S, Vec<u8>, COMPRESSED>
 where
     C: CurveAffine,
     S: Write,
@@ -282,8 +425,8 @@ where
     }
 }
 
-impl<C, S> halo2_proofs::transcript::Transcript<C, ChallengeEvm<C>>
-    for EvmTranscript<C, NativeLoader, S, Vec<u8>>
+impl<C, S, const COMPRESSED: bool> halo2_proofs::transcript::Transcript<C, ChallengeEvm<C>>
+    for EvmTranscript<C, NativeLoader, S, Vec<u8>, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 32]>,
@@ -309,8 +452,9 @@ where
     }
 }
 
-impl<C, R: Read> halo2_proofs::transcript::TranscriptRead<C, ChallengeEvm<C>>
-    for EvmTranscript<C, NativeLoader, R, Vec<u8>>
+impl<C, R: Read, const COMPRESSED: bool>
+    halo2_proofs::transcript::TranscriptRead<C, ChallengeEvm<C>>
+    for EvmTranscript<C, NativeLoader, R, Vec<u8>, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 32]>,
@@ -332,8 +476,9 @@ where
     }
 }
 
-impl<C, R: Read> halo2_proofs::transcript::TranscriptReadBuffer<R, C, ChallengeEvm<C>>
-    for EvmTranscript<C, NativeLoader, R, Vec<u8>>
+impl<C, R: Read, const COMPRESSED: bool>
+    halo2_proofs::transcript::TranscriptReadBuffer<R, C, ChallengeEvm<C>>
+    for EvmTranscript<C, NativeLoader, R, Vec<u8>, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 32]>,
@@ -343,14 +488,24 @@ where
     }
 }
 
-impl<C, W: Write> halo2_proofs::transcript::TranscriptWrite<C, ChallengeEvm<C>>
-    for EvmTranscript<C, NativeLoader, W, Vec<u8>>
+impl<C, W: Write, const COMPRESSED: bool>
+    halo2_proofs::transcript::TranscriptWrite<C, ChallengeEvm<C>>
+    for EvmTranscript<C, NativeLoader, W, Vec<u8>, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 32]>,
 {
     fn write_point(&mut self, ec_point: C) -> io::Result<()> {
         halo2_proofs::transcript::Transcript::<C, ChallengeEvm<C>>::common_point(self, ec_point)?;
+        if COMPRESSED {
+            let x = compress_ec_point(&ec_point).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    "Cannot write points at infinity to the transcript",
+                )
+            })?;
+            return self.stream_mut().write_all(x.as_ref());
+        }
         let coords: Coordinates<C> = Option::from(ec_point.coordinates()).ok_or_else(|| {
             io::Error::new(
                 io::ErrorKind::Other,
@@ -373,8 +528,9 @@ where
     }
 }
 
-impl<C, W: Write> halo2_proofs::transcript::TranscriptWriterBuffer<W, C, ChallengeEvm<C>>
-    for EvmTranscript<C, NativeLoader, W, Vec<u8>>
+impl<C, W: Write, const COMPRESSED: bool>
+    halo2_proofs::transcript::TranscriptWriterBuffer<W, C, ChallengeEvm<C>>
+    for EvmTranscript<C, NativeLoader, W, Vec<u8>, COMPRESSED>
 where
     C: CurveAffine,
     C::Scalar: PrimeField<Repr = [u8; 32]>,
@@ -387,3 +543,100 @@ where
         self.finalize()
     }
 }
+
+#[test]
+fn test_compressed_ec_point() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine},
+        loader::{
+            evm::{assemble_yul, encode_calldata, Address, ExecutorBuilder},
+            EcPointLoader, ScalarLoader,
+        },
+        util::arithmetic::Curve,
+    };
+    use halo2_proofs::transcript::TranscriptWrite;
+
+    const N: usize = 8;
+
+    // writes `ec_points` natively, reads them back natively and on EVM, and
+    // checks the squeezed challenges agree
+    fn gen<const COMPRESSED: bool>(state: Fr, ec_points: &[G1Affine]) -> (Vec<u8>, Fr, Vec<u8>) {
+        let proof = {
+            let mut transcript =
+                EvmTranscript::<G1Affine, NativeLoader, _, _, COMPRESSED>::new(Vec::new());
+            Transcript::common_scalar(&mut transcript, &state).unwrap();
+            for ec_point in ec_points {
+                transcript.write_point(*ec_point).unwrap();
+            }
+            transcript.finalize()
+        };
+        let challenge = {
+            let mut transcript =
+                EvmTranscript::<G1Affine, NativeLoader, _, _, COMPRESSED>::new(proof.as_slice());
+            Transcript::common_scalar(&mut transcript, &state).unwrap();
+            for ec_point in ec_points {
+                assert_eq!(transcript.read_ec_point().unwrap(), *ec_point);
+            }
+            transcript.squeeze_challenge()
+        };
+
+        let loader = EvmLoader::new::<Fq, Fr>();
+        // loaded before the transcript which expects what it reads to be
+        // contiguous in memory
+        let expected = ec_points
+            .iter()
+            .map(|ec_point| EcPointLoader::<G1Affine>::ec_point_load_const(&loader, ec_point))
+            .collect_vec();
+        let mut transcript =
+            EvmTranscript::<G1Affine, Rc<EvmLoader>, _, _, COMPRESSED>::new(&loader);
+        let state = ScalarLoader::<Fr>::load_const(&loader, &state);
+        transcript.common_scalar(&state).unwrap();
+        let ec_points = iter::repeat_with(|| transcript.read_ec_point().unwrap())
+            .take(ec_points.len())
+            .collect_vec();
+        let loaded_challenge = transcript.squeeze_challenge();
+        for (lhs, rhs) in ec_points.iter().zip(expected.iter()) {
+            EcPointLoader::<G1Affine>::ec_point_assert_eq(&loader, "", lhs, rhs);
+        }
+        let expected_challenge = ScalarLoader::<Fr>::load_const(&loader, &challenge);
+        ScalarLoader::<Fr>::assert_eq(&loader, "", &loaded_challenge, &expected_challenge);
+
+        (proof, challenge, assemble_yul(&loader.yul_code()).unwrap())
+    }
+
+    let state = Fr::from(42);
+    let ec_points =
+        (1..=N as u64).map(|k| (G1Affine::generator() * Fr::from(k)).to_affine()).collect_vec();
+    let (proof, challenge, code) = gen::<false>(state, &ec_points);
+    let (compressed_proof, compressed_challenge, compressed_code) = gen::<true>(state, &ec_points);
+    assert_eq!(challenge, compressed_challenge);
+    assert_eq!(proof.len(), compressed_proof.len() + N * 0x20);
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let mut verify = |code: Vec<u8>, proof: &[u8]| {
+        let verifier = evm.deploy(caller, code.into(), 0.into()).address.unwrap();
+        let calldata = encode_calldata::<Fr>(&[], proof);
+        let result = evm.call_raw(caller, verifier, calldata.clone().into(), 0.into());
+        (!result.reverted, result.gas_used, calldata)
+    };
+    let (accepted, gas, calldata) = verify(code, &proof);
+    assert!(accepted);
+    let (accepted, compressed_gas, compressed_calldata) =
+        verify(compressed_code.clone(), &compressed_proof);
+    assert!(accepted);
+    // flipping the sign bit decompresses to the negated point
+    let mut tampered_proof = compressed_proof.clone();
+    tampered_proof[0] ^= 0x80;
+    assert!(!verify(compressed_code, &tampered_proof).0);
+
+    // 16 gas per non-zero byte and 4 gas per zero byte
+    let calldata_gas =
+        |calldata: &[u8]| calldata.iter().map(|byte| if *byte == 0 { 4 } else { 16 }).sum::<u64>();
+    let saved = (calldata_gas(&calldata) - calldata_gas(&compressed_calldata)) / N as u64;
+    let decompression = ((compressed_gas - calldata_gas(&compressed_calldata))
+        - (gas - calldata_gas(&calldata)))
+        / N as u64;
+    assert!(saved <= 512, "{saved}");
+    assert!((1200..2000).contains(&decompression), "{decompression}");
+}
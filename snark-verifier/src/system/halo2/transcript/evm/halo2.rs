@@ -0,0 +1,501 @@
+use crate::{
+    loader::{
+        evm::decompress_ec_point,
+        halo2::{EcPoint, EccInstructions, Halo2Loader, IntegerInstructions, Scalar},
+    },
+    system::halo2::transcript::evm::EvmTranscript,
+    util::{
+        arithmetic::{powers, CurveAffine, Field, PrimeField},
+        transcript::{Transcript, TranscriptRead},
+        Itertools,
+    },
+    Error,
+};
+use std::{
+    io::{self, Read},
+    iter,
+    marker::PhantomData,
+    mem,
+    rc::Rc,
+};
+
+/// Instructions to derive challenges in circuit as [`EvmTranscript`] does,
+/// where bytes are represented by assigned bits, 8 per byte from the least
+/// significant one.
+pub trait KeccakInstructions<C: CurveAffine>: EccInstructions<C> {
+    /// Returns bits of `scalar` encoded in 32 bytes in big-endian.
+    fn scalar_to_bits(
+        &self,
+        ctx: &mut Self::Context,
+        scalar: &Self::AssignedScalar,
+    ) -> Vec<Self::AssignedScalar>;
+
+    /// Returns bits of coordinates of `ec_point` each encoded in 32 bytes in
+    /// big-endian.
+    fn ec_point_to_bits(
+        &self,
+        ctx: &mut Self::Context,
+        ec_point: &Self::AssignedEcPoint,
+    ) -> Vec<Self::AssignedScalar>;
+
+    /// Returns bits of keccak256 digest of `bits`, whose length should be a
+    /// multiple of 8.
+    fn keccak256(
+        &self,
+        ctx: &mut Self::Context,
+        bits: &[Self::AssignedScalar],
+    ) -> Vec<Self::AssignedScalar>;
+}
+
+impl<C, R, EccChip, const COMPRESSED: bool>
+    EvmTranscript<C, Rc<Halo2Loader<C, EccChip>>, R, Vec<EccChip::AssignedScalar>, COMPRESSED>
+where
+    C: CurveAffine,
+    EccChip: KeccakInstructions<C>,
+{
+    /// Initialize [`EvmTranscript`] given [`Rc<Halo2Loader>`] and readable
+    /// stream for verifying in circuit, which derives the same challenges as
+    /// the native one and the one on EVM, so a proof generated for verifier on
+    /// EVM could also be verified recursively.
+    ///
+    /// Keccak is computed on bits in circuit, with gates on each bit of its
+    /// 1600-bit state in each round, so it's much more expensive than
+    /// [`PoseidonTranscript`](crate::system::halo2::transcript::halo2::PoseidonTranscript).
+    /// The cells it takes could be measured by
+    /// [`Halo2Loader::cell_profile`].
+    pub fn new(loader: &Rc<Halo2Loader<C, EccChip>>, stream: R) -> Self {
+        Self { loader: loader.clone(), stream, buf: Vec::new(), _marker: PhantomData }
+    }
+
+    /// Clear the buffer and set the stream to a new one. Effectively the same
+    /// as starting from a new transcript.
+    pub fn new_stream(&mut self, stream: R) {
+        self.buf.clear();
+        self.stream = stream;
+    }
+}
+
+impl<C, R, EccChip, const COMPRESSED: bool> Transcript<C, Rc<Halo2Loader<C, EccChip>>>
+    for EvmTranscript<C, Rc<Halo2Loader<C, EccChip>>, R, Vec<EccChip::AssignedScalar>, COMPRESSED>
+where
+    C: CurveAffine,
+    EccChip: KeccakInstructions<C>,
+{
+    fn loader(&self) -> &Rc<Halo2Loader<C, EccChip>> {
+        &self.loader
+    }
+
+    fn squeeze_challenge(&mut self) -> Scalar<C, EccChip> {
+        let loader = self.loader.clone();
+        let data = if self.buf.len() == 0x100 {
+            let [zero, one] = [C::Scalar::zero(), C::Scalar::one()]
+                .map(|bit| loader.scalar_chip().assign_constant(&mut loader.ctx_mut(), bit));
+            let buf = mem::take(&mut self.buf);
+            buf.into_iter().chain(Some(one)).chain(iter::repeat(zero).take(7)).collect_vec()
+        } else {
+            mem::take(&mut self.buf)
+        };
+        let hash = loader.ecc_chip().keccak256(&mut loader.ctx_mut(), &data);
+
+        // Interpret the digest as an integer in big-endian and reduce it
+        let challenge = loader.scalar_chip().sum_with_coeff_and_const(
+            &mut loader.ctx_mut(),
+            &powers(C::Scalar::from(2)).zip(hash.chunks(8).rev().flatten()).collect_vec(),
+            C::Scalar::zero(),
+        );
+        self.buf = hash;
+        loader.scalar_from_assigned(challenge)
+    }
+
+    fn common_ec_point(&mut self, ec_point: &EcPoint<C, EccChip>) -> Result<(), Error> {
+        let ec_point = ec_point.assigned();
+        let bits = self.loader.ecc_chip().ec_point_to_bits(&mut self.loader.ctx_mut(), &ec_point);
+        self.buf.extend(bits);
+        Ok(())
+    }
+
+    fn common_scalar(&mut self, scalar: &Scalar<C, EccChip>) -> Result<(), Error> {
+        let scalar = scalar.assigned();
+        let bits = self.loader.ecc_chip().scalar_to_bits(&mut self.loader.ctx_mut(), &scalar);
+        self.buf.extend(bits);
+        Ok(())
+    }
+}
+
+impl<C, R, EccChip, const COMPRESSED: bool> TranscriptRead<C, Rc<Halo2Loader<C, EccChip>>>
+    for EvmTranscript<C, Rc<Halo2Loader<C, EccChip>>, R, Vec<EccChip::AssignedScalar>, COMPRESSED>
+where
+    C: CurveAffine,
+    R: Read,
+    EccChip: KeccakInstructions<C>,
+{
+    fn read_scalar(&mut self) -> Result<Scalar<C, EccChip>, Error> {
+        let mut data = <C::Scalar as PrimeField>::Repr::default();
+        self.stream
+            .read_exact(data.as_mut())
+            .map_err(|err| Error::Transcript(err.kind(), err.to_string()))?;
+        data.as_mut().reverse();
+        let scalar = C::Scalar::from_repr_vartime(data).ok_or_else(|| {
+            Error::Transcript(io::ErrorKind::Other, "Invalid scalar encoding in proof".to_string())
+        })?;
+        let scalar = self.loader.assign_scalar(scalar);
+        self.common_scalar(&scalar)?;
+        Ok(scalar)
+    }
+
+    fn read_ec_point(&mut self) -> Result<EcPoint<C, EccChip>, Error> {
+        let ec_point = if COMPRESSED {
+            let mut x = <C::Base as PrimeField>::Repr::default();
+            self.stream
+                .read_exact(x.as_mut())
+                .map_err(|err| Error::Transcript(err.kind(), err.to_string()))?;
+            decompress_ec_point(x)
+        } else {
+            let [mut x, mut y] = [<C::Base as PrimeField>::Repr::default(); 2];
+            for repr in [&mut x, &mut y] {
+                self.stream
+                    .read_exact(repr.as_mut())
+                    .map_err(|err| Error::Transcript(err.kind(), err.to_string()))?;
+                repr.as_mut().reverse();
+            }
+            let x = Option::from(<C::Base as PrimeField>::from_repr(x));
+            let y = Option::from(<C::Base as PrimeField>::from_repr(y));
+            x.zip(y).and_then(|(x, y)| Option::from(C::from_xy(x, y)))
+        }
+        .ok_or_else(|| {
+            Error::Transcript(
+                io::ErrorKind::Other,
+                "Invalid elliptic curve point encoding in proof".to_string(),
+            )
+        })?;
+        let ec_point = self.loader.assign_ec_point(ec_point);
+        self.common_ec_point(&ec_point)?;
+        Ok(ec_point)
+    }
+}
+
+mod halo2_lib {
+    use crate::{
+        halo2_curves::CurveAffineExt,
+        system::halo2::transcript::evm::KeccakInstructions,
+        util::{arithmetic::modulus, Itertools},
+    };
+    use halo2_base::{
+        gates::{GateChip, GateInstructions, RangeInstructions},
+        utils::ScalarField,
+        AssignedValue, Context,
+        QuantumCell::Constant,
+    };
+    use halo2_ecc::{ecc::BaseFieldEccChip, fields::PrimeField};
+    use num_bigint::BigUint;
+
+    /// Number of bits absorbed by each permutation.
+    const RATE: usize = 1088;
+
+    /// Round constants of Keccak-f[1600].
+    const ROUND_CONSTANTS: [u64; 24] = [
+        0x0000000000000001,
+        0x0000000000008082,
+        0x800000000000808A,
+        0x8000000080008000,
+        0x000000000000808B,
+        0x0000000080000001,
+        0x8000000080008081,
+        0x8000000000008009,
+        0x000000000000008A,
+        0x0000000000000088,
+        0x0000000080008009,
+        0x000000008000000A,
+        0x000000008000808B,
+        0x800000000000008B,
+        0x8000000000008089,
+        0x8000000000008003,
+        0x8000000000008002,
+        0x8000000000000080,
+        0x000000000000800A,
+        0x800000008000000A,
+        0x8000000080008081,
+        0x8000000000008080,
+        0x0000000080000001,
+        0x8000000080008008,
+    ];
+
+    /// Rotation offsets of lane `(x, y)` at index `x + 5 * y`.
+    const ROTATION_OFFSETS: [usize; 25] = [
+        0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56,
+        14,
+    ];
+
+    impl<'chip, C: CurveAffineExt> KeccakInstructions<C> for BaseFieldEccChip<'chip, C>
+    where
+        C::ScalarExt: PrimeField,
+        C::Base: PrimeField,
+    {
+        fn scalar_to_bits(
+            &self,
+            ctx: &mut Self::Context,
+            scalar: &Self::AssignedScalar,
+        ) -> Vec<Self::AssignedScalar> {
+            let gate = self.field_chip.range().gate();
+            let ctx = ctx.main(0);
+            let bits = gate.num_to_bits(ctx, *scalar, C::Scalar::NUM_BITS as usize);
+            assert_le_const(gate, ctx, &bits, &(modulus::<C::Scalar>() - 1u64));
+            to_be_bytes_bits(ctx, bits)
+        }
+
+        fn ec_point_to_bits(
+            &self,
+            ctx: &mut Self::Context,
+            ec_point: &Self::AssignedEcPoint,
+        ) -> Vec<Self::AssignedScalar> {
+            let gate = self.field_chip.range().gate();
+            let ctx = ctx.main(0);
+            let mut encoded = Vec::with_capacity(0x200);
+            for coordinate in [ec_point.x(), ec_point.y()] {
+                let bits = coordinate
+                    .limbs()
+                    .iter()
+                    .flat_map(|limb| gate.num_to_bits(ctx, *limb, self.field_chip.limb_bits))
+                    .collect_vec();
+                assert_le_const(gate, ctx, &bits, &(modulus::<C::Base>() - 1u64));
+                encoded.extend(to_be_bytes_bits(ctx, bits));
+            }
+            encoded
+        }
+
+        fn keccak256(
+            &self,
+            ctx: &mut Self::Context,
+            bits: &[Self::AssignedScalar],
+        ) -> Vec<Self::AssignedScalar> {
+            keccak256(self.field_chip.range().gate(), ctx.main(0), bits)
+        }
+    }
+
+    /// Constrains the integer of little-endian `bits` to be at most `bound`,
+    /// which makes the encoding canonical.
+    fn assert_le_const<F: ScalarField>(
+        gate: &GateChip<F>,
+        ctx: &mut Context<F>,
+        bits: &[AssignedValue<F>],
+        bound: &BigUint,
+    ) {
+        assert!(bound.bits() <= bits.len() as u64);
+
+        // Whether the bits from the most significant one so far are equal to
+        // the ones of `bound`, where `None` means it's always true.
+        let mut is_eq = None;
+        for (idx, bit) in bits.iter().enumerate().rev() {
+            let bit = match is_eq {
+                Some(is_eq) => gate.mul(ctx, is_eq, *bit),
+                None => *bit,
+            };
+            if bound.bit(idx as u64) {
+                is_eq = Some(bit);
+            } else {
+                gate.assert_is_const(ctx, &bit, &F::zero());
+            }
+        }
+    }
+
+    /// Returns bits of 32 bytes in big-endian, given little-endian `bits`
+    /// whose ones beyond 256 are constrained to be zero.
+    fn to_be_bytes_bits<F: ScalarField>(
+        ctx: &mut Context<F>,
+        mut bits: Vec<AssignedValue<F>>,
+    ) -> Vec<AssignedValue<F>> {
+        let zero = ctx.load_zero();
+        bits.resize(0x100, zero);
+        bits.chunks(8).rev().flatten().copied().collect()
+    }
+
+    /// Returns `a ^ b` as `a + b * (1 - 2a)`.
+    fn xor<F: ScalarField>(
+        gate: &GateChip<F>,
+        ctx: &mut Context<F>,
+        a: AssignedValue<F>,
+        b: AssignedValue<F>,
+    ) -> AssignedValue<F> {
+        let one_minus_two_a = gate.mul_add(ctx, a, Constant(-F::from(2)), Constant(F::one()));
+        gate.mul_add(ctx, b, one_minus_two_a, a)
+    }
+
+    /// Returns bits of keccak256 digest of `bits`.
+    fn keccak256<F: ScalarField>(
+        gate: &GateChip<F>,
+        ctx: &mut Context<F>,
+        bits: &[AssignedValue<F>],
+    ) -> Vec<AssignedValue<F>> {
+        assert_eq!(bits.len() % 8, 0);
+
+        // Pad with `0x01`, zeros and `0x80` to a multiple of `RATE`
+        let [zero, one] = [F::zero(), F::one()].map(|constant| ctx.load_constant(constant));
+        let mut padded = bits.to_vec();
+        padded.push(one);
+        padded.resize((bits.len() / RATE + 1) * RATE, zero);
+        *padded.last_mut().unwrap() = one;
+
+        let mut state = vec![zero; 1600];
+        for (idx, block) in padded.chunks(RATE).enumerate() {
+            if idx == 0 {
+                state[..RATE].copy_from_slice(block);
+            } else {
+                for (state, bit) in state.iter_mut().zip(block) {
+                    *state = xor(gate, ctx, *state, *bit);
+                }
+            }
+            keccak_f(gate, ctx, &mut state);
+        }
+
+        state.truncate(0x100);
+        state
+    }
+
+    /// Keccak-f[1600] permutation on `state` of 25 lanes, where bit `z` of
+    /// lane `(x, y)` is at index `64 * (x + 5 * y) + z`.
+    fn keccak_f<F: ScalarField>(
+        gate: &GateChip<F>,
+        ctx: &mut Context<F>,
+        state: &mut [AssignedValue<F>],
+    ) {
+        let lane = |x: usize, y: usize| 64 * (x % 5 + 5 * (y % 5));
+
+        for round_constant in ROUND_CONSTANTS {
+            // θ
+            let mut parities = Vec::with_capacity(5 * 64);
+            for x in 0..5 {
+                for z in 0..64 {
+                    let mut parity = state[lane(x, 0) + z];
+                    for y in 1..5 {
+                        parity = xor(gate, ctx, parity, state[lane(x, y) + z]);
+                    }
+                    parities.push(parity);
+                }
+            }
+            for x in 0..5 {
+                for z in 0..64 {
+                    let lhs = parities[64 * ((x + 4) % 5) + z];
+                    let rhs = parities[64 * ((x + 1) % 5) + (z + 63) % 64];
+                    let d = xor(gate, ctx, lhs, rhs);
+                    for y in 0..5 {
+                        state[lane(x, y) + z] = xor(gate, ctx, state[lane(x, y) + z], d);
+                    }
+                }
+            }
+
+            // ρ and π
+            let mut rotated = state.to_vec();
+            for x in 0..5 {
+                for y in 0..5 {
+                    let offset = ROTATION_OFFSETS[x + 5 * y];
+                    for z in 0..64 {
+                        rotated[lane(y, 2 * x + 3 * y) + (z + offset) % 64] = state[lane(x, y) + z];
+                    }
+                }
+            }
+
+            // χ
+            for x in 0..5 {
+                for y in 0..5 {
+                    for z in 0..64 {
+                        let masked = gate.mul_not(
+                            ctx,
+                            rotated[lane(x + 1, y) + z],
+                            rotated[lane(x + 2, y) + z],
+                        );
+                        state[lane(x, y) + z] = xor(gate, ctx, rotated[lane(x, y) + z], masked);
+                    }
+                }
+            }
+
+            // ι
+            for (z, bit) in state.iter_mut().take(64).enumerate() {
+                if ((round_constant >> z) & 1) == 1 {
+                    *bit = gate.not(ctx, *bit);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_evm_transcript_in_circuit() {
+    use crate::{
+        halo2_curves::bn256::{Fr, G1Affine},
+        halo2_proofs::{dev::MockProver, transcript::TranscriptWrite},
+        loader::{native::NativeLoader, ScalarLoader},
+        system::halo2::transcript::evm::ChallengeEvm,
+    };
+    use halo2_base::gates::{
+        builder::{GateThreadBuilder, RangeCircuitBuilder},
+        RangeChip,
+    };
+    use halo2_ecc::{bn254::FpChip, ecc::BaseFieldEccChip};
+    use std::env::set_var;
+
+    // 32 + 128 bytes are absorbed before the first challenge, which takes two
+    // permutations, and the last challenge absorbs only the previous one
+    let initial_state = Fr::from(7);
+    let ec_points = [G1Affine::generator(), (G1Affine::generator() + G1Affine::generator()).into()];
+    let scalars = [Fr::from(3), -Fr::one()];
+
+    let mut transcript = EvmTranscript::<G1Affine, NativeLoader, _, _>::new(Vec::new());
+    Transcript::common_scalar(&mut transcript, &initial_state).unwrap();
+    let mut challenges = Vec::new();
+    for ec_point in ec_points {
+        TranscriptWrite::<_, ChallengeEvm<_>>::write_point(&mut transcript, ec_point).unwrap();
+    }
+    challenges.push(Transcript::<G1Affine, NativeLoader>::squeeze_challenge(&mut transcript));
+    for scalar in scalars {
+        TranscriptWrite::<G1Affine, ChallengeEvm<_>>::write_scalar(&mut transcript, scalar)
+            .unwrap();
+    }
+    challenges.push(Transcript::<G1Affine, NativeLoader>::squeeze_challenge(&mut transcript));
+    challenges.push(Transcript::<G1Affine, NativeLoader>::squeeze_challenge(&mut transcript));
+    let proof = transcript.finalize();
+
+    // reads `proof` in circuit, constraining the challenges to the native ones
+    let k = 20;
+    let lookup_bits = 8;
+    set_var("LOOKUP_BITS", lookup_bits.to_string());
+    let mock = |proof: &[u8]| {
+        let range = RangeChip::<Fr>::default(lookup_bits);
+        let fp_chip = FpChip::<Fr>::new(&range, 88, 3);
+        let loader = Halo2Loader::new(BaseFieldEccChip::new(&fp_chip), GateThreadBuilder::mock());
+        let mut transcript =
+            EvmTranscript::<G1Affine, Rc<Halo2Loader<_, _>>, _, _>::new(&loader, proof);
+        transcript.common_scalar(&loader.load_const(&initial_state)).unwrap();
+        let mut assigned = Vec::new();
+        for _ in ec_points {
+            transcript.read_ec_point().unwrap();
+        }
+        assigned.push(transcript.squeeze_challenge());
+        let read_scalars = scalars.map(|_| *transcript.read_scalar().unwrap().assigned().value());
+        assigned.push(transcript.squeeze_challenge());
+        assigned.push(transcript.squeeze_challenge());
+
+        let values = assigned.iter().map(|assigned| *assigned.assigned().value()).collect_vec();
+        for (challenge, assigned) in challenges.iter().zip(assigned.iter()) {
+            loader.assert_eq("", assigned, &loader.load_const(challenge));
+        }
+        let builder = loader.take_ctx();
+        builder.config(k, Some(10));
+        let circuit = RangeCircuitBuilder::mock(builder);
+        let result = MockProver::run(k as u32, &circuit, vec![]).unwrap().verify();
+        (read_scalars, values, result)
+    };
+
+    let (read_scalars, values, result) = mock(&proof);
+    assert_eq!(read_scalars, scalars);
+    assert_eq!(values, challenges);
+    assert_eq!(result, Ok(()));
+
+    // a tampered byte of the first scalar changes the later challenges
+    let mut tampered = proof.clone();
+    tampered[128 + 31] ^= 1;
+    let (read_scalars, values, result) = mock(&tampered);
+    assert_eq!(read_scalars[0], Fr::from(2));
+    assert_eq!(values[0], challenges[0]);
+    assert!(values[1..] != challenges[1..]);
+    assert!(result.is_err());
+}
@@ -1,6 +1,8 @@
 //! Transcript for verifier in [`halo2_proofs`] circuit.
 
 use crate::halo2_proofs;
+#[cfg(feature = "loader_evm")]
+use crate::loader::evm::{self, loader::Value, modulus, EvmLoader};
 use crate::{
     loader::{
         halo2::{EcPoint, EccInstructions, Halo2Loader, Scalar},
@@ -16,6 +18,8 @@ use crate::{
     Error,
 };
 use halo2_proofs::transcript::EncodedChallenge;
+#[cfg(feature = "loader_evm")]
+use std::iter;
 use std::{
     io::{self, Read, Write},
     rc::Rc,
@@ -160,6 +164,128 @@ where
     }
 }
 
+#[cfg(feature = "loader_evm")]
+impl<C, const T: usize, const RATE: usize, const R_F: usize, const R_P: usize>
+    PoseidonTranscript<C, Rc<EvmLoader>, usize, T, RATE, R_F, R_P>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+{
+    /// Initialize [`PoseidonTranscript`] given [`Rc<EvmLoader>`] for verifier
+    /// on EVM, which reads the proof from calldata after instances.
+    ///
+    /// It derives the same challenges as the native and in-circuit ones, so a
+    /// proof could be verified both recursively and on EVM. However, each
+    /// permutation is computed in Yul by [`EvmLoader::poseidon_permutation`],
+    /// so it costs far more gas than
+    /// [`EvmTranscript`](super::evm::EvmTranscript) with `KECCAK256`.
+    pub fn new<const SECURE_MDS: usize>(loader: &Rc<EvmLoader>) -> Self {
+        let buf = Poseidon::new::<R_F, R_P, SECURE_MDS>(loader);
+        Self { loader: loader.clone(), stream: 0, buf }
+    }
+
+    /// Initialize [`PoseidonTranscript`] from a precomputed spec of round constants and MDS matrix because computing the constants is expensive.
+    pub fn from_spec(
+        loader: &Rc<EvmLoader>,
+        spec: OptimizedPoseidonSpec<C::Scalar, T, RATE>,
+    ) -> Self {
+        let buf = Poseidon::from_spec(loader, spec);
+        Self { loader: loader.clone(), stream: 0, buf }
+    }
+
+    /// Load `num_instance` instances from calldata to memory.
+    pub fn load_instances(&mut self, num_instance: Vec<usize>) -> Vec<Vec<evm::Scalar>> {
+        let instances = num_instance
+            .into_iter()
+            .map(|len| {
+                iter::repeat_with(|| {
+                    let scalar = self.loader.calldataload_scalar(self.stream);
+                    self.stream += 0x20;
+                    scalar
+                })
+                .take(len)
+                .collect_vec()
+            })
+            .collect_vec();
+        self.loader.record_instances(instances.iter().flatten());
+        instances
+    }
+
+    /// Checks calldata ends right after what has been read, so nothing could
+    /// be appended to the proof. It should be called after the whole proof is
+    /// read.
+    pub fn check_proof_len(&self) {
+        self.loader.calldata_check_end(self.stream.into());
+    }
+}
+
+#[cfg(feature = "loader_evm")]
+impl<C, const T: usize, const RATE: usize, const R_F: usize, const R_P: usize>
+    Transcript<C, Rc<EvmLoader>> for PoseidonTranscript<C, Rc<EvmLoader>, usize, T, RATE, R_F, R_P>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+{
+    fn loader(&self) -> &Rc<EvmLoader> {
+        &self.loader
+    }
+
+    fn squeeze_challenge(&mut self) -> evm::Scalar {
+        let loader = self.loader.clone();
+        self.buf
+            .squeeze_with(|spec, state, inputs| loader.poseidon_permutation(spec, state, inputs))
+    }
+
+    fn common_scalar(&mut self, scalar: &evm::Scalar) -> Result<(), Error> {
+        self.buf.update(&[scalar.clone()]);
+        Ok(())
+    }
+
+    fn common_ec_point(&mut self, ec_point: &evm::EcPoint) -> Result<(), Error> {
+        // Encode coordinates into native field elements as `fe_to_fe` does
+        let encoded = match ec_point.value() {
+            Value::Constant((x, y)) => {
+                let scalar_modulus = modulus::<C::Scalar>();
+                [x, y].map(|coordinate| {
+                    self.loader.scalar(Value::Constant(coordinate % scalar_modulus))
+                })
+            }
+            Value::Memory(ptr) => [ptr, ptr + 0x20].map(|ptr| {
+                let encoded_ptr = self.loader.allocate_value(0x20);
+                let code = format!("mstore({encoded_ptr:#x}, mod(mload({ptr:#x}), f_q))");
+                self.loader.code_mut().runtime_append(code);
+                self.loader.scalar(Value::Memory(encoded_ptr))
+            }),
+            _ => unreachable!(),
+        };
+        self.buf.update(&encoded);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "loader_evm")]
+impl<C, const T: usize, const RATE: usize, const R_F: usize, const R_P: usize>
+    TranscriptRead<C, Rc<EvmLoader>>
+    for PoseidonTranscript<C, Rc<EvmLoader>, usize, T, RATE, R_F, R_P>
+where
+    C: CurveAffine,
+    C::Scalar: PrimeField<Repr = [u8; 0x20]>,
+{
+    fn read_scalar(&mut self) -> Result<evm::Scalar, Error> {
+        let scalar = self.loader.calldataload_scalar_le(self.stream);
+        self.stream += 0x20;
+        self.common_scalar(&scalar)?;
+        Ok(scalar)
+    }
+
+    fn read_ec_point(&mut self) -> Result<evm::EcPoint, Error> {
+        let ec_point = self.loader.calldataload_ec_point_compressed_le(self.stream);
+        self.stream += 0x20;
+        self.common_ec_point(&ec_point)?;
+        Ok(ec_point)
+    }
+}
+
 impl<C: CurveAffine, S, const T: usize, const RATE: usize, const R_F: usize, const R_P: usize>
     PoseidonTranscript<C, NativeLoader, S, T, RATE, R_F, R_P>
 {
@@ -439,3 +565,67 @@ mod halo2_lib {
         }
     }
 }
+
+#[cfg(feature = "loader_evm")]
+#[test]
+fn test_poseidon_transcript_on_evm() {
+    use crate::{
+        halo2_curves::bn256::{Fq, Fr, G1Affine, G1},
+        loader::{
+            evm::{assemble_yul, encode_calldata, Address, ExecutorBuilder},
+            EcPointLoader,
+        },
+        util::arithmetic::{Curve, Field, Group},
+    };
+    use rand::rngs::OsRng;
+
+    const T: usize = 5;
+    const RATE: usize = 4;
+    const R_F: usize = 8;
+    const R_P: usize = 60;
+
+    let mut transcript =
+        PoseidonTranscript::<G1Affine, NativeLoader, _, T, RATE, R_F, R_P>::new::<0>(Vec::new());
+    let rounds = iter::repeat_with(|| {
+        let scalar = Fr::random(OsRng);
+        let ec_point = G1::random(OsRng).to_affine();
+        transcript.write_scalar(scalar).unwrap();
+        transcript.write_ec_point(ec_point).unwrap();
+        (scalar, ec_point, transcript.squeeze_challenge())
+    })
+    .take(3)
+    .collect_vec();
+    let proof = transcript.finalize();
+
+    let loader = EvmLoader::new::<Fq, Fr>();
+    let mut transcript =
+        PoseidonTranscript::<G1Affine, Rc<EvmLoader>, _, T, RATE, R_F, R_P>::new::<0>(&loader);
+    for (scalar, ec_point, challenge) in rounds {
+        let loaded_scalar = transcript.read_scalar().unwrap();
+        let loaded_ec_point = transcript.read_ec_point().unwrap();
+        let loaded_challenge = transcript.squeeze_challenge();
+        for (lhs, rhs) in [(loaded_scalar, scalar), (loaded_challenge, challenge)] {
+            let rhs = ScalarLoader::<Fr>::load_const(&loader, &rhs);
+            ScalarLoader::<Fr>::assert_eq(&loader, "", &lhs, &rhs);
+        }
+        let ec_point = EcPointLoader::<G1Affine>::ec_point_load_const(&loader, &ec_point);
+        EcPointLoader::<G1Affine>::ec_point_assert_eq(&loader, "", &loaded_ec_point, &ec_point);
+    }
+    transcript.check_proof_len();
+    let deployment_code = assemble_yul(&loader.yul_code()).unwrap();
+
+    let caller = Address::from_low_u64_be(0xfe);
+    let mut evm = ExecutorBuilder::default().with_gas_limit(u64::MAX.into()).build();
+    let verifier = evm.deploy(caller, deployment_code.into(), 0.into()).address.unwrap();
+    let verify = |proof: &[u8]| {
+        let calldata = encode_calldata::<Fr>(&[], proof);
+        !evm.call_raw(caller, verifier, calldata.into(), 0.into()).reverted
+    };
+    assert!(verify(&proof));
+    // the least significant byte of the first scalar
+    let mut tampered_proof = proof.clone();
+    tampered_proof[0] ^= 1;
+    assert!(!verify(&tampered_proof));
+    assert!(!verify(&proof[..proof.len() - 1]));
+    assert!(!verify(&[proof.as_slice(), &[0]].concat()));
+}
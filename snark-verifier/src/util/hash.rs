@@ -1,9 +1,9 @@
 //! Hash algorithms.
 
-#[cfg(feature = "loader_halo2")]
+#[cfg(any(feature = "loader_evm", feature = "loader_halo2"))]
 mod poseidon;
 
-#[cfg(feature = "loader_halo2")]
+#[cfg(any(feature = "loader_evm", feature = "loader_halo2"))]
 pub use crate::util::hash::poseidon::{OptimizedPoseidonSpec, Poseidon};
 
 #[cfg(feature = "loader_evm")]
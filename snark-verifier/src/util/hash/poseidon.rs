@@ -466,6 +466,24 @@ impl<F: FieldExt, L: LoadedScalar<F>, const T: usize, const RATE: usize> Poseido
         self.state.inner[1].clone()
     }
 
+    /// Same as [`Poseidon::squeeze`] but performs permutation by given
+    /// `permutation`, which takes the spec, the state and inputs to absorb,
+    /// and returns the new state.
+    #[cfg(all(feature = "loader_evm", feature = "loader_halo2"))]
+    pub(crate) fn squeeze_with(
+        &mut self,
+        mut permutation: impl FnMut(&OptimizedPoseidonSpec<F, T, RATE>, &[L; T], &[L]) -> [L; T],
+    ) -> L {
+        let buf = mem::take(&mut self.buf);
+        let exact = buf.len() % RATE == 0;
+
+        for chunk in buf.chunks(RATE).chain(exact.then_some(&[][..])) {
+            self.state.inner = permutation(&self.spec, &self.state.inner, chunk);
+        }
+
+        self.state.inner[1].clone()
+    }
+
     fn permutation(&mut self, inputs: &[L]) {
         let r_f = self.spec.r_f / 2;
         let mds = self.spec.mds_matrices.mds.0;
@@ -83,3 +83,41 @@ fn test_poseidon_against_test_vectors() {
         }
     }
 }
+
+#[test]
+fn test_poseidon_with_dense_mds() {
+    // Optimized round constants with dense MDS matrix in every round, as
+    // `EvmLoader::poseidon_permutation` does, should be equivalent to the
+    // sparse matrices.
+    const R_F: usize = 8;
+    const R_P: usize = 57;
+    const T: usize = 3;
+    const RATE: usize = 2;
+
+    let mut hasher = Poseidon::<Fr, Fr, T, RATE>::new::<R_F, R_P, 0>(&NativeLoader);
+    let state = [0u64, 1, 2].map(Fr::from);
+    hasher.state = State::new(state);
+    hasher.permutation(&[(); RATE].map(|_| Fr::zero()));
+
+    let spec = &hasher.spec;
+    let mds = spec.mds_matrices.mds.0;
+    let r_f_half = spec.r_f / 2;
+    let mut constants = iter::empty()
+        .chain(spec.constants.start.iter().flatten())
+        .chain(spec.constants.partial.iter())
+        .chain(spec.constants.end.iter().flatten())
+        .copied()
+        .chain(iter::repeat(Fr::zero()).take(T));
+    let mut state = state.map(|state| state + constants.next().unwrap());
+    for round in 0..2 * r_f_half + R_P {
+        let sbox_len = if (r_f_half..r_f_half + R_P).contains(&round) { 1 } else { T };
+        for state in state.iter_mut().take(sbox_len) {
+            *state = state.pow_vartime([5]) + constants.next().unwrap();
+        }
+        state = mds.map(|row| {
+            row.iter().zip(state.iter()).fold(Fr::zero(), |acc, (m, state)| acc + *m * state)
+        });
+    }
+    assert!(constants.next().is_none());
+    assert_eq!(state, hasher.state.inner);
+}
@@ -10,7 +10,7 @@
 
 use crate::{
     cost::{Cost, CostEstimation},
-    loader::Loader,
+    loader::{LoadedScalar, Loader},
     pcs::{
         AccumulationDecider, AccumulationScheme, AccumulatorEncoding, PolynomialCommitmentScheme,
         Query,
@@ -52,7 +52,11 @@ where
     where
         T: TranscriptRead<C, L>,
     {
-        PlonkProof::read::<T, AE>(svk, protocol, instances, transcript)
+        let loader = transcript.loader().clone();
+        loader.start_cost_metering("read_proof");
+        let proof = PlonkProof::read::<T, AE>(svk, protocol, instances, transcript);
+        loader.end_cost_metering();
+        proof
     }
 
     fn verify(
@@ -61,6 +65,9 @@ where
         instances: &[Vec<L::LoadedScalar>],
         proof: &Self::Proof,
     ) -> Result<Self::Output, Error> {
+        let loader = proof.z.loader();
+
+        loader.start_cost_metering("common_polynomial_evaluation");
         let common_poly_eval = {
             let mut common_poly_eval =
                 CommonPolynomialEvaluation::new(&protocol.domain, protocol.langranges(), &proof.z);
@@ -70,18 +77,32 @@ where
 
             common_poly_eval
         };
-
-        let mut evaluations = proof.evaluations(protocol, instances, &common_poly_eval)?;
-        let commitments = proof.commitments(protocol, &common_poly_eval, &mut evaluations)?;
-        let queries = proof.queries(protocol, evaluations);
-
+        loader.end_cost_metering();
+
+        // Each section is ended before propagating its error, so the metering
+        // stays balanced when verification fails.
+        loader.start_cost_metering("quotient_evaluation");
+        let evaluations = proof.evaluations(protocol, instances, &common_poly_eval);
+        loader.end_cost_metering();
+        let mut evaluations = evaluations?;
+
+        loader.start_cost_metering("linearization");
+        let linearization = proof
+            .commitments(protocol, &common_poly_eval, &mut evaluations)
+            .map(|commitments| (commitments, proof.queries(protocol, evaluations)));
+        loader.end_cost_metering();
+        let (commitments, queries) = linearization?;
+
+        loader.start_cost_metering("pcs_verify");
         let accumulator = <AS as PolynomialCommitmentScheme<C, L>>::verify(
             svk,
             &commitments,
             &proof.z,
             &queries,
             &proof.pcs,
-        )?;
+        );
+        loader.end_cost_metering();
+        let accumulator = accumulator?;
 
         let accumulators = iter::empty()
             .chain(Some(accumulator))
@@ -119,7 +140,7 @@ where
     where
         T: TranscriptRead<C, L>,
     {
-        PlonkProof::read::<T, AE>(vk.as_ref(), protocol, instances, transcript)
+        PlonkSuccinctVerifier::<AS, AE>::read_proof(vk.as_ref(), protocol, instances, transcript)
     }
 
     fn verify(
@@ -130,7 +151,12 @@ where
     ) -> Result<Self::Output, Error> {
         let accumulators =
             PlonkSuccinctVerifier::<AS, AE>::verify(vk.as_ref(), protocol, instances, proof)?;
-        AS::decide_all(vk, accumulators)
+
+        let loader = proof.z.loader();
+        loader.start_cost_metering("decide");
+        let output = AS::decide_all(vk, accumulators);
+        loader.end_cost_metering();
+        output
     }
 }
 